@@ -0,0 +1,196 @@
+use anyhow::{bail, Result as AnyResult};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{one_bigram, one_letter, Evaluation, KeyboardLayout},
+    keyboard::Keyboard,
+    layout::{Behavior, Layout},
+    shift::ShiftTable,
+    symbol::Symbol,
+};
+
+/// A partially filled layout and the raw metrics of the characters placed so far.
+#[derive(Debug, Clone)]
+struct Partial {
+    layout: Layout,
+    eval: Evaluation,
+    score: f32,
+}
+
+/// The n-grams whose cost is known once a key is placed: every letter typed
+/// with it, and every bigram whose other key was placed earlier.
+#[derive(Debug)]
+struct Step {
+    key: Symbol,
+    /// the key itself and its shifted character
+    chars: Vec<Symbol>,
+    letters: Vec<([Symbol; 1], u32)>,
+    bigrams: Vec<([Symbol; 2], u32)>,
+}
+
+/// Keys in the order they are placed, most frequent first, counting shifted
+/// characters towards the key they are typed with.
+fn steps(count: &CountOutcome, shift: &ShiftTable) -> Vec<Step> {
+    let mut freq = FxHashMap::<Symbol, u32>::default();
+    for ch in Symbol::alphabet() {
+        *freq.entry(shift.unshifted(ch)).or_default() +=
+            count.letter.get(&[ch]).copied().unwrap_or(0);
+    }
+    let mut keys = freq.into_iter().collect::<Vec<_>>();
+    keys.sort_unstable_by(|(k1, f1), (k2, f2)| f2.cmp(f1).then(k1.cmp(k2)));
+
+    let rank = keys
+        .iter()
+        .enumerate()
+        .map(|(i, &(key, _))| (key, i))
+        .collect::<FxHashMap<_, _>>();
+    let mut steps = keys
+        .iter()
+        .map(|&(key, _)| Step {
+            key,
+            chars: Symbol::alphabet()
+                .filter(|&ch| shift.unshifted(ch) == key)
+                .collect(),
+            letters: vec![],
+            bigrams: vec![],
+        })
+        .collect::<Vec<_>>();
+    for (&[ch], &freq) in &count.letter {
+        if let Some(&i) = rank.get(&shift.unshifted(ch)) {
+            steps[i].letters.push(([ch], freq));
+        }
+    }
+    for (&[a, b], &freq) in &count.bigrams {
+        if let (Some(&i), Some(&j)) = (rank.get(&shift.unshifted(a)), rank.get(&shift.unshifted(b)))
+        {
+            steps[i.max(j)].bigrams.push(([a, b], freq));
+        }
+    }
+    for step in &mut steps {
+        step.letters.sort_unstable();
+        step.bigrams.sort_unstable();
+    }
+    steps
+}
+
+/// Raw metrics added by the n-grams of `step` that `info` can type.
+fn step_eval(info: &KeyboardLayout, step: &Step) -> Evaluation {
+    let mut eval = Evaluation::default();
+    for &(letter, freq) in &step.letters {
+        if info.try_key(letter[0]).is_some() {
+            let (cost, ways) = one_letter(info, letter);
+            eval.letter += cost / ways * freq as f32;
+        }
+    }
+    for &(bigram, freq) in &step.bigrams {
+        if bigram.iter().all(|&ch| info.try_key(ch).is_some()) {
+            let (cost, ways) = one_bigram(info, bigram);
+            eval.bigram += cost / ways * freq as f32;
+        }
+    }
+    eval
+}
+
+/// Places every key one at a time, most frequent first, into the key slots
+/// of `start`, keeping its holds. After each key only the `width` partial
+/// layouts with the lowest `score` are kept. Letters stay on the base layer
+/// and digits share a layer, as in annealing.
+pub fn beam_search(
+    start: &Layout,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    width: usize,
+    score: impl Fn(&Evaluation) -> f32,
+) -> AnyResult<Layout> {
+    let mut empty = start.clone();
+    let mut slots = vec![];
+    for (loc, _) in start.iter_keys() {
+        empty.set_key_at(loc, None);
+        slots.push(loc);
+    }
+    // a key under a held shift on another layer blocks shifting from that layer
+    slots.retain(|slot| {
+        slot.layer() == 0 || start.base_hold()[slot.index()] != Some(Behavior::Shift)
+    });
+
+    let steps = steps(count, keyboard.shift_table());
+    if steps.len() > slots.len() {
+        bail!(
+            "{} keys to place but only {} free slots",
+            steps.len(),
+            slots.len()
+        );
+    }
+
+    let mut beam = vec![Partial {
+        layout: empty,
+        eval: Evaluation::default(),
+        score: 0.0,
+    }];
+    for (n, step) in steps.iter().enumerate() {
+        // shifting from another layer is usually impossible, so keep base
+        // slots for every remaining key that has a shifted character
+        let shiftable = |key: Symbol| keyboard.shift_table().shifted(key).is_some();
+        let shiftable_left = steps[n..].iter().filter(|step| shiftable(step.key)).count();
+        let digits_left = steps[n..]
+            .iter()
+            .filter(|step| step.key.char().is_ascii_digit())
+            .count();
+        let mut next = vec![];
+        for partial in &beam {
+            let digit_layer = partial
+                .layout
+                .find_all_key(|k| k.char().is_ascii_digit())
+                .next()
+                .map(|loc| loc.layer());
+            let free = |layer: u8| {
+                slots
+                    .iter()
+                    .filter(|slot| slot.layer() == layer)
+                    .filter(|slot| partial.layout.key(layer, slot.index()).is_none())
+                    .count()
+            };
+            for &slot in &slots {
+                let layer = slot.layer();
+                let allowed = match step.key.char() {
+                    'a'..='z' => layer == 0,
+                    '0'..='9' => digit_layer.map_or(free(layer) >= digits_left, |l| l == layer),
+                    _ => digit_layer.is_none_or(|l| l != layer) || free(layer) > digits_left,
+                } && (layer != 0 || shiftable(step.key) || free(0) > shiftable_left);
+                if !allowed || partial.layout.key(slot.layer(), slot.index()).is_some() {
+                    continue;
+                }
+
+                let mut layout = partial.layout.clone();
+                layout.set_key_at(slot, Some(step.key));
+                let Ok(info) = KeyboardLayout::generate_partial(&layout, keyboard) else {
+                    continue;
+                };
+                if step.chars.iter().any(|&ch| info.try_key(ch).is_none()) {
+                    continue;
+                }
+                let added = step_eval(&info, step);
+                let eval = Evaluation {
+                    letter: partial.eval.letter + added.letter,
+                    bigram: partial.eval.bigram + added.bigram,
+                    trigram: partial.eval.trigram,
+                    finger: partial.eval.finger,
+                };
+                next.push(Partial {
+                    score: score(&eval),
+                    layout,
+                    eval,
+                });
+            }
+        }
+        if next.is_empty() {
+            bail!("no slot left for {:?}", step.key);
+        }
+        next.sort_by(|a, b| a.score.total_cmp(&b.score));
+        next.truncate(width);
+        beam = next;
+    }
+
+    Ok(beam.swap_remove(0).layout)
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{Context as _, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{evaluate::KeyboardLayout, symbol::Symbol};
+
+/// One key press of a typing recording, `time` in milliseconds.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Keystroke {
+    pub time: u64,
+    pub key: Symbol,
+}
+
+/// Reads a recording with one JSON keystroke per line.
+pub fn load_recording(path: impl AsRef<Path>) -> AnyResult<Vec<Keystroke>> {
+    let file = std::fs::File::open(path)?;
+    BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.as_ref().is_ok_and(|l| l.trim().is_empty()))
+        .map(|(i, line)| {
+            serde_json::from_str(&line?).with_context(|| format!("bad keystroke on line {}", i + 1))
+        })
+        .collect()
+}
+
+/// Anything longer is a pause rather than the time it took to reach a key.
+const MAX_INTERVAL: u64 = 1000;
+/// Keys with fewer samples than this are left uncalibrated.
+const MIN_SAMPLES: usize = 20;
+/// Cost of a key that takes twice the usual time to reach, in `base.z` units.
+const PENALTY_SCALE: f32 = 2.0;
+
+/// Per-key cost offsets learned from how slowly each key is typed, indexed
+/// like `Keyboard::keys`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Calibration {
+    pub offsets: Vec<f32>,
+}
+
+impl Calibration {
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Fits offsets to a recording made on `info`'s layout. Every interval is
+    /// charged to the key it ended on, and a key whose median interval is above
+    /// the overall median gets a penalty proportional to how much slower it is.
+    pub fn fit(info: &KeyboardLayout, recording: &[Keystroke]) -> Self {
+        let key_count = info.keyboard().keys().len();
+        let mut intervals = vec![vec![]; key_count];
+
+        for pair in recording.windows(2) {
+            let interval = pair[1].time.saturating_sub(pair[0].time);
+            if interval > MAX_INTERVAL {
+                continue;
+            }
+            if let Some(combo) = info.try_key(pair[1].key).and_then(|combos| combos.first()) {
+                intervals[combo.key()].push(interval);
+            }
+        }
+
+        let mut all = intervals.concat();
+        let Some(overall) = median(&mut all) else {
+            return Self {
+                offsets: vec![0.0; key_count],
+            };
+        };
+
+        let offsets = intervals
+            .iter_mut()
+            .map(|samples| match samples.len() >= MIN_SAMPLES {
+                true => {
+                    median(samples).map_or(0.0, |m| (m / overall - 1.0).max(0.0) * PENALTY_SCALE)
+                }
+                false => 0.0,
+            })
+            .collect();
+        Self { offsets }
+    }
+}
+
+fn median(samples: &mut [u64]) -> Option<f32> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable();
+    Some(samples[samples.len() / 2] as f32)
+}
@@ -0,0 +1,81 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    },
+    time::Duration,
+};
+
+use anyhow::{bail, Result as AnyResult};
+use rdev::{Event, EventType, Key};
+
+use crate::{
+    counter::{CountOutcome, NGramTracker, NgramDepth},
+    symbol::Symbol,
+};
+
+/// Keys held for shortcuts rather than for typing.
+const SHORTCUT_MODIFIERS: [Key; 6] = [
+    Key::ControlLeft,
+    Key::ControlRight,
+    Key::Alt,
+    Key::MetaLeft,
+    Key::MetaRight,
+    Key::AltGr,
+];
+
+/// Counts the keys typed anywhere on the desktop until `stop` is set, like a
+/// corpus counted to `depth`. Keys pressed while ctrl, alt or meta is held
+/// are shortcuts and break the n-grams, as does any key that types nothing
+/// in the alphabet. Only the counts are kept, never the typed text.
+pub fn capture(depth: NgramDepth, stop: &AtomicBool) -> AnyResult<CountOutcome> {
+    let (send, receive) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut held = HashSet::new();
+        let events = send.clone();
+        let listened = rdev::listen(move |event| match event.event_type {
+            EventType::KeyPress(key) if SHORTCUT_MODIFIERS.contains(&key) => {
+                held.insert(key);
+            }
+            EventType::KeyRelease(key) => {
+                held.remove(&key);
+            }
+            EventType::KeyPress(key) => {
+                let typed = typed(key, &event).filter(|_| held.is_empty());
+                let _ = events.send(Ok(typed));
+            }
+            _ => {}
+        });
+        if let Err(err) = listened {
+            let _ = send.send(Err(err));
+        }
+    });
+
+    let mut count = CountOutcome::default();
+    let mut tracker = NGramTracker::new(depth);
+    while !stop.load(Ordering::Relaxed) {
+        match receive.recv_timeout(Duration::from_millis(100)) {
+            Ok(Ok(Some(key))) => tracker.apply(&mut count, key),
+            Ok(Ok(None)) => tracker.clear(),
+            Ok(Err(err)) => bail!("cannot listen to the keyboard: {err:?}"),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(count)
+}
+
+/// The alphabet key a press typed, if any.
+fn typed(key: Key, event: &Event) -> Option<Symbol> {
+    let symbol = match key {
+        Key::Return | Key::KpReturn => Symbol::ENTER,
+        Key::Tab => Symbol::TAB,
+        Key::Space => Symbol::SPACE,
+        _ => match event.name.as_deref()?.as_bytes() {
+            &[byte] => Symbol::from_ascii(byte)?,
+            _ => return None,
+        },
+    };
+    Some(symbol).filter(|symbol| symbol.in_alphabet())
+}
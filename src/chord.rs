@@ -0,0 +1,208 @@
+use std::{path::Path, str::FromStr};
+
+use anyhow::{anyhow, bail, Result as AnyResult};
+use serde::Deserialize;
+
+use crate::{
+    evaluate::{convert_fingers, letter_cost, KeyboardLayout, LetterEval},
+    layout::{Behavior, KeyLoc},
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Shift,
+    Ctrl,
+    Alt,
+    Gui,
+}
+
+impl Modifier {
+    pub fn behavior(self) -> Behavior {
+        match self {
+            Modifier::Shift => Behavior::Shift,
+            Modifier::Ctrl => Behavior::Ctrl,
+            Modifier::Alt => Behavior::Alt,
+            Modifier::Gui => Behavior::Gui,
+        }
+    }
+}
+
+impl FromStr for Modifier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "shift" | "sft" => Modifier::Shift,
+            "ctrl" | "control" | "ctl" => Modifier::Ctrl,
+            "alt" | "opt" | "option" => Modifier::Alt,
+            "gui" | "super" | "cmd" | "win" | "meta" => Modifier::Gui,
+            _ => bail!("unknown modifier {s:?}"),
+        })
+    }
+}
+
+/// A modifier chord such as `ctrl+shift+p`: every modifier is held while the
+/// final key is tapped.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Chord {
+    mods: Vec<Modifier>,
+    key: Symbol,
+}
+
+impl Chord {
+    pub fn mods(&self) -> &[Modifier] {
+        &self.mods
+    }
+
+    pub fn key(&self) -> Symbol {
+        self.key
+    }
+}
+
+/// The modifiers of a chord such as `ctrl+shift+p` and the name of its final
+/// key. A trailing `+` is the plus key itself, as in `ctrl++`.
+pub fn split_chord(s: &str) -> AnyResult<(Vec<Modifier>, &str)> {
+    let (mods, key) = match s.strip_suffix("++") {
+        Some(mods) => (mods, "+"),
+        None => s.rsplit_once('+').unwrap_or(("", s)),
+    };
+    let mods = mods
+        .split('+')
+        .filter(|m| !m.is_empty())
+        .map(Modifier::from_str)
+        .collect::<AnyResult<_>>()?;
+    Ok((mods, key))
+}
+
+/// The symbol a key name in a chord types: one character, or a name such as
+/// `space`, `spc`, `enter` or `ret`.
+pub fn key_symbol(name: &str) -> Option<Symbol> {
+    let mut chars = name.chars();
+    let single = match (chars.next(), chars.next()) {
+        (Some(ch), None) => Symbol::from_char(ch),
+        _ => None,
+    };
+    match name.to_ascii_lowercase().as_str() {
+        "spc" => Some(Symbol::SPACE),
+        "ret" => Some(Symbol::ENTER),
+        name => Symbol::named(name).or(single),
+    }
+}
+
+impl FromStr for Chord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mods, key) = split_chord(s)?;
+        let key = key_symbol(key).ok_or_else(|| anyhow!("unknown key {key:?} in chord {s:?}"))?;
+        Ok(Self { mods, key })
+    }
+}
+
+impl TryFrom<String> for Chord {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChordEntry {
+    pub chord: Chord,
+    pub freq: u32,
+}
+
+pub fn load(path: impl AsRef<Path>) -> AnyResult<Vec<ChordEntry>> {
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChordEval {
+    pub cost: LetterEval,
+    pub unreachable: u32,
+}
+
+pub fn eval_chords(info: &KeyboardLayout, chords: &[ChordEntry]) -> ChordEval {
+    let mut eval = ChordEval {
+        cost: LetterEval::ZERO,
+        unreachable: 0,
+    };
+
+    for entry in chords {
+        match one_chord(info, &entry.chord) {
+            Some(cost) => eval.cost += cost * entry.freq as f32,
+            None => eval.unreachable += entry.freq,
+        }
+    }
+
+    eval
+}
+
+/// Average cost of every way to press `chord`, or `None` if the layout
+/// cannot produce it at all.
+pub fn one_chord(info: &KeyboardLayout, chord: &Chord) -> Option<LetterEval> {
+    let layout = info.layout();
+    let keyboard = info.keyboard();
+    let mod_keys = chord
+        .mods
+        .iter()
+        .map(|m| {
+            layout
+                .find_on_base(|b| b == m.behavior())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut total = LetterEval::ZERO;
+    let mut count = 0.0;
+    for combo in info.try_key(chord.key)? {
+        let base = convert_fingers(info, combo);
+        let active_layer = combo.tap_layer();
+
+        let mut choice = vec![0; mod_keys.len()];
+        'choices: loop {
+            let held = choice
+                .iter()
+                .zip(&mod_keys)
+                .map(|(&i, keys)| keys.get(i).copied())
+                .collect::<Option<Vec<KeyLoc>>>();
+            let Some(held) = held else {
+                break;
+            };
+
+            let mut positions = base;
+            let mut valid = true;
+            for loc in &held {
+                let key = keyboard.key(loc.index());
+                // the mod-tap only fires if the active layer is transparent there
+                let covered = active_layer != 0 && layout.key(active_layer, loc.index()).is_some();
+                let slot = &mut positions[key.finger()];
+                if covered || slot.is_pressing() {
+                    valid = false;
+                    break;
+                }
+                slot.hold = Some(key.pos());
+            }
+
+            if valid {
+                total += letter_cost(info, &positions);
+                count += 1.0;
+            }
+
+            for (i, keys) in choice.iter_mut().zip(&mod_keys) {
+                *i += 1;
+                if *i < keys.len() {
+                    continue 'choices;
+                }
+                *i = 0;
+            }
+            break;
+        }
+    }
+
+    (count > 0.0).then(|| total / count)
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context as _, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    calibration::Calibration,
+    core::{FingerTargets, HandModel},
+    counter::{CorpusFilter, CorpusMode, Indentation, NgramDepth, TextOptions, Transliteration},
+    evaluate::{ComboChoice, EvalWeights},
+    ferris,
+    keyboard::Keyboard,
+    layout::Layout,
+    output::Palette,
+    shift::ShiftTable,
+};
+
+/// Everything a run needs that used to be a literal in `main.rs`, loaded
+/// from `keyboard.toml`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// name runs are filed under in the history
+    pub profile: String,
+    /// seed 0 unless set, no corpus cache and zeroed timestamps, so reruns
+    /// give identical layouts and history records
+    pub deterministic: bool,
+    /// colors of the printed layouts and reports
+    pub palette: Palette,
+    pub eval: EvalConfig,
+    pub optimize: OptimizeConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            profile: "default".to_string(),
+            deterministic: false,
+            palette: Palette::default(),
+            eval: EvalConfig::default(),
+            optimize: OptimizeConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// A missing file gives the default config.
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(data) => {
+                let mut config: Self = toml::from_str(&data)
+                    .with_context(|| format!("bad config {}", path.display()))?;
+                if config.eval.model == CostModel::Fitts {
+                    config.eval.weights = EvalWeights::fitts();
+                }
+                Ok(config)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalConfig {
+    pub corpus: PathBuf,
+    /// corpora counted separately and mixed by weight instead of `corpus`
+    pub corpora: Vec<CorpusPart>,
+    /// `code`, `prose` or `markdown`
+    pub mode: CorpusMode,
+    /// extra ascii keys typed for non-ascii characters, on top of the
+    /// built-in accents and typographic punctuation
+    pub transliterate: BTreeMap<char, String>,
+    /// extensions of the counted files, every file if empty and the mode's
+    /// usual ones if missing
+    pub extensions: Option<Vec<String>>,
+    /// names or globs of files and directories left out of the corpus
+    pub ignore: Vec<String>,
+    /// also leave out whatever `.gitignore` files in the corpus do
+    pub gitignore: bool,
+    /// count code without its comments, for rust, python and c-like files
+    pub strip_comments: bool,
+    /// count code with string literals emptied, keeping the quotes
+    pub strip_strings: bool,
+    /// characters counted per file, every one if missing
+    pub max_file_chars: Option<usize>,
+    /// weigh every file the same instead of by its length
+    pub equal_files: bool,
+    /// how the indentation of code is typed: `tabs`, `literal`, `collapse`
+    /// or `ignore`
+    pub indentation: Indentation,
+    pub tab_width: usize,
+    /// type runs of capitals with caps word instead of holding shift
+    pub caps_word: bool,
+    /// share of the keystrokes typed as vim normal mode commands, with the
+    /// corpus as the text typed in insert mode; 0 for no vim
+    pub vim: f32,
+    /// share of the presses, in percent, each finger takes before
+    /// `finger.load` counts it as overloaded
+    pub finger_targets: FingerTargets,
+    /// strength of each finger, cost of spreading each pair of fingers and
+    /// how much harder holding a key makes both
+    pub hand: HandModel,
+    /// shifted symbols and the keys shifted to type them, on top of the
+    /// capitals
+    pub shift: ShiftTable,
+    /// longest n-gram counted: `letters`, `bigrams`, `trigrams` or `quadgrams`
+    pub depth: NgramDepth,
+    /// geometry evaluated on: `ferris_sweep`, `laptop`, a bundled board such
+    /// as `corne` or `totem`, or a geometry file
+    pub keyboard: String,
+    /// name of a built-in layout or a path to a layout file
+    pub reference: String,
+    pub weights: EvalWeights,
+    /// what the objective scores, `geometry` or `fitts`
+    pub model: CostModel,
+    /// per-key offsets fitted by `calibrate`, none if missing
+    pub calibration: Option<PathBuf>,
+    pub affinity: PathBuf,
+    pub affinity_weight: f32,
+    pub learnability: PathBuf,
+    pub learnability_weight: f32,
+    /// millimetres within which another key of the same hand, pressed right
+    /// after a tap-hold key, counts as a misfire
+    pub misfire_reach: f32,
+    /// how the ways of typing an n-gram reduce to its cost: `average`, `min`
+    /// or `weighted`
+    pub combo_choice: ComboChoice,
+    /// how `optimize` evaluates layouts
+    pub backend: EvalBackend,
+    /// transitions less likely than this are left out of the markov backend
+    pub markov_min_probability: f32,
+}
+
+/// One corpus of a mix, normalized to its total before weighting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusPart {
+    pub path: PathBuf,
+    /// share of the mix, relative to the other parts
+    pub weight: f32,
+    /// the eval mode if missing
+    #[serde(default)]
+    pub mode: Option<CorpusMode>,
+    /// the mode's usual extensions if missing and a mode is given, otherwise
+    /// the eval extensions
+    #[serde(default)]
+    pub extensions: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostModel {
+    /// the metrics of the geometry, weighed by `weights`
+    #[default]
+    Geometry,
+    /// `bigram.time` alone, the milliseconds Fitts's law estimates for every
+    /// keystroke, in place of `weights`
+    Fitts,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EvalBackend {
+    /// sum over every counted n-gram
+    #[default]
+    Ngram,
+    /// expected cost per keystroke of a markov chain fitted to the bigrams
+    Markov,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        Self {
+            corpus: PathBuf::from(".."),
+            corpora: vec![],
+            mode: CorpusMode::default(),
+            transliterate: BTreeMap::new(),
+            extensions: None,
+            ignore: ["target", "uiua", "uiua-main"].map(String::from).to_vec(),
+            gitignore: true,
+            strip_comments: false,
+            strip_strings: false,
+            max_file_chars: None,
+            equal_files: false,
+            indentation: Indentation::default(),
+            tab_width: 4,
+            caps_word: false,
+            vim: 0.0,
+            finger_targets: FingerTargets::default(),
+            hand: HandModel::default(),
+            shift: ShiftTable::default(),
+            depth: NgramDepth::default(),
+            keyboard: "ferris_sweep".to_string(),
+            reference: "qwerty".to_string(),
+            weights: EvalWeights::default(),
+            model: CostModel::default(),
+            calibration: None,
+            affinity: PathBuf::from("kb/affinity.json"),
+            affinity_weight: 5_000.0,
+            learnability: PathBuf::from("kb/learnability.json"),
+            learnability_weight: 10_000.0,
+            misfire_reach: Keyboard::DEFAULT_MISFIRE_REACH,
+            combo_choice: ComboChoice::default(),
+            backend: EvalBackend::default(),
+            markov_min_probability: 0.001,
+        }
+    }
+}
+
+impl EvalConfig {
+    /// This config counting only `part`.
+    pub fn for_part(&self, part: &CorpusPart) -> Self {
+        let mut config = self.clone();
+        config.corpus = part.path.clone();
+        config.corpora = vec![];
+        // normal mode goes into the mix of the parts once
+        config.vim = 0.0;
+        if let Some(mode) = part.mode {
+            config.mode = mode;
+            config.extensions = None;
+        }
+        if part.extensions.is_some() {
+            config.extensions = part.extensions.clone();
+        }
+        config
+    }
+
+    pub fn text_options(&self) -> TextOptions {
+        TextOptions {
+            mode: self.mode,
+            transliteration: Transliteration::with(&self.transliterate),
+            strip_comments: self.strip_comments,
+            strip_strings: self.strip_strings,
+            max_file_chars: self.max_file_chars,
+            indentation: self.indentation,
+            tab_width: self.tab_width.max(1),
+            caps_word: self.caps_word,
+            shift: self.shift.clone(),
+        }
+    }
+
+    pub fn corpus_filter(&self) -> AnyResult<CorpusFilter> {
+        let extensions = self
+            .extensions
+            .clone()
+            .unwrap_or_else(|| self.mode.default_extensions());
+        let filter = CorpusFilter::new(extensions, &self.ignore).context("bad ignore pattern")?;
+        Ok(filter
+            .with_gitignore(self.gitignore)
+            .with_equal_files(self.equal_files))
+    }
+
+    pub fn keyboard(&self) -> AnyResult<Keyboard> {
+        let keyboard = Keyboard::by_name(&self.keyboard)
+            .with_context(|| format!("keyboard {}", self.keyboard))?
+            .with_misfire_reach(self.misfire_reach)
+            .with_combo_choice(self.combo_choice)
+            .with_finger_targets(self.finger_targets)
+            .with_hand_model(self.hand)
+            .with_shift_table(self.shift.clone());
+        Ok(match &self.calibration {
+            Some(path) => keyboard.with_calibration(&Calibration::load(path)?),
+            None => keyboard,
+        })
+    }
+
+    pub fn reference_layout(&self) -> AnyResult<Layout> {
+        match ferris::by_name(&self.reference) {
+            Some(layout) => Ok(layout),
+            None => Layout::load(&self.reference),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OptimizeConfig {
+    pub start: PathBuf,
+    pub output: PathBuf,
+    pub iterations: u32,
+    /// starting temperature, cooled linearly to zero
+    pub temperature: f32,
+    /// random if missing
+    pub seed: Option<u64>,
+    pub mutation: MutationRates,
+    /// score on a second keyboard as well, producing one layout for both
+    pub joint: Option<JointConfig>,
+    /// partial layouts kept after placing each key in `beam`
+    pub beam_width: usize,
+    /// caps on metrics by name, such as `"bigram.sfb" = 80.0`, in percent of
+    /// the reference: moves past a cap are rejected whatever the score,
+    /// unless the current layout is already past it and the move does not
+    /// make it worse
+    pub protected: BTreeMap<String, f32>,
+}
+
+impl Default for OptimizeConfig {
+    fn default() -> Self {
+        Self {
+            start: PathBuf::from("kb/final.json"),
+            output: PathBuf::from("kb/final2.json"),
+            iterations: 1_000_000,
+            temperature: 30.0,
+            seed: None,
+            mutation: MutationRates::default(),
+            joint: None,
+            beam_width: 16,
+            protected: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JointConfig {
+    /// built-in geometry name or geometry file
+    pub keyboard: String,
+    /// share of the score taken from the second keyboard
+    pub weight: f32,
+    /// bonus for keys staying on the same finger on both keyboards
+    pub consistency_weight: f32,
+}
+
+impl Default for JointConfig {
+    fn default() -> Self {
+        Self {
+            keyboard: "laptop".to_string(),
+            weight: 0.5,
+            consistency_weight: 100_000.0,
+        }
+    }
+}
+
+/// Chance of each mutation being applied in a step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MutationRates {
+    pub hold_swap: f64,
+    pub key_swap: f64,
+    pub vertical_swap: f64,
+    pub new_key: f64,
+    pub new_hold: f64,
+    /// moving one key of a combo, if the layout has any
+    pub combo_move: f64,
+    /// swapping the taps of two thumb keys, keeping their holds, which
+    /// decides which thumb pairs e.g. space with a layer
+    pub thumb_pairing: f64,
+    /// moving space, enter or tab to a thumb key of its layer, swapping with
+    /// what is there
+    pub thumb_whitespace: f64,
+}
+
+impl Default for MutationRates {
+    fn default() -> Self {
+        Self {
+            hold_swap: 1.0,
+            key_swap: 1.0,
+            vertical_swap: 0.8,
+            new_key: 0.01,
+            new_hold: 0.0,
+            combo_move: 0.5,
+            thumb_pairing: 0.5,
+            thumb_whitespace: 0.2,
+        }
+    }
+}
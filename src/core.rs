@@ -0,0 +1,1145 @@
+use alloc::{format, string::String, vec, vec::Vec};
+use core::ops::{Div, Mul};
+
+use array_map::{ArrayMap, Indexable};
+use derive_more::{Add, AddAssign, Sub, Sum};
+use glam::{Vec2, Vec3};
+use macro_rules_attribute::macro_rules_derive;
+use num_enum::{FromPrimitive, IntoPrimitive};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Debug, Clone, Copy, Default, Indexable, PartialEq, Eq, IntoPrimitive, FromPrimitive, Deserialize,
+)]
+#[repr(u8)]
+#[serde(rename_all = "lowercase")]
+pub enum Hand {
+    #[default]
+    Left = 0,
+    Right = 1,
+}
+
+impl Hand {
+    pub const ALL: [Self; 2] = [Self::Left, Self::Right];
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, Indexable, PartialEq, Eq, IntoPrimitive, FromPrimitive, Deserialize,
+)]
+#[repr(u8)]
+#[serde(rename_all = "lowercase")]
+pub enum Finger {
+    #[default]
+    Thumb = 0,
+    Index = 1,
+    Middle = 2,
+    Ring = 3,
+    Pinky = 4,
+}
+
+impl Finger {
+    pub const ALL: [Self; 5] = [
+        Self::Thumb,
+        Self::Index,
+        Self::Middle,
+        Self::Ring,
+        Self::Pinky,
+    ];
+}
+
+/// One of the ten fingers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct HandFinger {
+    pub hand: Hand,
+    pub finger: Finger,
+}
+
+impl HandFinger {
+    pub fn new(hand: Hand, finger: Finger) -> Self {
+        Self { hand, finger }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HandFingerIter {
+    done: bool,
+    hand: Hand,
+    finger: Finger,
+}
+
+impl Iterator for HandFingerIter {
+    type Item = HandFinger;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let current = HandFinger {
+            hand: self.hand,
+            finger: self.finger,
+        };
+
+        let (next_finger, next_hand) = match self.finger {
+            Finger::Thumb => (Finger::Index, false),
+            Finger::Index => (Finger::Middle, false),
+            Finger::Middle => (Finger::Ring, false),
+            Finger::Ring => (Finger::Pinky, false),
+            Finger::Pinky => (Finger::Thumb, true),
+        };
+
+        let (next_hand, alive) = match (self.hand, next_hand) {
+            (Hand::Left, true) => (Hand::Right, true),
+            (Hand::Left, false) => (Hand::Left, true),
+            (Hand::Right, true) => (Hand::Right, false),
+            (Hand::Right, false) => (Hand::Right, true),
+        };
+
+        self.finger = next_finger;
+        self.hand = next_hand;
+
+        if !alive {
+            self.done = true;
+        }
+
+        Some(current)
+    }
+}
+
+unsafe impl Indexable for HandFinger {
+    const SIZE: usize = 10;
+
+    const SET_SIZE: usize = array_map::set_size(Self::SIZE);
+
+    type Iter = HandFingerIter;
+
+    fn index(self) -> usize {
+        usize::from(5 * u8::from(self.hand) + u8::from(self.finger))
+    }
+
+    fn iter() -> Self::Iter {
+        HandFingerIter::default()
+    }
+}
+
+/// Raw metrics of a layout, summed over a corpus weighted by frequency.
+#[derive(Debug, Clone, Copy, Default, Add, AddAssign, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Evaluation {
+    pub letter: LetterEval,
+    pub bigram: BigramEval,
+    pub trigram: TrigramEval,
+    pub finger: FingerEval,
+}
+
+impl Evaluation {
+    /// Every scalar metric, named by its path such as `bigram.sfb`.
+    pub fn fields(&self) -> Vec<(String, f32)> {
+        let mut fields = self.letter.fields("letter");
+        fields.extend(self.bigram.fields("bigram"));
+        fields.extend(self.trigram.fields("trigram"));
+        fields.extend(self.finger.fields("finger"));
+        fields
+    }
+}
+
+impl core::ops::Div for Evaluation {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self::Output {
+        Self {
+            letter: self.letter / rhs.letter,
+            bigram: self.bigram / rhs.bigram,
+            trigram: self.trigram / rhs.trigram,
+            finger: self.finger / rhs.finger,
+        }
+    }
+}
+
+impl core::ops::Mul<f32> for Evaluation {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self::Output {
+        Self {
+            letter: self.letter * rhs,
+            bigram: self.bigram * rhs,
+            trigram: self.trigram * rhs,
+            finger: self.finger * rhs,
+        }
+    }
+}
+
+macro_rules! splat_eval {
+    ($name:ident : f32) => {
+        $name
+    };
+    ($name:ident : Vec3) => {
+        Vec3::splat($name)
+    };
+    ($name:ident : Vec2) => {
+        Vec2::splat($name)
+    };
+}
+
+macro_rules! flatten_eval {
+    ($out:ident, $prefix:ident, $name:ident = $value:expr, f32) => {
+        $out.push((format!("{}.{}", $prefix, stringify!($name)), $value))
+    };
+    ($out:ident, $prefix:ident, $name:ident = $value:expr, Vec3) => {
+        flatten_eval!($out, $prefix, $name = $value.truncate(), Vec2);
+        $out.push((format!("{}.{}.z", $prefix, stringify!($name)), $value.z))
+    };
+    ($out:ident, $prefix:ident, $name:ident = $value:expr, Vec2) => {
+        $out.push((format!("{}.{}.x", $prefix, stringify!($name)), $value.x));
+        $out.push((format!("{}.{}.y", $prefix, stringify!($name)), $value.y))
+    };
+}
+
+macro_rules! multi_eval {
+    (
+        $(#[$meta:meta])*
+        $v:vis struct $name:ident {
+            $($v_:vis $field:ident : $ty:tt),*
+            $(,)?
+        }
+    ) =>{
+        impl core::ops::Mul<f32> for $name {
+            type Output = Self;
+
+            fn mul(self, _rhs: f32) -> Self::Output {
+                Self { $($field : self.$field * _rhs),* }
+            }
+        }
+
+        impl core::ops::Div for $name {
+            type Output = Self;
+
+            fn div(self, _rhs: Self) -> Self {
+                Self { $($field : self.$field / _rhs.$field),* }
+            }
+        }
+
+        impl core::ops::Div<f32> for $name {
+            type Output = Self;
+
+            fn div(self, _rhs: f32) -> Self {
+                Self { $($field : self.$field / _rhs),* }
+            }
+        }
+
+        #[allow(unused)]
+        impl $name {
+            pub const NAN: Self = Self::splat(f32::NAN);
+            pub const ZERO: Self = Self::splat(0.0);
+
+            pub const fn splat(_x: f32) -> Self {
+                Self { $($field: splat_eval!(_x: $ty)),* }
+            }
+
+            pub fn min(self, _other: Self) -> Self {
+                Self { $($field : self.$field.min(_other.$field)),* }
+            }
+
+            /// Every scalar metric, named like `prefix.field.x`.
+            pub fn fields(&self, _prefix: &str) -> Vec<(String, f32)> {
+                let mut _out = vec![];
+                $(flatten_eval!(_out, _prefix, $field = self.$field, $ty);)*
+                _out
+            }
+        }
+    };
+}
+
+#[macro_rules_derive(multi_eval!)]
+#[derive(Debug, Clone, Copy, Default, Add, AddAssign, Sub, Sum, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LetterEval {
+    pub base: Vec3,
+    pub stretch: Vec2,
+    pub tap_hold: f32,
+    pub lateral: f32,
+    pub effort: f32,
+}
+
+#[macro_rules_derive(multi_eval!)]
+#[derive(Debug, Clone, Copy, Default, Add, AddAssign, Sub, Sum, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BigramEval {
+    pub sfb: f32,
+    pub sfs: f32,
+    pub movement: Vec2,
+    pub staccato: f32,
+    pub coupling: f32,
+    pub misfire: f32,
+    pub retap: f32,
+    pub scissors: f32,
+    pub same_thumb: f32,
+    pub thumb_travel: f32,
+    pub thumb_alternation: f32,
+    pub time: f32,
+}
+
+#[macro_rules_derive(multi_eval!)]
+#[derive(Debug, Clone, Copy, Default, Add, AddAssign, Sub, Sum, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrigramEval {
+    pub redirects: f32,
+    pub inward_rolls: f32,
+    pub outward_rolls: f32,
+    pub alternates: f32,
+}
+
+/// Presses of each finger, holds included, and in `load` the presses each
+/// counted as many times over as its finger's share is over the target, see
+/// [`finger_load`].
+#[macro_rules_derive(multi_eval!)]
+#[derive(Debug, Clone, Copy, Default, Add, AddAssign, Sub, Sum, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FingerEval {
+    pub left_pinky: f32,
+    pub left_ring: f32,
+    pub left_middle: f32,
+    pub left_index: f32,
+    pub left_thumb: f32,
+    pub right_thumb: f32,
+    pub right_index: f32,
+    pub right_middle: f32,
+    pub right_ring: f32,
+    pub right_pinky: f32,
+    pub load: f32,
+}
+
+impl FingerEval {
+    pub fn presses_mut(&mut self, finger: HandFinger) -> &mut f32 {
+        match (finger.hand, finger.finger) {
+            (Hand::Left, Finger::Pinky) => &mut self.left_pinky,
+            (Hand::Left, Finger::Ring) => &mut self.left_ring,
+            (Hand::Left, Finger::Middle) => &mut self.left_middle,
+            (Hand::Left, Finger::Index) => &mut self.left_index,
+            (Hand::Left, Finger::Thumb) => &mut self.left_thumb,
+            (Hand::Right, Finger::Thumb) => &mut self.right_thumb,
+            (Hand::Right, Finger::Index) => &mut self.right_index,
+            (Hand::Right, Finger::Middle) => &mut self.right_middle,
+            (Hand::Right, Finger::Ring) => &mut self.right_ring,
+            (Hand::Right, Finger::Pinky) => &mut self.right_pinky,
+        }
+    }
+
+    pub fn presses(&self, finger: HandFinger) -> f32 {
+        let mut copy = *self;
+        *copy.presses_mut(finger)
+    }
+
+    pub fn total_presses(&self) -> f32 {
+        HandFinger::iter().map(|finger| self.presses(finger)).sum()
+    }
+}
+
+/// The share of all presses, in percent, each finger of either hand takes
+/// before it counts as overloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FingerTargets {
+    pub thumb: f32,
+    pub index: f32,
+    pub middle: f32,
+    pub ring: f32,
+    pub pinky: f32,
+}
+
+impl Default for FingerTargets {
+    fn default() -> Self {
+        Self {
+            thumb: 30.0,
+            index: 20.0,
+            middle: 16.0,
+            ring: 12.0,
+            pinky: 8.0,
+        }
+    }
+}
+
+impl FingerTargets {
+    pub fn of(&self, finger: Finger) -> f32 {
+        match finger {
+            Finger::Thumb => self.thumb,
+            Finger::Index => self.index,
+            Finger::Middle => self.middle,
+            Finger::Ring => self.ring,
+            Finger::Pinky => self.pinky,
+        }
+    }
+}
+
+/// The presses of `fingers`, each weighed by its finger's share over the
+/// target: once within the target, twice at double of it. Never zero, so a
+/// reference within its targets still scales it.
+pub fn finger_load(fingers: &FingerEval, targets: &FingerTargets) -> f32 {
+    let total = fingers.total_presses();
+    HandFinger::iter()
+        .map(|finger| {
+            let presses = fingers.presses(finger);
+            let share = presses / total * 100.0;
+            presses * (share / targets.of(finger.finger)).max(1.0)
+        })
+        .sum()
+}
+
+/// What one finger does for a combo: the key it taps, and the key it holds
+/// down through the tap, such as a layer key. A finger doing both is costed
+/// for both presses rather than for one between them.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FingerState<T> {
+    pub tap: Option<T>,
+    pub hold: Option<T>,
+}
+
+impl<T: Copy> FingerState<T> {
+    pub const NONE: Self = Self {
+        tap: None,
+        hold: None,
+    };
+
+    pub fn is_pressing(&self) -> bool {
+        self.tap.is_some() || self.hold.is_some()
+    }
+
+    /// The tap or the hold.
+    pub fn press_mut(&mut self, hold: bool) -> &mut Option<T> {
+        match hold {
+            true => &mut self.hold,
+            false => &mut self.tap,
+        }
+    }
+
+    /// Every press, the tap first, with whether it is held.
+    pub fn presses(&self) -> impl Iterator<Item = (T, bool)> {
+        let tap = self.tap.map(|tap| (tap, false));
+        tap.into_iter().chain(self.hold.map(|hold| (hold, true)))
+    }
+
+    /// Where the finger comes from for a press of the same kind next: the
+    /// press of that kind, or else the other one.
+    pub fn before(&self, hold: bool) -> Option<T> {
+        match hold {
+            true => self.hold.or(self.tap),
+            false => self.tap.or(self.hold),
+        }
+    }
+}
+
+pub type FingerPositions = ArrayMap<HandFinger, FingerState<Vec2>, 10>;
+/// Resting position of every finger.
+pub type BasePositions = ArrayMap<HandFinger, Vec2, 10>;
+
+/// How strong each finger is relative to the index finger; movement costs
+/// as much more as a finger is weaker.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FingerStrengths {
+    pub thumb: f32,
+    pub index: f32,
+    pub middle: f32,
+    pub ring: f32,
+    pub pinky: f32,
+}
+
+impl Default for FingerStrengths {
+    fn default() -> Self {
+        Self {
+            thumb: 0.8,
+            index: 1.0,
+            middle: 1.0,
+            ring: 0.4,
+            pinky: 0.3,
+        }
+    }
+}
+
+/// How hard it is to spread or squeeze two fingers of a hand apart from
+/// where they rest, by pair.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GapCosts {
+    pub index_middle: f32,
+    pub index_ring: f32,
+    pub index_pinky: f32,
+    pub middle_ring: f32,
+    pub middle_pinky: f32,
+    pub ring_pinky: f32,
+}
+
+impl Default for GapCosts {
+    fn default() -> Self {
+        Self {
+            index_middle: 0.6,
+            index_ring: 0.5,
+            index_pinky: 0.4,
+            middle_ring: 1.0,
+            middle_pinky: 0.9,
+            ring_pinky: 1.5,
+        }
+    }
+}
+
+/// What one finger pressing a different key in each of two keystrokes counts
+/// as in `bigram.sfb`, by whether it taps or holds each key.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SfbWeights {
+    pub tap_tap: f32,
+    /// a tap then a hold, or a hold then a tap
+    pub tap_hold: f32,
+    /// two held keys, such as a shift key then a layer key
+    pub hold_hold: f32,
+}
+
+impl Default for SfbWeights {
+    fn default() -> Self {
+        Self {
+            tap_tap: 1.0,
+            tap_hold: 1.0,
+            hold_hold: 1.0,
+        }
+    }
+}
+
+impl SfbWeights {
+    pub fn of(&self, hold1: bool, hold2: bool) -> f32 {
+        match (hold1, hold2) {
+            (false, false) => self.tap_tap,
+            (true, true) => self.hold_hold,
+            _ => self.tap_hold,
+        }
+    }
+
+    /// How much the finger pressing `x1` then `x2` counts as a same finger
+    /// bigram: the heaviest pair of a press in each on different keys. A key
+    /// held through both keystrokes pairs with nothing, and tapping or
+    /// holding the same key again is no sfb.
+    pub fn finger<T: Copy + PartialEq>(&self, x1: &FingerState<T>, x2: &FingerState<T>) -> f32 {
+        let mut sfb = 0.0f32;
+        for (k1, hold1) in x1.presses() {
+            if hold1 && x2.hold == Some(k1) {
+                continue;
+            }
+            for (k2, hold2) in x2.presses() {
+                if k1 != k2 && !(hold2 && x1.hold == Some(k2)) {
+                    sfb = sfb.max(self.of(hold1, hold2));
+                }
+            }
+        }
+        sfb
+    }
+}
+
+/// Fitts's law for a keypress: `intercept + slope * log2(distance /
+/// key_size + 1)` milliseconds, the slope over the strength of the finger.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FittsModel {
+    /// milliseconds of a press without moving
+    pub intercept: f32,
+    /// milliseconds per bit of difficulty
+    pub slope: f32,
+    /// width of a key in millimetres
+    pub key_size: f32,
+}
+
+impl Default for FittsModel {
+    fn default() -> Self {
+        Self {
+            intercept: 100.0,
+            slope: 80.0,
+            key_size: 18.0,
+        }
+    }
+}
+
+impl FittsModel {
+    /// Milliseconds for a finger of `strength` to move `distance` and press.
+    pub fn time(&self, strength: f32, distance: f32) -> f32 {
+        self.intercept + self.slope / strength * libm::log2f(distance / self.key_size + 1.0)
+    }
+}
+
+/// The hands typing: finger strengths, gap costs, how much harder a finger
+/// works while it holds a key, and how long a press takes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandModel {
+    pub strength: FingerStrengths,
+    pub gaps: GapCosts,
+    /// cost of moving or stretching a finger that holds a key, relative to
+    /// one that taps
+    pub hold_multiplier: f32,
+    pub sfb: SfbWeights,
+    pub fitts: FittsModel,
+}
+
+impl Default for HandModel {
+    fn default() -> Self {
+        Self {
+            strength: FingerStrengths::default(),
+            gaps: GapCosts::default(),
+            hold_multiplier: 1.8,
+            sfb: SfbWeights::default(),
+            fitts: FittsModel::default(),
+        }
+    }
+}
+
+impl HandModel {
+    pub fn strength(&self, finger: Finger) -> f32 {
+        let strength = &self.strength;
+        match finger {
+            Finger::Thumb => strength.thumb,
+            Finger::Index => strength.index,
+            Finger::Middle => strength.middle,
+            Finger::Ring => strength.ring,
+            Finger::Pinky => strength.pinky,
+        }
+    }
+
+    /// `a` comes before `b` counting from the thumb.
+    pub fn gap_cost(&self, a: Finger, b: Finger) -> f32 {
+        let gaps = &self.gaps;
+        match (a, b) {
+            (Finger::Thumb, _) => 0.0,
+            (Finger::Index, Finger::Middle) => gaps.index_middle,
+            (Finger::Index, Finger::Ring) => gaps.index_ring,
+            (Finger::Index, Finger::Pinky) => gaps.index_pinky,
+            (Finger::Middle, Finger::Ring) => gaps.middle_ring,
+            (Finger::Middle, Finger::Pinky) => gaps.middle_pinky,
+            (Finger::Ring, Finger::Pinky) => gaps.ring_pinky,
+
+            (_, Finger::Thumb)
+            | (Finger::Index, Finger::Index)
+            | (Finger::Middle, Finger::Index)
+            | (Finger::Ring, Finger::Index)
+            | (Finger::Pinky, Finger::Index)
+            | (Finger::Middle, Finger::Middle)
+            | (Finger::Ring, Finger::Middle)
+            | (Finger::Pinky, Finger::Middle)
+            | (Finger::Ring, Finger::Ring)
+            | (Finger::Pinky, Finger::Ring)
+            | (Finger::Pinky, Finger::Pinky) => unreachable!(),
+        }
+    }
+
+    pub fn hold_multiplier(&self, hold: bool) -> f32 {
+        match hold {
+            true => self.hold_multiplier,
+            false => 1.0,
+        }
+    }
+}
+
+/// Cost of one finger pressing keys at `a` and `b` at once, on top of
+/// reaching between them: a flat finger across two keys is far harder than
+/// moving the same distance.
+pub fn same_finger_chord_cost(hands: &HandModel, finger: Finger, a: Vec2, b: Vec2) -> Vec2 {
+    const SAME_FINGER_CHORD: f32 = 3.0;
+    (a - b).abs() * SAME_FINGER_CHORD / hands.strength(finger)
+}
+
+pub fn finger_axis(finger: HandFinger) -> Vec2 {
+    let flip = finger.hand != Hand::Right;
+    let vec = match finger.finger {
+        Finger::Thumb => Vec2::X,
+        Finger::Index => Vec2::new(-1.0, 4.0).normalize(),
+        Finger::Middle | Finger::Ring | Finger::Pinky => Vec2::Y,
+    };
+    match flip {
+        true => Vec2::new(vec.x, vec.y),
+        false => Vec2::new(-vec.x, vec.y),
+    }
+}
+
+fn alignment_to_multiplier(hand: HandFinger, delta: Vec2) -> f32 {
+    let axis = finger_axis(hand);
+    let align = delta.normalize_or_zero().dot(axis);
+
+    1.0 + (-align) * 0.5
+}
+
+/// Cost of holding every finger in `h` away from its resting position in `bases`.
+pub fn letter_cost(hands: &HandModel, bases: &BasePositions, h: &FingerPositions) -> LetterEval {
+    let mut base = Vec3::ZERO;
+
+    for ((h, state), &b) in h.iter().zip(bases.values()) {
+        for (a, hold) in state.presses() {
+            let strength = hands.strength(h.finger);
+            let delta = (a - b).abs();
+            let align = alignment_to_multiplier(h, delta);
+
+            let delta = Vec3::new(delta.x, delta.y, Z)
+                * strength.recip()
+                * hands.hold_multiplier(hold)
+                * align;
+            const Z: f32 = 2.0;
+            base += delta;
+        }
+    }
+
+    let mut stretch = Vec2::ZERO;
+    for hand in Hand::ALL {
+        let mut last_finger: Option<(Finger, FingerState<Vec2>)> = None;
+        for finger in Finger::ALL {
+            let hf = HandFinger::new(hand, finger);
+            let state = h[hf];
+            if !state.is_pressing() {
+                continue;
+            }
+
+            if let Some((last_finger, last_state)) = last_finger {
+                let cost = hands.gap_cost(last_finger, finger);
+                let base_pos = bases[hf];
+                let last_base = bases[HandFinger::new(hand, last_finger)];
+
+                // flat rows put neighbouring fingers at the same height
+                let base_dist = (base_pos - last_base).abs().max(Vec2::ONE);
+                for (pos, hold) in state.presses() {
+                    for (last_pos, last_hold) in last_state.presses() {
+                        let now_dist: Vec2 = pos - last_pos;
+
+                        let ratio = (now_dist.abs() / base_dist)
+                            * cost
+                            * hands.hold_multiplier(hold)
+                            * hands.hold_multiplier(last_hold);
+
+                        stretch += ratio;
+                    }
+                }
+            } else {
+                last_finger = Some((finger, state));
+            }
+        }
+    }
+
+    LetterEval {
+        base,
+        stretch,
+        tap_hold: 0.0,
+        lateral: 0.0,
+        effort: 0.0,
+    }
+}
+
+/// Cost of moving from the fingers in `h1` to the fingers in `h2`.
+/// `staccato` counts the held keys that had to be released in between, and
+/// `crossing` is whether the two taps are on different layers.
+pub fn bigram_cost(
+    hands: &HandModel,
+    h1: &FingerPositions,
+    h2: &FingerPositions,
+    staccato: f32,
+    crossing: bool,
+) -> BigramEval {
+    let sfb = h1
+        .values()
+        .zip(h2.values())
+        .map(|(x1, x2)| hands.sfb.finger(x1, x2))
+        .sum();
+
+    let mut movement = Vec2::ZERO;
+    for ((hand, x1), x2) in h1.iter().zip(h2.values()) {
+        for (y, hy) in x2.presses() {
+            if let Some(x) = x1.before(hy) {
+                let delta = (x - y).abs() / hands.strength(hand.finger) * hands.hold_multiplier(hy);
+                movement += delta;
+            }
+        }
+    }
+
+    // movement spent crossing between layers, e.g. from a letter to a symbol
+    let coupling = match crossing {
+        true => movement.x + movement.y,
+        false => 0.0,
+    };
+
+    BigramEval {
+        sfb,
+        sfs: 0.0,
+        movement,
+        staccato,
+        coupling,
+        misfire: 0.0,
+        retap: 0.0,
+        scissors: 0.0,
+        same_thumb: 0.0,
+        thumb_travel: 0.0,
+        thumb_alternation: 0.0,
+        time: 0.0,
+    }
+}
+
+/// Milliseconds from pressing `h1` to pressing `h2` by [`FittsModel`], of
+/// the slowest finger. Each finger moves from its own press in `h1`, or else
+/// from where it rests, and a key held through both takes no time.
+pub fn bigram_time(
+    hands: &HandModel,
+    bases: &BasePositions,
+    h1: &FingerPositions,
+    h2: &FingerPositions,
+) -> f32 {
+    let mut time = 0.0f32;
+    for ((finger, x1), x2) in h1.iter().zip(h2.values()) {
+        for (y, hold) in x2.presses() {
+            if hold && x1.hold == Some(y) {
+                continue;
+            }
+            let from = x1.before(hold).unwrap_or(bases[finger]);
+            time = time.max(
+                hands
+                    .fitts
+                    .time(hands.strength(finger.finger), from.distance(y)),
+            );
+        }
+    }
+    time
+}
+
+/// Vertical distance in millimetres, measured from where each finger rests,
+/// past which two neighbouring fingers scissor: more than a row apart, such as
+/// the top row with the bottom one.
+pub const SCISSOR_DISTANCE: f32 = 25.0;
+
+/// Neighbouring fingers of one hand going from `h1` to `h2` more than
+/// [`SCISSOR_DISTANCE`] apart vertically, each weighed by the gap cost of the
+/// pair, such as the middle finger on the bottom row then the ring finger on
+/// the top one.
+pub fn scissor_cost(
+    hands: &HandModel,
+    base: &BasePositions,
+    h1: &FingerPositions,
+    h2: &FingerPositions,
+) -> f32 {
+    let mut scissors = 0.0;
+    let presses = |h: &FingerPositions| {
+        h.iter()
+            .flat_map(|(finger, state)| state.presses().map(move |(pos, _)| (finger, pos)))
+            .collect::<Vec<_>>()
+    };
+    let (presses1, presses2) = (presses(h1), presses(h2));
+    for &(f1, p1) in &presses1 {
+        for &(f2, p2) in &presses2 {
+            let (a, b) = (f1.finger as u8, f2.finger as u8);
+            if f1.hand != f2.hand || a.abs_diff(b) != 1 || a.min(b) == Finger::Thumb as u8 {
+                continue;
+            }
+            let dy = (p1.y - base[f1].y) - (p2.y - base[f2].y);
+            if dy.abs() > SCISSOR_DISTANCE {
+                scissors += match a < b {
+                    true => hands.gap_cost(f1.finger, f2.finger),
+                    false => hands.gap_cost(f2.finger, f1.finger),
+                };
+            }
+        }
+    }
+    scissors
+}
+
+/// The key each finger taps and holds for one combo, for combos that tap
+/// and hold at most one key with each finger.
+pub type FingerKeys = ArrayMap<HandFinger, FingerState<usize>, 10>;
+
+/// What [`letter_cost`], [`bigram_cost`] and [`scissor_cost`] work out from
+/// key positions, worked out once for every key and pair of keys of a
+/// keyboard, so that evaluating an n-gram only adds up table entries.
+#[derive(Debug, Clone, Default)]
+pub struct KeyTables {
+    fingers: Vec<HandFinger>,
+    hold_multiplier: f32,
+    sfb: SfbWeights,
+    /// `letter.base` of tapping each key
+    base: Vec<Vec3>,
+    /// `letter.stretch` of tapping `a` and `b` at once, `[a * len + b]`, for
+    /// `a` on a finger before `b`'s on the same hand
+    stretch: Vec<Vec2>,
+    /// `bigram.movement` of one finger tapping `a` then `b`
+    movement: Vec<Vec2>,
+    /// `bigram.scissors` of tapping `a` then `b`
+    scissors: Vec<f32>,
+    /// milliseconds of pressing each key from where its finger rests
+    reach: Vec<f32>,
+    /// milliseconds of one finger pressing `a` then `b`
+    travel: Vec<f32>,
+}
+
+impl KeyTables {
+    /// Tables for keys at `keys`, pressed by their fingers.
+    pub fn new(hands: &HandModel, bases: &BasePositions, keys: &[(Vec2, HandFinger)]) -> Self {
+        let len = keys.len();
+        let single = |k: usize| {
+            let mut h = FingerPositions::new([FingerState::NONE; 10]);
+            h[keys[k].1].tap = Some(keys[k].0);
+            h
+        };
+        let base = (0..len)
+            .map(|k| letter_cost(hands, bases, &single(k)).base)
+            .collect();
+        let none = FingerPositions::new([FingerState::NONE; 10]);
+        let reach = (0..len)
+            .map(|k| bigram_time(hands, bases, &none, &single(k)))
+            .collect();
+        let (mut stretch, mut movement, mut scissors, mut travel) =
+            (vec![], vec![], vec![], vec![]);
+        for a in 0..len {
+            for b in 0..len {
+                let (h1, h2) = (single(a), single(b));
+                let (fa, fb) = (keys[a].1, keys[b].1);
+                stretch.push(
+                    match fa.hand == fb.hand && (fa.finger as u8) < fb.finger as u8 {
+                        true => {
+                            let mut both = h1;
+                            both[fb] = h2[fb];
+                            letter_cost(hands, bases, &both).stretch
+                        }
+                        false => Vec2::ZERO,
+                    },
+                );
+                movement.push(match fa == fb {
+                    true => bigram_cost(hands, &h1, &h2, 0.0, false).movement,
+                    false => Vec2::ZERO,
+                });
+                travel.push(match fa == fb {
+                    true => bigram_time(hands, bases, &h1, &h2),
+                    false => 0.0,
+                });
+                scissors.push(scissor_cost(hands, bases, &h1, &h2));
+            }
+        }
+
+        Self {
+            fingers: keys.iter().map(|&(_, finger)| finger).collect(),
+            hold_multiplier: hands.hold_multiplier,
+            sfb: hands.sfb,
+            base,
+            stretch,
+            movement,
+            scissors,
+            reach,
+            travel,
+        }
+    }
+
+    fn hold(&self, hold: bool) -> f32 {
+        match hold {
+            true => self.hold_multiplier,
+            false => 1.0,
+        }
+    }
+
+    fn pair(&self, a: usize, b: usize) -> usize {
+        a * self.fingers.len() + b
+    }
+
+    /// Same as [`letter_cost`] of the positions of `h`.
+    pub fn letter_cost(&self, h: &FingerKeys) -> LetterEval {
+        let mut base = Vec3::ZERO;
+        for (key, hold) in h.values().flat_map(FingerState::presses) {
+            base += self.base[key] * self.hold(hold);
+        }
+
+        let mut stretch = Vec2::ZERO;
+        for hand in Hand::ALL {
+            let mut first: Option<FingerState<usize>> = None;
+            for finger in Finger::ALL {
+                let state = h[HandFinger::new(hand, finger)];
+                if !state.is_pressing() {
+                    continue;
+                }
+                match first {
+                    Some(first_state) => {
+                        for (key, hold) in state.presses() {
+                            for (first, first_hold) in first_state.presses() {
+                                stretch += self.stretch[self.pair(first, key)]
+                                    * self.hold(hold)
+                                    * self.hold(first_hold);
+                            }
+                        }
+                    }
+                    None => first = Some(state),
+                }
+            }
+        }
+
+        LetterEval {
+            base,
+            stretch,
+            tap_hold: 0.0,
+            lateral: 0.0,
+            effort: 0.0,
+        }
+    }
+
+    /// Same as [`bigram_cost`] of the positions of `h1` and `h2`, with
+    /// `scissors` as [`scissor_cost`] and `time` as [`bigram_time`].
+    pub fn bigram_cost(
+        &self,
+        h1: &FingerKeys,
+        h2: &FingerKeys,
+        staccato: f32,
+        crossing: bool,
+    ) -> BigramEval {
+        let mut sfb = 0.0;
+        let mut movement = Vec2::ZERO;
+        let mut time = 0.0f32;
+        for (x1, x2) in h1.values().zip(h2.values()) {
+            sfb += self.sfb.finger(x1, x2);
+            for (k2, hold2) in x2.presses() {
+                let held = hold2 && x1.hold == Some(k2);
+                match x1.before(hold2) {
+                    Some(k1) => {
+                        movement += self.movement[self.pair(k1, k2)] * self.hold(hold2);
+                        if !held {
+                            time = time.max(self.travel[self.pair(k1, k2)]);
+                        }
+                    }
+                    None => time = time.max(self.reach[k2]),
+                }
+            }
+        }
+
+        let mut scissors = 0.0;
+        for (k1, _) in h1.values().flat_map(FingerState::presses) {
+            for (k2, _) in h2.values().flat_map(FingerState::presses) {
+                scissors += self.scissors[self.pair(k1, k2)];
+            }
+        }
+
+        let coupling = match crossing {
+            true => movement.x + movement.y,
+            false => 0.0,
+        };
+
+        BigramEval {
+            sfb,
+            sfs: 0.0,
+            movement,
+            staccato,
+            coupling,
+            misfire: 0.0,
+            retap: 0.0,
+            scissors,
+            same_thumb: 0.0,
+            thumb_travel: 0.0,
+            thumb_alternation: 0.0,
+            time,
+        }
+    }
+}
+
+/// Frequency-weighted sum of `cost` over the given n-grams. `cost` returns a
+/// total and how many ways of typing the n-gram it was summed over.
+pub fn weighted_sum<K, T>(
+    ngrams: impl IntoIterator<Item = (K, u32)>,
+    cost: impl Fn(K) -> (T, f32),
+) -> T
+where
+    T: Div<f32, Output = T> + Mul<f32, Output = T> + core::iter::Sum,
+{
+    ngrams
+        .into_iter()
+        .map(|(ngram, freq)| {
+            let (total, ways) = cost(ngram);
+            total / ways * freq as f32
+        })
+        .sum()
+}
+
+/// sum squared evaluation
+pub fn sse<const N: usize>(combos: [(f32, f32); N]) -> f32 {
+    // a metric is 0/0 when neither the layout nor the reference has any of it,
+    // e.g. no misfires without tap-hold keys, which costs nothing
+    combos
+        .into_iter()
+        .filter(|&(w, x)| w != 0.0 && !x.is_nan())
+        .map(|(w, x)| w * x * x)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(tap: Option<usize>, hold: Option<usize>) -> FingerState<usize> {
+        FingerState { tap, hold }
+    }
+
+    const WEIGHTS: SfbWeights = SfbWeights {
+        tap_tap: 1.0,
+        tap_hold: 0.5,
+        hold_hold: 0.25,
+    };
+
+    #[test]
+    fn sfb_tap_tap() {
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(1), None), &state(Some(2), None)),
+            1.0
+        );
+    }
+
+    #[test]
+    fn sfb_tap_hold() {
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(1), None), &state(None, Some(2))),
+            0.5
+        );
+    }
+
+    #[test]
+    fn sfb_hold_tap() {
+        assert_eq!(
+            WEIGHTS.finger(&state(None, Some(1)), &state(Some(2), None)),
+            0.5
+        );
+    }
+
+    #[test]
+    fn sfb_hold_hold() {
+        assert_eq!(
+            WEIGHTS.finger(&state(None, Some(1)), &state(None, Some(2))),
+            0.25
+        );
+    }
+
+    #[test]
+    fn no_sfb_on_the_same_key() {
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(1), None), &state(Some(1), None)),
+            0.0
+        );
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(1), None), &state(None, Some(1))),
+            0.0
+        );
+    }
+
+    #[test]
+    fn no_sfb_for_a_key_held_through() {
+        assert_eq!(
+            WEIGHTS.finger(&state(None, Some(1)), &state(None, Some(1))),
+            0.0
+        );
+        // the tap before is still an sfb with the other tap
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(2), Some(1)), &state(Some(3), Some(1))),
+            1.0
+        );
+    }
+
+    #[test]
+    fn no_sfb_without_the_finger() {
+        assert_eq!(
+            WEIGHTS.finger(&state(Some(1), None), &FingerState::NONE),
+            0.0
+        );
+        assert_eq!(
+            WEIGHTS.finger(&FingerState::NONE, &state(None, Some(1))),
+            0.0
+        );
+    }
+}
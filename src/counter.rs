@@ -1,182 +1,1163 @@
+use anyhow::{anyhow, bail, Result as AnyResult};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use rustc_hash::{FxHashMap, FxHasher};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    collections::BTreeMap,
+    ffi::OsStr,
     fs::File,
+    hash::Hasher,
     io::Read,
-    num::NonZeroU8,
     path::{Component, Path, PathBuf},
+    time::SystemTime,
 };
 
-use walkdir::WalkDir;
+use ignore::WalkBuilder;
 
-use crate::in_alphabet;
+use crate::{
+    in_alphabet, prose,
+    shift::ShiftTable,
+    strip::{self, Language},
+    symbol::{self, Symbol},
+};
+
+/// Longest n-gram that is counted. Shallower corpora take far less memory:
+/// trigrams outnumber letters by orders of magnitude, and quadgrams, only
+/// counted when asked for, trigrams.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NgramDepth {
+    Letters,
+    Bigrams,
+    #[default]
+    Trigrams,
+    Quadgrams,
+}
+
+/// How the text of a corpus file becomes keystrokes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CorpusMode {
+    /// source code, with indentation typed as [`Indentation`] says
+    #[default]
+    Code,
+    /// writing, see [`crate::prose::normalize`]
+    Prose,
+    /// writing with markdown syntax left out
+    Markdown,
+}
+
+impl CorpusMode {
+    /// Extensions counted unless configured otherwise.
+    pub fn default_extensions(self) -> Vec<String> {
+        let extensions: &[&str] = match self {
+            CorpusMode::Code => &["rs", "wgsl", "glsl", "vert", "comp", "frag", "py"],
+            CorpusMode::Prose | CorpusMode::Markdown => &["txt", "md"],
+        };
+        extensions.iter().map(|&ext| ext.to_string()).collect()
+    }
+}
+
+/// How the leading whitespace of lines of code is typed, as editors seldom
+/// have it typed space by space.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Indentation {
+    /// a tab for every tab width of indentation, spaces for the rest
+    #[default]
+    Tabs,
+    /// every space and tab as it is in the file
+    Literal,
+    /// one tab for any indentation
+    Collapse,
+    /// not typed at all
+    Ignore,
+}
+
+/// Ascii keys typed for characters outside the alphabet, so that accents and
+/// typographic punctuation do not break the n-grams around them. A character
+/// mapped to nothing is skipped without breaking them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transliteration(BTreeMap<char, String>);
+
+impl Default for Transliteration {
+    fn default() -> Self {
+        const LETTERS: [(&str, &str); 16] = [
+            ("àáâãäåāą", "a"),
+            ("çćč", "c"),
+            ("ďđ", "d"),
+            ("èéêëēęě", "e"),
+            ("ìíîïī", "i"),
+            ("ł", "l"),
+            ("ñńň", "n"),
+            ("òóôõöøō", "o"),
+            ("ř", "r"),
+            ("śšş", "s"),
+            ("ťţ", "t"),
+            ("ùúûüūů", "u"),
+            ("ýÿ", "y"),
+            ("źżž", "z"),
+            ("æ", "ae"),
+            ("œ", "oe"),
+        ];
+        const SYMBOLS: [(&str, &str); 6] = [
+            ("‘’‚′", "'"),
+            ("“”„″«»", "\""),
+            ("‐–—−", "-"),
+            ("…", "..."),
+            ("\u{a0}\u{2009}\u{202f}", " "),
+            ("ß", "ss"),
+        ];
+
+        let mut map = BTreeMap::new();
+        for (from, to) in LETTERS {
+            for ch in from.chars() {
+                map.insert(ch, to.to_string());
+                map.extend(ch.to_uppercase().map(|upper| (upper, to.to_uppercase())));
+            }
+        }
+        for (from, to) in SYMBOLS {
+            map.extend(from.chars().map(|ch| (ch, to.to_string())));
+        }
+        Self(map)
+    }
+}
+
+impl Transliteration {
+    /// The default table with `entries` added or overriding it.
+    pub fn with(entries: &BTreeMap<char, String>) -> Self {
+        let mut table = Self::default();
+        table
+            .0
+            .extend(entries.iter().map(|(&ch, to)| (ch, to.clone())));
+        table
+    }
+
+    pub fn get(&self, ch: char) -> Option<&str> {
+        self.0.get(&ch).map(String::as_str)
+    }
+}
+
+/// How the text of a corpus file becomes keystrokes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextOptions {
+    pub mode: CorpusMode,
+    pub transliteration: Transliteration,
+    /// leave out comments of code, see [`crate::strip::strip`]
+    pub strip_comments: bool,
+    /// leave out the contents of string literals in code
+    pub strip_strings: bool,
+    /// characters counted from the start of each file, after stripping, so
+    /// that one huge generated file cannot drown out the rest
+    pub max_file_chars: Option<usize>,
+    pub indentation: Indentation,
+    /// columns a tab indents by, for [`Indentation::Tabs`]
+    pub tab_width: usize,
+    /// type runs of two or more capitals, such as `HTTP_RESPONSE`, with a
+    /// caps word toggle instead of shifting every capital
+    pub caps_word: bool,
+    /// the keys a caps word types its capitals with
+    pub shift: ShiftTable,
+}
+
+impl Default for TextOptions {
+    fn default() -> Self {
+        Self {
+            mode: CorpusMode::default(),
+            transliteration: Transliteration::default(),
+            strip_comments: false,
+            strip_strings: false,
+            max_file_chars: None,
+            indentation: Indentation::default(),
+            tab_width: 4,
+            caps_word: false,
+            shift: ShiftTable::default(),
+        }
+    }
+}
 
 #[derive(Default)]
 pub struct NGramTracker {
-    last: [Option<NonZeroU8>; 2],
+    last: [Option<Symbol>; 3],
+    depth: NgramDepth,
 }
 
 impl NGramTracker {
+    pub fn new(depth: NgramDepth) -> Self {
+        Self {
+            last: [None; 3],
+            depth,
+        }
+    }
+
     pub fn clear(&mut self) {
-        *self = Self::default();
+        self.last = [None; 3];
     }
 
-    pub fn shift(&mut self, x: NonZeroU8) {
-        let [_, b] = self.last;
-        self.last = [b, Some(x)];
+    pub fn shift(&mut self, x: Symbol) {
+        let [_, a, b] = self.last;
+        self.last = [a, b, Some(x)];
     }
 
-    pub fn apply(&mut self, counter: &mut CountOutcome, c: NonZeroU8) {
-        let [a, b] = self.last;
-        counter.add_letter([c.into()]);
-        if let Some(b) = b {
-            counter.add_bigram([b.into(), c.into()]);
-            if let Some(a) = a {
-                counter.add_trigram([a.into(), b.into(), c.into()]);
+    pub fn apply(&mut self, counter: &mut CountOutcome, c: Symbol) {
+        let [z, a, b] = self.last;
+        counter.add_letter([c]);
+        if let Some(b) = b.filter(|_| self.depth >= NgramDepth::Bigrams) {
+            counter.add_bigram([b, c]);
+            if let Some(a) = a.filter(|_| self.depth >= NgramDepth::Trigrams) {
+                counter.add_trigram([a, b, c]);
+                counter.add_skipgram([a, c]);
+                if let Some(z) = z.filter(|_| self.depth >= NgramDepth::Quadgrams) {
+                    counter.add_quadgram([z, a, b, c]);
+                }
             }
         }
         self.shift(c);
     }
 }
 
-pub type Letters = HashMap<[u8; 1], u32>;
-pub type Bigrams = HashMap<[u8; 2], u32>;
-pub type Trigrams = HashMap<[u8; 3], u32>;
+// fixed hasher so that iteration, and with it float summation, happens in the
+// same order on every run
+pub type Letters = FxHashMap<[Symbol; 1], u32>;
+pub type Bigrams = FxHashMap<[Symbol; 2], u32>;
+pub type Trigrams = FxHashMap<[Symbol; 3], u32>;
+/// Pairs of keys with one key between them, counted with trigrams.
+pub type Skipgrams = FxHashMap<[Symbol; 2], u32>;
+pub type Quadgrams = FxHashMap<[Symbol; 4], u32>;
+
+/// Total each table is scaled to where counts of unrelated sizes are combined
+/// or imported: enough to keep rare n-grams, little enough for `u32` counts.
+pub const NORMALIZED_TOTAL: f64 = 1e9;
 
+fn mix_into<const N: usize>(
+    out: &mut FxHashMap<[Symbol; N], u32>,
+    table: &FxHashMap<[Symbol; N], u32>,
+    share: f64,
+) {
+    let total = table.values().map(|&freq| f64::from(freq)).sum::<f64>();
+    if total <= 0.0 {
+        return;
+    }
+    for (&ngram, &freq) in table {
+        let count = (f64::from(freq) / total * share).round() as u32;
+        if count > 0 {
+            *out.entry(ngram).or_default() += count;
+        }
+    }
+}
+
+/// N-gram frequencies of a corpus.
 #[derive(Default, Debug, Deserialize, Serialize)]
 pub struct CountOutcome {
     pub letter: Letters,
     pub bigrams: Bigrams,
     pub trigrams: Trigrams,
+    pub skipgrams: Skipgrams,
+    /// empty unless counted to [`NgramDepth::Quadgrams`]
+    #[serde(default)]
+    pub quadgrams: Quadgrams,
+    /// runs of capitals typed lowercase after one caps word toggle, by the
+    /// letter each run starts with; empty unless counted with
+    /// [`TextOptions::caps_word`]
+    #[serde(default)]
+    pub caps_words: Letters,
 }
 
 impl CountOutcome {
-    pub fn add_letter(&mut self, letter: [u8; 1]) {
+    /// Drops every n-gram longer than `depth`.
+    pub fn truncate(&mut self, depth: NgramDepth) {
+        if depth < NgramDepth::Quadgrams {
+            self.quadgrams = Quadgrams::default();
+        }
+        if depth < NgramDepth::Trigrams {
+            self.trigrams = Trigrams::default();
+            self.skipgrams = Skipgrams::default();
+        }
+        if depth < NgramDepth::Bigrams {
+            self.bigrams = Bigrams::default();
+        }
+    }
+
+    /// Every table of every part scaled to its weight's share of
+    /// [`NORMALIZED_TOTAL`] and added up, so corpora mix by weight whatever
+    /// their size.
+    pub fn mix(parts: &[(&CountOutcome, f32)]) -> Self {
+        let total_weight = parts
+            .iter()
+            .map(|&(_, weight)| f64::from(weight))
+            .sum::<f64>();
+        let mut mixed = Self::default();
+        for (count, weight) in parts {
+            let share = f64::from(*weight) / total_weight * NORMALIZED_TOTAL;
+            mix_into(&mut mixed.letter, &count.letter, share);
+            mix_into(&mut mixed.bigrams, &count.bigrams, share);
+            mix_into(&mut mixed.trigrams, &count.trigrams, share);
+            mix_into(&mut mixed.skipgrams, &count.skipgrams, share);
+            mix_into(&mut mixed.quadgrams, &count.quadgrams, share);
+            // toggles stay in proportion to the letters they are typed among
+            let letters = count
+                .letter
+                .values()
+                .map(|&freq| f64::from(freq))
+                .sum::<f64>();
+            if letters > 0.0 {
+                for (&letter, &freq) in &count.caps_words {
+                    let freq = (f64::from(freq) / letters * share).round() as u32;
+                    *mixed.caps_words.entry(letter).or_default() += freq;
+                }
+            }
+        }
+        mixed
+    }
+
+    /// Adds every count of `other`.
+    pub fn merge(&mut self, other: &CountOutcome) {
+        fn add<const N: usize>(
+            into: &mut FxHashMap<[Symbol; N], u32>,
+            from: &FxHashMap<[Symbol; N], u32>,
+        ) {
+            for (&ngram, &freq) in from {
+                *into.entry(ngram).or_default() += freq;
+            }
+        }
+        add(&mut self.letter, &other.letter);
+        add(&mut self.bigrams, &other.bigrams);
+        add(&mut self.trigrams, &other.trigrams);
+        add(&mut self.skipgrams, &other.skipgrams);
+        add(&mut self.quadgrams, &other.quadgrams);
+        add(&mut self.caps_words, &other.caps_words);
+    }
+
+    /// Approximate heap size of the tables in bytes.
+    pub fn memory_bytes(&self) -> usize {
+        fn table<K, V>(map: &FxHashMap<K, V>) -> usize {
+            // one control byte per bucket next to each entry
+            map.capacity() * (size_of::<(K, V)>() + 1)
+        }
+        table(&self.letter)
+            + table(&self.bigrams)
+            + table(&self.trigrams)
+            + table(&self.skipgrams)
+            + table(&self.quadgrams)
+            + table(&self.caps_words)
+    }
+
+    pub fn add_letter(&mut self, letter: [Symbol; 1]) {
         let count = self.letter.entry(letter).or_insert(0);
         *count += 1;
     }
 
-    pub fn add_bigram(&mut self, bigram: [u8; 2]) {
+    pub fn add_bigram(&mut self, bigram: [Symbol; 2]) {
         let count = self.bigrams.entry(bigram).or_insert(0);
         *count += 1;
     }
 
-    pub fn add_trigram(&mut self, trigram: [u8; 3]) {
+    pub fn add_trigram(&mut self, trigram: [Symbol; 3]) {
         let count = self.trigrams.entry(trigram).or_insert(0);
         *count += 1;
     }
+
+    pub fn add_skipgram(&mut self, skipgram: [Symbol; 2]) {
+        let count = self.skipgrams.entry(skipgram).or_insert(0);
+        *count += 1;
+    }
+
+    pub fn add_caps_word(&mut self, letter: Symbol) {
+        let count = self.caps_words.entry([letter]).or_insert(0);
+        *count += 1;
+    }
+
+    pub fn add_quadgram(&mut self, quadgram: [Symbol; 4]) {
+        let count = self.quadgrams.entry(quadgram).or_insert(0);
+        *count += 1;
+    }
+}
+
+/// Version written into exported counts; imports of any other are rejected.
+pub const COUNTS_VERSION: u32 = 2;
+
+/// N-gram counts keyed by the n-gram text, sorted so exports diff cleanly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NgramTable {
+    pub letters: BTreeMap<String, u32>,
+    pub bigrams: BTreeMap<String, u32>,
+    pub trigrams: BTreeMap<String, u32>,
+    /// missing before skipgrams were counted
+    #[serde(default)]
+    pub skipgrams: BTreeMap<String, u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub quadgrams: BTreeMap<String, u32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub caps_words: BTreeMap<String, u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CountsJson {
+    version: u32,
+    #[serde(flatten)]
+    table: NgramTable,
+}
+
+fn to_strings<const N: usize>(
+    counts: &FxHashMap<[Symbol; N], u32>,
+    min_count: u32,
+) -> BTreeMap<String, u32> {
+    counts
+        .iter()
+        .filter(|&(_, &freq)| freq >= min_count)
+        .map(|(ngram, &freq)| (symbol::text(ngram), freq))
+        .collect()
+}
+
+/// Zero counts are left out, so an n-gram can be dropped from an exported
+/// table by zeroing it by hand.
+fn from_strings<const N: usize>(
+    counts: &BTreeMap<String, u32>,
+) -> AnyResult<FxHashMap<[Symbol; N], u32>> {
+    counts
+        .iter()
+        .filter(|(_, &freq)| freq > 0)
+        .map(|(ngram, &freq)| {
+            let key = symbol::ngram(ngram)
+                .ok_or_else(|| anyhow!("{ngram:?} is not {N} characters with a symbol each"))?;
+            Ok((key, freq))
+        })
+        .collect()
+}
+
+impl NgramTable {
+    /// Every n-gram of `count` seen at least `min_count` times.
+    pub fn new(count: &CountOutcome, min_count: u32) -> Self {
+        Self {
+            letters: to_strings(&count.letter, min_count),
+            bigrams: to_strings(&count.bigrams, min_count),
+            trigrams: to_strings(&count.trigrams, min_count),
+            skipgrams: to_strings(&count.skipgrams, min_count),
+            quadgrams: to_strings(&count.quadgrams, min_count),
+            caps_words: to_strings(&count.caps_words, min_count),
+        }
+    }
+
+    /// Every table with the name of the n-grams in it.
+    pub fn kinds(&self) -> [(&'static str, &BTreeMap<String, u32>); 6] {
+        [
+            ("letter", &self.letters),
+            ("bigram", &self.bigrams),
+            ("trigram", &self.trigrams),
+            ("skipgram", &self.skipgrams),
+            ("quadgram", &self.quadgrams),
+            ("caps_word", &self.caps_words),
+        ]
+    }
+
+    pub fn to_count(&self) -> AnyResult<CountOutcome> {
+        Ok(CountOutcome {
+            letter: from_strings(&self.letters)?,
+            bigrams: from_strings(&self.bigrams)?,
+            trigrams: from_strings(&self.trigrams)?,
+            skipgrams: from_strings(&self.skipgrams)?,
+            quadgrams: from_strings(&self.quadgrams)?,
+            caps_words: from_strings(&self.caps_words)?,
+        })
+    }
+}
+
+/// Quotes an n-gram for a csv field, escaping line breaks so every row stays
+/// on one line.
+fn csv_field(ngram: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in ngram.chars() {
+        match ch {
+            '"' => out.push_str("\"\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Reads one `"ngram",count` row.
+fn parse_csv_row(row: &str) -> Option<(String, u32)> {
+    let mut chars = row.strip_prefix('"')?.chars();
+    let mut ngram = String::new();
+    loop {
+        match chars.next()? {
+            '"' => match chars.next()? {
+                '"' => ngram.push('"'),
+                ',' => break,
+                _ => return None,
+            },
+            '\\' => ngram.push(match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                ch => ch,
+            }),
+            ch => ngram.push(ch),
+        }
+    }
+    Some((ngram, chars.as_str().trim().parse().ok()?))
+}
+
+impl CountOutcome {
+    /// Writes `{"version": 2, "letters": {"e": 120}, "bigrams": ..., "trigrams": ...,
+    /// "skipgrams": ...}`.
+    pub fn save_json(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        let json = CountsJson {
+            version: COUNTS_VERSION,
+            table: NgramTable::new(self, 0),
+        };
+        std::fs::write(path, serde_json::to_string_pretty(&json)?)?;
+        Ok(())
+    }
+
+    pub fn load_json(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let json: CountsJson = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if json.version != COUNTS_VERSION {
+            bail!(
+                "unsupported counts version {}, expected {COUNTS_VERSION}",
+                json.version
+            );
+        }
+        json.table.to_count()
+    }
+
+    /// Writes a `# keyboard-counts/2` line, a `kind,ngram,count` header and one
+    /// row per n-gram, where kind is `letter`, `bigram`, `trigram`, `skipgram`,
+    /// `quadgram` or `caps_word`. Line breaks and tabs in n-grams are written as `\n` and
+    /// `\t`, and backslashes as `\\`.
+    pub fn save_csv(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        let table = NgramTable::new(self, 0);
+        let mut out = format!("# keyboard-counts/{COUNTS_VERSION}\nkind,ngram,count\n");
+        for (kind, map) in table.kinds() {
+            for (ngram, freq) in map {
+                out.push_str(&format!("{kind},{},{freq}\n", csv_field(ngram)));
+            }
+        }
+        std::fs::write(path, out)?;
+        Ok(())
+    }
+
+    pub fn load_csv(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let data = std::fs::read_to_string(path)?;
+        let mut lines = data.lines();
+        let version = lines
+            .next()
+            .and_then(|line| line.strip_prefix("# keyboard-counts/"));
+        if version != Some(&COUNTS_VERSION.to_string()) {
+            bail!("not a keyboard-counts/{COUNTS_VERSION} csv");
+        }
+
+        let mut table = NgramTable::default();
+        for (i, row) in lines.enumerate().skip(1) {
+            let bad_row = || anyhow!("bad row {}: {row}", i + 2);
+            let (kind, rest) = row.split_once(',').ok_or_else(bad_row)?;
+            let (ngram, freq) = parse_csv_row(rest).ok_or_else(bad_row)?;
+            let map = match kind {
+                "letter" => &mut table.letters,
+                "bigram" => &mut table.bigrams,
+                "trigram" => &mut table.trigrams,
+                "skipgram" => &mut table.skipgrams,
+                "quadgram" => &mut table.quadgrams,
+                "caps_word" => &mut table.caps_words,
+                _ => return Err(bad_row()),
+            };
+            map.insert(ngram, freq);
+        }
+        table.to_count()
+    }
+}
+
+/// Writes `count` as csv if `path` ends in `.csv`, as json otherwise.
+pub fn export(count: &CountOutcome, path: impl AsRef<Path>) -> AnyResult<()> {
+    let path = path.as_ref();
+    match path.extension().is_some_and(|ext| ext == "csv") {
+        true => count.save_csv(path),
+        false => count.save_json(path),
+    }
+}
+
+/// Reads counts written by [`export`], possibly edited since.
+pub fn import(path: impl AsRef<Path>) -> AnyResult<CountOutcome> {
+    let path = path.as_ref();
+    match path.extension().is_some_and(|ext| ext == "csv") {
+        true => CountOutcome::load_csv(path),
+        false => CountOutcome::load_json(path),
+    }
 }
 
 const CACHE_PATH: &str = "cache.bin";
+/// Runs a cached file may go uncounted before it is dropped from the cache.
+const CACHE_KEEP_RUNS: u64 = 8;
 
 #[derive(Debug)]
 #[allow(unused)]
 pub enum CacheFailReason {
     FileSystem(std::io::Error),
     Deserialize(bincode::Error),
-    BadPath(PathBuf),
+    /// files were added or modified since they were cached, and recounted
+    Stale {
+        recounted: usize,
+        files: usize,
+    },
 }
 
-pub fn count(path: impl AsRef<Path>) -> (CountOutcome, Option<CacheFailReason>) {
-    let path = path.as_ref();
+/// Size and modification time of a counted file, used to tell whether it
+/// changed since it was cached without reading it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    path: PathBuf,
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// Counts of one file, reused while its contents hash the same.
+#[derive(Serialize, Deserialize)]
+struct FileCache {
+    stamp: FileStamp,
+    hash: u64,
+    depth: NgramDepth,
+    text: TextOptions,
+    /// the run that last counted the file
+    used: u64,
+    outcome: CountOutcome,
+}
+
+impl FileCache {
+    fn fits(&self, depth: NgramDepth, text: &TextOptions) -> bool {
+        self.depth >= depth && self.text == *text
+    }
+}
+
+/// Per-file counts of every corpus counted recently, keyed by path.
+#[derive(Default, Serialize, Deserialize)]
+struct Cache {
+    run: u64,
+    files: FxHashMap<PathBuf, FileCache>,
+}
+
+/// Which files under the corpus directory are counted, and how much each
+/// weighs.
+#[derive(Debug, Clone)]
+pub struct CorpusFilter {
+    extensions: Vec<String>,
+    ignore: GlobSet,
+    /// skip whatever `.gitignore` and `.ignore` files under the corpus skip
+    gitignore: bool,
+    /// scale every file to the same total instead of counting it by size
+    equal_files: bool,
+}
+
+impl CorpusFilter {
+    /// Counts files with one of `extensions`, or every file if it is empty.
+    /// Files and directories matching an `ignore` glob are skipped; a pattern
+    /// is tried on every path component and on the path inside the corpus, so
+    /// `target`, `*.min.js` and `docs/**` all work.
+    pub fn new(extensions: Vec<String>, ignore: &[String]) -> Result<Self, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in ignore {
+            builder.add(Glob::new(pattern)?);
+        }
+        Ok(Self {
+            extensions,
+            ignore: builder.build()?,
+            gitignore: true,
+            equal_files: false,
+        })
+    }
+
+    /// Whether `.gitignore` files are respected, as they are by default.
+    pub fn with_gitignore(mut self, gitignore: bool) -> Self {
+        self.gitignore = gitignore;
+        self
+    }
+
+    /// Whether every file weighs the same however long it is, rather than
+    /// each character weighing the same as by default.
+    pub fn with_equal_files(mut self, equal_files: bool) -> Self {
+        self.equal_files = equal_files;
+        self
+    }
+
+    /// The counts of every file added up, or mixed evenly if files weigh
+    /// the same.
+    fn combine<'a>(&self, files: impl IntoIterator<Item = &'a CountOutcome>) -> CountOutcome {
+        match self.equal_files {
+            true => {
+                let parts = files
+                    .into_iter()
+                    .map(|count| (count, 1.0))
+                    .collect::<Vec<_>>();
+                CountOutcome::mix(&parts)
+            }
+            false => {
+                let mut result = CountOutcome::default();
+                files.into_iter().for_each(|count| result.merge(count));
+                result
+            }
+        }
+    }
+
+    fn includes(&self, root: &Path, path: &Path) -> bool {
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let ignored = self.ignore.is_match(relative)
+            || relative.components().any(|part| match part {
+                Component::Normal(part) => self.ignore.is_match(part),
+                _ => false,
+            });
+        let extension = path.extension().and_then(OsStr::to_str);
+        !ignored
+            && (self.extensions.is_empty()
+                || extension.is_some_and(|ext| self.extensions.iter().any(|e| e == ext)))
+    }
+}
+
+/// Every file under `path` that `filter` includes, in file name order.
+/// Hidden files are included, and ignore files apply whether or not the
+/// corpus is a git repository.
+pub fn corpus_files(path: &Path, filter: &CorpusFilter) -> Vec<PathBuf> {
+    WalkBuilder::new(path)
+        .sort_by_file_name(OsStr::cmp)
+        .standard_filters(false)
+        .git_ignore(filter.gitignore)
+        .git_exclude(filter.gitignore)
+        .ignore(filter.gitignore)
+        .parents(filter.gitignore)
+        .require_git(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .map(|entry| entry.into_path())
+        .filter(|file| filter.includes(path, file))
+        .collect()
+}
+
+fn stamp(files: Vec<PathBuf>) -> Vec<FileStamp> {
+    files
+        .into_iter()
+        .map(|path| {
+            let meta = std::fs::metadata(&path).ok();
+            FileStamp {
+                len: meta.as_ref().map_or(0, |meta| meta.len()),
+                modified: meta.and_then(|meta| meta.modified().ok()),
+                path,
+            }
+        })
+        .collect()
+}
+
+fn content_hash(data: &str) -> u64 {
+    let mut hasher = FxHasher::default();
+    hasher.write(data.as_bytes());
+    hasher.finish()
+}
+
+/// Counts every file under `path` that `filter` includes up to `depth`,
+/// reusing the counts `cache.bin` holds for each file. A file is only read
+/// again if its size or modification time changed, and only recounted if its
+/// contents did, so editing a few files of a corpus recounts just those. The
+/// reason is returned if any file could not come from the cache.
+pub fn count(
+    path: impl AsRef<Path>,
+    depth: NgramDepth,
+    filter: &CorpusFilter,
+    text: &TextOptions,
+) -> (CountOutcome, Option<CacheFailReason>) {
+    let files = stamp(corpus_files(path.as_ref(), filter));
 
     let cache_raw = std::fs::read(CACHE_PATH);
     let cache = cache_raw
         .map_err(CacheFailReason::FileSystem)
         .and_then(|data| {
-            bincode::deserialize::<(PathBuf, CountOutcome)>(data.as_slice())
-                .map_err(CacheFailReason::Deserialize)
+            bincode::deserialize::<Cache>(data.as_slice()).map_err(CacheFailReason::Deserialize)
         });
-
-    let fail_reason = match cache {
-        Ok((cached_path, cached)) if cached_path == path => return (cached, None),
-        Ok((cached_path, _)) => Some(CacheFailReason::BadPath(cached_path)),
-        Err(err) => Some(err),
+    let (mut cache, mut fail_reason) = match cache {
+        Ok(cache) => (cache, None),
+        Err(err) => (Cache::default(), Some(err)),
     };
+    cache.run += 1;
+
+    let mut counted = vec![];
+    let mut recounted = 0;
+    for file in &files {
+        let cached = cache
+            .files
+            .get_mut(&file.path)
+            .filter(|cached| cached.fits(depth, text));
+        if let Some(cached) = cached.filter(|cached| cached.stamp == *file) {
+            cached.used = cache.run;
+            counted.push(&file.path);
+            continue;
+        }
 
-    let outcome = count_uncached(path);
-    let data = (path.to_path_buf(), outcome);
-    let ser = bincode::serialize(&data);
-    let outcome = data.1;
+        let Some(data) = read_file(&file.path) else {
+            continue;
+        };
+        let hash = content_hash(&data);
+        counted.push(&file.path);
+        match cache.files.get_mut(&file.path) {
+            // touched but not changed
+            Some(cached) if cached.hash == hash && cached.fits(depth, text) => {
+                cached.stamp = file.clone();
+                cached.used = cache.run;
+            }
+            _ => {
+                println!("counting {}...", file.path.display());
+                let mut outcome = CountOutcome::default();
+                count_text(&mut outcome, &file.path, &data, depth, text);
+                recounted += 1;
+                let cached = FileCache {
+                    stamp: file.clone(),
+                    hash,
+                    depth,
+                    text: text.clone(),
+                    used: cache.run,
+                    outcome,
+                };
+                cache.files.insert(file.path.clone(), cached);
+            }
+        }
+    }
+    let mut result = filter.combine(counted.iter().map(|&path| &cache.files[path].outcome));
+    result.truncate(depth);
 
-    if let Ok(ser) = ser {
+    let run = cache.run;
+    cache
+        .files
+        .retain(|_, cached| run - cached.used < CACHE_KEEP_RUNS);
+    if let Ok(ser) = bincode::serialize(&cache) {
         let _ = std::fs::write(CACHE_PATH, ser);
     }
-    (outcome, fail_reason)
+    if recounted > 0 {
+        fail_reason = fail_reason.or(Some(CacheFailReason::Stale {
+            recounted,
+            files: files.len(),
+        }));
+    }
+    (result, fail_reason)
+}
+
+/// Counts every file under `path` that `filter` includes in file name order,
+/// ignoring the cache.
+pub fn count_uncached(
+    path: impl AsRef<Path>,
+    depth: NgramDepth,
+    filter: &CorpusFilter,
+    text: &TextOptions,
+) -> CountOutcome {
+    let files = corpus_files(path.as_ref(), filter);
+    let counts = files
+        .iter()
+        .filter_map(|path| {
+            let string = read_file(path)?;
+            println!("counting {}...", path.display());
+            let mut result = CountOutcome::default();
+            count_text(&mut result, path, &string, depth, text);
+            Some(result)
+        })
+        .collect::<Vec<_>>();
+    filter.combine(&counts)
 }
 
-fn count_uncached(path: impl AsRef<Path>) -> CountOutcome {
+/// Counts all of the text `reader` yields, such as piped output, as a single
+/// file of no known language.
+pub fn count_reader(
+    mut reader: impl Read,
+    depth: NgramDepth,
+    text: &TextOptions,
+) -> AnyResult<CountOutcome> {
+    let mut bytes = vec![];
+    reader.read_to_end(&mut bytes)?;
     let mut result = CountOutcome::default();
+    count_text(
+        &mut result,
+        Path::new("-"),
+        &String::from_utf8_lossy(&bytes),
+        depth,
+        text,
+    );
+    Ok(result)
+}
 
-    for item in WalkDir::new(path) {
-        let Ok(entry) = item else {
-            continue;
-        };
+fn type_key(tracker: &mut NGramTracker, result: &mut CountOutcome, key: u8) {
+    match Symbol::from_ascii(key).filter(|key| key.in_alphabet()) {
+        Some(key) => tracker.apply(result, key),
+        None => tracker.clear(),
+    }
+}
+
+fn read_file(path: &Path) -> Option<String> {
+    let mut string = String::new();
+    File::open(path).ok()?.read_to_string(&mut string).ok()?;
+    Some(string)
+}
 
-        let file_type = entry.file_type();
-        if file_type.is_file() {
-            let path = entry.path();
-            let ext = path.extension();
+/// The text of `path` as it is counted: stripped or normalized for the mode
+/// and cut to the characters counted per file.
+fn prepare(path: &Path, string: &str, text: &TextOptions) -> String {
+    let strip = text.strip_comments || text.strip_strings;
+    let string = match text.mode {
+        CorpusMode::Code => {
+            let string = match Language::of(path).filter(|_| strip) {
+                Some(language) => {
+                    strip::strip(string, language, text.strip_comments, text.strip_strings)
+                }
+                None => string.to_string(),
+            };
+            reindent(&string, text.indentation, text.tab_width)
+        }
+        CorpusMode::Prose => prose::normalize(string, false),
+        CorpusMode::Markdown => prose::normalize(string, true),
+    };
+    match text.max_file_chars {
+        Some(max) => string.chars().take(max).collect(),
+        None => string,
+    }
+}
 
-            const IGNORE_COMPONENTS: [&str; 3] = ["target", "uiua", "uiua-main"];
+/// Length of the caps word starting at the front of `chars`, 0 if there is
+/// none. Like caps word in firmware, it goes on through digits and
+/// underscores and ends at anything else. It needs two capitals, and leaves
+/// out a capital a lowercase letter follows, so `HTTPResponse` turns on caps
+/// word for `HTTP` only.
+fn caps_run(chars: &[char]) -> usize {
+    let mut len = chars
+        .iter()
+        .position(|&ch| !(ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_'))
+        .unwrap_or(chars.len());
+    if chars.get(len).is_some_and(char::is_ascii_lowercase) {
+        len = chars[..len]
+            .iter()
+            .rposition(char::is_ascii_uppercase)
+            .unwrap_or(0);
+    }
+    let capitals = chars[..len]
+        .iter()
+        .filter(|ch| ch.is_ascii_uppercase())
+        .count();
+    match capitals >= 2 {
+        true => len,
+        false => 0,
+    }
+}
 
-            if path.components().any(|part| {
-                IGNORE_COMPONENTS.iter().any(|&component| match part {
-                    Component::Normal(part) => component == part,
-                    _ => false,
-                })
-            }) {
-                continue;
+/// `string` with the indentation of every line retyped as `indentation` says.
+fn reindent(string: &str, indentation: Indentation, tab_width: usize) -> String {
+    if indentation == Indentation::Literal {
+        return string.to_string();
+    }
+    let mut out = String::with_capacity(string.len());
+    for line in string.split_inclusive('\n') {
+        let code = line.trim_start_matches([' ', '\t']);
+        let indent = &line[..line.len() - code.len()];
+        match indentation {
+            Indentation::Tabs => {
+                let columns = indent.chars().fold(0, |columns, ch| match ch {
+                    '\t' => (columns / tab_width + 1) * tab_width,
+                    _ => columns + 1,
+                });
+                out.extend(std::iter::repeat_n('\t', columns / tab_width));
+                out.extend(std::iter::repeat_n(' ', columns % tab_width));
             }
+            Indentation::Collapse if !indent.is_empty() => out.push('\t'),
+            Indentation::Literal | Indentation::Collapse | Indentation::Ignore => {}
+        }
+        out.push_str(code);
+    }
+    out
+}
 
-            const INCLUDE_EXTENSIONS: [&str; 7] =
-                ["rs", "wgsl", "glsl", "vert", "comp", "frag", "py"];
-
-            if ext.is_some_and(|ext| INCLUDE_EXTENSIONS.iter().any(|&e| ext == e)) {
-                let Ok(mut file) = File::open(entry.path()) else {
-                    continue;
-                };
+/// Adds the n-grams typed for the contents of `path` to `result`.
+fn count_text(
+    result: &mut CountOutcome,
+    path: &Path,
+    string: &str,
+    depth: NgramDepth,
+    text: &TextOptions,
+) {
+    let string = prepare(path, string, text);
+    let chars = string.chars().filter(|&ch| ch != '\r').collect::<Vec<_>>();
+    let mut tracker = NGramTracker::new(depth);
+    let mut caps_until = 0;
+    for (i, &ch) in chars.iter().enumerate() {
+        let mut ch = ch;
+        if text.caps_word && ch.is_ascii_uppercase() {
+            if i >= caps_until {
+                let run = caps_run(&chars[i..]);
+                if run > 0 {
+                    caps_until = i + run;
+                    result.add_caps_word(text.shift.unshifted(Symbol::byte(ch as u8)));
+                }
+            }
+            if i < caps_until {
+                ch = text.shift.unshifted(Symbol::byte(ch as u8)).char();
+            }
+        }
+        match text.transliteration.get(ch) {
+            Some(keys) => keys
+                .bytes()
+                .for_each(|key| type_key(&mut tracker, result, key)),
+            None => match u8::try_from(ch) {
+                Ok(key) => type_key(&mut tracker, result, key),
+                Err(_) => tracker.clear(),
+            },
+        }
+    }
+}
 
-                println!("counting {}...", path.display());
+/// What the characters of a corpus became, to check the alphabet and the
+/// choice of files against.
+#[derive(Debug, Default)]
+pub struct CorpusStats {
+    pub files: usize,
+    /// characters typed, by the key that types them
+    pub typed: FxHashMap<Symbol, u64>,
+    /// characters outside the alphabet, which break the n-grams around them
+    pub rejected: FxHashMap<char, u64>,
+    /// typed characters of each file extension, `""` for none
+    pub extensions: BTreeMap<String, u64>,
+}
 
-                let mut string = String::new();
-                let Ok(_) = file.read_to_string(&mut string) else {
-                    continue;
+impl CorpusStats {
+    /// Adds the characters of every file under `path` that `filter` includes,
+    /// as [`count`] would read them.
+    pub fn add_corpus(
+        &mut self,
+        path: impl AsRef<Path>,
+        filter: &CorpusFilter,
+        text: &TextOptions,
+    ) {
+        for file in corpus_files(path.as_ref(), filter) {
+            let Some(string) = read_file(&file) else {
+                continue;
+            };
+            self.files += 1;
+            let mut typed = 0;
+            for ch in prepare(&file, &string, text)
+                .chars()
+                .filter(|&ch| ch != '\r')
+            {
+                let keys = match text.transliteration.get(ch) {
+                    Some(keys) => keys.bytes().collect(),
+                    None => u8::try_from(ch).map_or(vec![], |key| vec![key]),
                 };
-                let string = string;
-                let mut chars = string.chars();
-
-                let mut tracker = NGramTracker::default();
-                while let Some(ch) = chars.next() {
-                    if ch == '\r' {
-                        continue;
-                    }
-                    if ch == '\n' {
-                        let mut spaces = 0;
-                        while chars.next() == Some(' ') {
-                            spaces += 1;
-                        }
-                        while spaces > 0 {
-                            spaces -= 4;
-                        }
-                        tracker.apply(&mut result, NonZeroU8::new(b'\t').unwrap());
-                        for _ in 0..spaces {
-                            tracker.apply(&mut result, NonZeroU8::new(b' ').unwrap());
-                        }
-                    }
-
-                    match u8::try_from(ch)
-                        .ok()
-                        .and_then(NonZeroU8::new)
-                        .and_then(|x| in_alphabet(x.into()).then_some(x))
-                    {
-                        Some(ch) => tracker.apply(&mut result, ch),
-                        None => tracker.clear(),
-                    }
+                if keys.is_empty() || !keys.iter().all(|&key| in_alphabet(key)) {
+                    *self.rejected.entry(ch).or_default() += 1;
+                    continue;
+                }
+                for key in keys {
+                    *self.typed.entry(Symbol::byte(key)).or_default() += 1;
+                    typed += 1;
                 }
             }
+            let extension = file.extension().and_then(OsStr::to_str).unwrap_or_default();
+            *self.extensions.entry(extension.to_string()).or_default() += typed;
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "fn main() {\n\tlet s = \"a\\\\b\";\n    HTTP_OK.print(); // \"x\"\n}\n";
+
+    fn count_text_with(text: &TextOptions) -> CountOutcome {
+        count_reader(TEXT.as_bytes(), NgramDepth::Quadgrams, text).unwrap()
+    }
+
+    fn temp_path(file: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("keyboard-counts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join(file)
+    }
+
+    fn assert_same_counts(a: &CountOutcome, b: &CountOutcome) {
+        let (a, b) = (NgramTable::new(a, 0), NgramTable::new(b, 0));
+        assert_eq!(a.kinds(), b.kinds());
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let count = count_text_with(&TextOptions::default());
+        let path = temp_path("counts.json");
+        count.save_json(&path).unwrap();
+        let loaded = CountOutcome::load_json(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_same_counts(&count, &loaded);
+    }
 
-    result
+    #[test]
+    fn csv_round_trip() {
+        let count = count_text_with(&TextOptions::default());
+        let path = temp_path("counts.csv");
+        count.save_csv(&path).unwrap();
+        let loaded = CountOutcome::load_csv(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_same_counts(&count, &loaded);
+    }
+
+    #[test]
+    fn export_picks_the_format_by_extension() {
+        let count = count_text_with(&TextOptions::default());
+        for file in ["export.json", "export.csv"] {
+            let path = temp_path(file);
+            export(&count, &path).unwrap();
+            let json = std::fs::read_to_string(&path).unwrap().starts_with('{');
+            assert_eq!(json, file.ends_with(".json"));
+            let imported = import(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_same_counts(&count, &imported);
+        }
+    }
+
+    #[test]
+    fn zeroed_ngrams_are_dropped() {
+        let counts = BTreeMap::from([("ab".to_string(), 0), ("cd".to_string(), 3)]);
+        let table = from_strings::<2>(&counts).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(
+            table.get(&[Symbol::byte(b'c'), Symbol::byte(b'd')]),
+            Some(&3)
+        );
+    }
+
+    #[test]
+    fn indentation_modes() {
+        let code = "a\n  \tb\n      c\n";
+        assert_eq!(reindent(code, Indentation::Literal, 4), code);
+        assert_eq!(reindent(code, Indentation::Tabs, 4), "a\n\tb\n\t  c\n");
+        assert_eq!(reindent(code, Indentation::Tabs, 2), "a\n\t\tb\n\t\t\tc\n");
+        assert_eq!(reindent(code, Indentation::Collapse, 4), "a\n\tb\n\tc\n");
+        assert_eq!(reindent(code, Indentation::Ignore, 4), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn caps_runs() {
+        let run = |s: &str| caps_run(&s.chars().collect::<Vec<_>>());
+        assert_eq!(run("HTTP_OK.print"), 7);
+        assert_eq!(run("HTTPResponse"), 4);
+        assert_eq!(run("Hello"), 0);
+        assert_eq!(run("A1_"), 0);
+        assert_eq!(run("AB"), 2);
+    }
+
+    #[test]
+    fn caps_words_are_typed_unshifted() {
+        let shifted = count_text_with(&TextOptions::default());
+        let caps = count_text_with(&TextOptions {
+            caps_word: true,
+            ..TextOptions::default()
+        });
+        let key = Symbol::byte;
+        assert_eq!(caps.caps_words.get(&[key(b'h')]), Some(&1));
+        assert!(shifted.caps_words.is_empty());
+        assert_eq!(shifted.letter.get(&[key(b'H')]), Some(&1));
+        assert_eq!(caps.letter.get(&[key(b'H')]), None);
+        assert_eq!(caps.letter.get(&[key(b'h')]), Some(&1));
+    }
 }
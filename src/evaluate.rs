@@ -1,16 +1,28 @@
-use std::{collections::HashSet, num::NonZeroU8};
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    fmt,
+    num::NonZeroU8,
+    ops::{Add, Div, Mul, Sub},
+    sync::OnceLock,
+};
 
 use array_map::ArrayMap;
-use derive_more::{Add, AddAssign, Sum};
 use glam::{Vec2, Vec3};
-use macro_rules_attribute::macro_rules_derive;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
 
+pub use crate::core::{
+    bigram_cost, bigram_time, finger_axis, finger_load, same_finger_chord_cost, scissor_cost, sse,
+    weighted_sum, BasePositions, BigramEval, Evaluation, FingerEval, FingerKeys, FingerPositions,
+    FingerState, HandModel, KeyTables, LetterEval, TrigramEval,
+};
 use crate::{
-    counter::{Bigrams, CountOutcome, Letters, Trigrams},
-    iter::{OneIter, Range},
+    counter::{Bigrams, CountOutcome, Letters, Skipgrams, Trigrams},
+    iter::OneIter,
     keyboard::{Finger, Hand, HandFinger, Keyboard},
     layout::{Behavior, KeyLoc, Layout},
+    symbol::Symbol,
 };
 
 // terminology:
@@ -23,175 +35,197 @@ use crate::{
 // + finger bias
 // ? hand bias
 // - lateral movement
+// - per key effort
 // - home row jumping bigrams
 // - redirects
-// ? rolls
-// ? alternates
+// - rolls, inward and outward
+// - alternates
 // - redirects
 // - staccato tax
 
+/// One way of typing a character: the tapped key plus any held shift or layer
+/// key, or two base layer keys tapped together for a combo.
 #[derive(Debug, Clone, Copy)]
-pub struct Evaluation {
-    pub letter: LetterEval,
-    pub bigram: BigramEval,
-    pub trigram: TrigramEval,
+pub struct KeyCombo {
+    shift: Option<usize>,
+    layer: Option<usize>,
+    key: usize,
+    chord: Option<usize>,
+    tap_layer: u8,
+    /// index into the cost tables of the layout that made it, `None` for one
+    /// made by hand
+    id: Option<usize>,
 }
 
-impl std::ops::Div for Evaluation {
-    type Output = Self;
-
-    fn div(self, rhs: Self) -> Self::Output {
+impl KeyCombo {
+    pub fn new(shift: Option<usize>, layer: Option<usize>, key: usize, tap_layer: u8) -> Self {
         Self {
-            letter: self.letter / rhs.letter,
-            bigram: self.bigram / rhs.bigram,
-            trigram: self.trigram / rhs.trigram,
+            shift,
+            layer,
+            key,
+            chord: None,
+            tap_layer,
+            id: None,
         }
     }
-}
 
-impl std::ops::Mul<f32> for Evaluation {
-    type Output = Self;
-
-    fn mul(self, rhs: f32) -> Self::Output {
+    /// Tapping `keys` together on the base layer.
+    pub fn combo(keys: [usize; 2]) -> Self {
         Self {
-            letter: self.letter * rhs,
-            bigram: self.bigram * rhs,
-            trigram: self.trigram * rhs,
+            shift: None,
+            layer: None,
+            key: keys[0],
+            chord: Some(keys[1]),
+            tap_layer: 0,
+            id: None,
         }
     }
-}
-
-macro_rules! splat_eval {
-    ($name:ident : f32) => {
-        $name
-    };
-    ($name:ident : Vec3) => {
-        Vec3::splat($name)
-    };
-    ($name:ident : Vec2) => {
-        Vec2::splat($name)
-    };
-}
-
-macro_rules! multi_eval {
-    (
-        $(#[$meta:meta])*
-        $v:vis struct $name:ident {
-            $($v_:vis $field:ident : $ty:tt),*
-            $(,)?
-        }
-    ) =>{
-        impl std::ops::Mul<f32> for $name {
-            type Output = Self;
 
-            fn mul(self, _rhs: f32) -> Self::Output {
-                Self { $($field : self.$field * _rhs),* }
-            }
-        }
-
-        impl std::ops::Div for $name {
-            type Output = Self;
-
-            fn div(self, _rhs: Self) -> Self {
-                Self { $($field : self.$field / _rhs.$field),* }
-            }
-        }
-
-        impl std::ops::Div<f32> for $name {
-            type Output = Self;
-
-            fn div(self, _rhs: f32) -> Self {
-                Self { $($field : self.$field / _rhs),* }
-            }
-        }
-
-        #[allow(unused)]
-        impl $name {
-            pub const NAN: Self = Self::splat(f32::NAN);
-            pub const ZERO: Self = Self::splat(0.0);
+    pub fn shift(&self) -> Option<usize> {
+        self.shift
+    }
 
-            pub const fn splat(_x: f32) -> Self {
-                Self { $($field: splat_eval!(_x: $ty)),* }
-            }
+    pub fn layer(&self) -> Option<usize> {
+        self.layer
+    }
 
-            pub fn min(self, _other: Self) -> Self {
-                Self { $($field : self.$field.min(_other.$field)),* }
-            }
-        }
-    };
-}
+    pub fn key(&self) -> usize {
+        self.key
+    }
 
-#[macro_rules_derive(multi_eval!)]
-#[derive(Debug, Clone, Copy, Add, AddAssign, Sum)]
-pub struct LetterEval {
-    pub base: Vec3,
-    pub stretch: Vec2,
-}
+    /// The key tapped together with [`Self::key`] for a combo.
+    pub fn chord(&self) -> Option<usize> {
+        self.chord
+    }
 
-#[macro_rules_derive(multi_eval!)]
-#[derive(Debug, Clone, Copy, Add, AddAssign, Sum)]
-pub struct BigramEval {
-    pub sfb: f32,
-    pub movement: Vec2,
-    pub staccato: f32,
+    pub fn tap_layer(&self) -> u8 {
+        self.tap_layer
+    }
 }
 
-#[macro_rules_derive(multi_eval!)]
-#[derive(Debug, Clone, Copy, Add, AddAssign, Sum)]
-pub struct TrigramEval {
-    pub redirects: f32,
-    pub rolls: f32,
-    pub alternates: f32,
+/// How the costs of the ways of typing an n-gram reduce to the cost of the
+/// n-gram. Finger presses and [`evaluate_per_key`] always average.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComboChoice {
+    /// every way is as likely
+    #[default]
+    Average,
+    /// each metric at its cheapest way, for a typist who always picks it
+    Min,
+    /// an average where each way counts one over the keys it presses, so a
+    /// plain tap counts twice as much as a shifted one
+    Weighted,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct KeyCombo {
-    shift: Option<usize>,
-    layer: Option<usize>,
-    key: usize,
+/// Why a layout cannot be placed on a keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerateError {
+    /// a character of the alphabet with no way to type it
+    Untypeable(Symbol),
+    /// the layout has more keys a layer than the keyboard has keys
+    TooManyKeys { layout: usize, keyboard: usize },
 }
 
-impl KeyCombo {
-    pub fn new(shift: Option<usize>, layer: Option<usize>, key: usize) -> Self {
-        Self { shift, layer, key }
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Untypeable(key) => write!(f, "cannot type {key:?}"),
+            Self::TooManyKeys { layout, keyboard } => {
+                write!(
+                    f,
+                    "has {layout} keys a layer, more than the {keyboard} of the keyboard"
+                )
+            }
+        }
     }
 }
 
+impl std::error::Error for GenerateError {}
+
+/// A layout placed on a keyboard, with every way of typing each character.
 #[derive(Debug)]
 pub struct KeyboardLayout<'a> {
-    keys: FxHashMap<u8, Vec<KeyCombo>>,
-    base: ArrayMap<HandFinger, Vec2, 10>,
+    keys: FxHashMap<Symbol, Vec<KeyCombo>>,
+    base: BasePositions,
     kb: &'a Keyboard,
+    layout: Cow<'a, Layout>,
+    choice: ComboChoice,
+    /// [`combo_cost`] of every combo by id, worked out when first asked for
+    costs: Vec<OnceLock<LetterEval>>,
+    /// [`combo_pair_cost`] of every pair of combos, `costs.len()` per row
+    pair_costs: Vec<OnceLock<BigramEval>>,
 }
 
 impl<'a> KeyboardLayout<'a> {
-    pub fn generate(layout: &'a Layout, keyboard: &'a Keyboard) -> Result<Self, u8> {
+    /// Fails with the first character of the alphabet that cannot be typed, or
+    /// when the layout does not fit the keyboard.
+    pub fn generate(layout: &'a Layout, keyboard: &'a Keyboard) -> Result<Self, GenerateError> {
         Self::generate_with_usage(layout, keyboard, None, None)
     }
 
     pub fn generate_with_usage(
         layout: &'a Layout,
         keyboard: &'a Keyboard,
+        used_keys: Option<&mut HashSet<KeyLoc>>,
+        used_holds: Option<&mut HashSet<usize>>,
+    ) -> Result<Self, GenerateError> {
+        Self::generate_inner(
+            Cow::Borrowed(layout),
+            keyboard,
+            used_keys,
+            used_holds,
+            false,
+        )
+    }
+
+    /// Like [`Self::generate_with_usage`], but keeping its own copy of
+    /// `layout`, so that it can outlive a layout that is changed in place.
+    pub fn generate_owned(
+        layout: Layout,
+        keyboard: &'a Keyboard,
+        used_keys: Option<&mut HashSet<KeyLoc>>,
+        used_holds: Option<&mut HashSet<usize>>,
+    ) -> Result<Self, GenerateError> {
+        Self::generate_inner(Cow::Owned(layout), keyboard, used_keys, used_holds, false)
+    }
+
+    /// Like [`Self::generate`], but characters that cannot be typed are left
+    /// out instead of failing, for layouts that are still being filled in.
+    pub fn generate_partial(
+        layout: &'a Layout,
+        keyboard: &'a Keyboard,
+    ) -> Result<Self, GenerateError> {
+        Self::generate_inner(Cow::Borrowed(layout), keyboard, None, None, true)
+    }
+
+    fn generate_inner(
+        layout: Cow<'a, Layout>,
+        keyboard: &'a Keyboard,
         mut used_keys: Option<&mut HashSet<KeyLoc>>,
         mut used_holds: Option<&mut HashSet<usize>>,
-    ) -> Result<Self, u8> {
+        partial: bool,
+    ) -> Result<Self, GenerateError> {
+        if layout.layer_size() > keyboard.keys().len() {
+            return Err(GenerateError::TooManyKeys {
+                layout: layout.layer_size(),
+                keyboard: keyboard.keys().len(),
+            });
+        }
+
         let shift_keys = layout
             .find_on_base(|b| b == Behavior::Shift)
             .collect::<Vec<_>>();
 
         let mut keys = FxHashMap::default();
-        for &key in crate::ALPHABET {
-            let real_key = match key {
-                b'A'..=b'Z' => key.to_ascii_lowercase(),
-                b'?' => b'/',
-                _ => key,
-            };
+        for key in Symbol::alphabet() {
+            let real_key = keyboard.shift_table().unshifted(key);
             let do_shift = key != real_key;
 
             let shift_keys: OneIter<_> = do_shift.then(|| shift_keys.iter().copied()).into();
 
             let mut combos = vec![];
-            for final_key in layout.find_all_key(|layout_key| layout_key.get() == real_key) {
+            for final_key in layout.find_all_key(|layout_key| layout_key == real_key) {
                 let layer = final_key.layer();
                 let layer_keys: OneIter<_> = NonZeroU8::new(layer)
                     .map(|l| layout.find_on_base(move |b| b == Behavior::Layer(l)))
@@ -226,256 +260,724 @@ impl<'a> KeyboardLayout<'a> {
                             shift_key.map(|x| x.index()),
                             layer_key.map(|x| x.index()),
                             final_key.index(),
+                            final_key.layer(),
                         ));
                     }
                 }
             }
 
+            let size = layout.layer_size();
+            for combo in layout.combos() {
+                let [a, b] = combo.keys;
+                if combo.output == key && a != b && a < size && b < size {
+                    combos.push(KeyCombo::combo(combo.keys));
+                }
+            }
+
             if combos.is_empty() {
-                return Err(key);
+                if partial {
+                    continue;
+                }
+                return Err(GenerateError::Untypeable(key));
             }
             keys.insert(key, combos);
         }
 
-        let mut base = ArrayMap::new([Vec2::NAN; 10]);
-        for key in keyboard.keys() {
-            if key.is_base() {
-                base[key.finger()] = key.pos();
-            }
+        let mut count = 0;
+        for combo in keys.values_mut().flatten() {
+            combo.id = Some(count);
+            count += 1;
         }
-
         Ok(Self {
             keys,
-            base,
+            base: keyboard.base_positions(),
             kb: keyboard,
+            layout,
+            choice: keyboard.combo_choice(),
+            costs: std::iter::repeat_with(OnceLock::new).take(count).collect(),
+            pair_costs: std::iter::repeat_with(OnceLock::new)
+                .take(count * count)
+                .collect(),
         })
     }
 
-    pub fn key(&self, x: u8) -> &[KeyCombo] {
+    /// Reduces the ways of typing an n-gram by `choice` instead of the
+    /// keyboard's.
+    pub fn with_choice(mut self, choice: ComboChoice) -> Self {
+        self.choice = choice;
+        self
+    }
+
+    pub fn choice(&self) -> ComboChoice {
+        self.choice
+    }
+
+    /// [`combo_cost`] of a combo of this layout, worked out once.
+    pub fn cached_cost(&self, c: &KeyCombo) -> LetterEval {
+        match c.id {
+            Some(id) => *self.costs[id].get_or_init(|| combo_cost(self, c)),
+            None => combo_cost(self, c),
+        }
+    }
+
+    /// [`combo_pair_cost`] of two combos of this layout, worked out once.
+    pub fn cached_pair_cost(&self, [c1, c2]: [&KeyCombo; 2]) -> BigramEval {
+        match (c1.id, c2.id) {
+            (Some(a), Some(b)) => *self.pair_costs[a * self.costs.len() + b]
+                .get_or_init(|| combo_pair_cost(self, [c1, c2])),
+            _ => combo_pair_cost(self, [c1, c2]),
+        }
+    }
+
+    /// [`evaluate`] of this layout from `previous`, the evaluation of `old`,
+    /// when the two differ only in where keys are, as after a swap: the
+    /// n-grams of characters typed differently are costed on both and the
+    /// difference patched in, and the rest are not looked at. A change of
+    /// holds, which can touch every way of typing, evaluates from scratch.
+    /// Patched sums drift from a fresh [`evaluate`] in the last digits.
+    pub fn evaluate_delta(
+        &self,
+        old: &KeyboardLayout,
+        previous: &Evaluation,
+        count: &CountOutcome,
+    ) -> Evaluation {
+        let comparable = std::ptr::eq(self.kb, old.kb)
+            && self.choice == old.choice
+            && self.layout.base_hold() == old.layout.base_hold();
+        if !comparable {
+            return evaluate(self, count);
+        }
+        let changed = self.changed_symbols(old);
+        let both = [old, self];
+
+        let mut eval = *previous;
+        eval.letter += ngram_delta(&count.letter, &changed, both, one_letter);
+        eval.bigram += ngram_delta(&count.bigrams, &changed, both, one_bigram);
+        let sfs = ngram_delta(&count.skipgrams, &changed, both, |info, s| {
+            let (cost, ways) = one_bigram(info, s);
+            (cost.sfb, ways)
+        });
+        eval.bigram.sfs = previous.bigram.sfs + sfs;
+        eval.trigram += ngram_delta(&count.trigrams, &changed, both, one_trigram);
+        eval.finger += ngram_delta(&count.letter, &changed, both, one_letter_fingers);
+        eval.finger.load = finger_load(&eval.finger, self.kb.finger_targets());
+        eval
+    }
+
+    /// The characters with other ways of typing them than on `old`.
+    fn changed_symbols(&self, old: &KeyboardLayout) -> FxHashSet<Symbol> {
+        let alike = |a: &[KeyCombo], b: &[KeyCombo]| {
+            let way = |c: &KeyCombo| (c.shift, c.layer, c.key, c.chord, c.tap_layer);
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| way(a) == way(b))
+        };
+        self.keys
+            .keys()
+            .chain(old.keys.keys())
+            .copied()
+            .filter(
+                |&symbol| match (self.try_key(symbol), old.try_key(symbol)) {
+                    (Some(new), Some(old)) => !alike(new, old),
+                    _ => true,
+                },
+            )
+            .collect()
+    }
+
+    /// The cost of one way of typing an n-gram with `combos` and its weight.
+    fn way<T: Mul<f32, Output = T>>(&self, cost: T, combos: &[&KeyCombo]) -> (T, f32) {
+        match self.choice {
+            ComboChoice::Average | ComboChoice::Min => (cost, 1.0),
+            ComboChoice::Weighted => {
+                let weight = combos
+                    .iter()
+                    .map(|&c| 1.0 / combo_keys(c).count() as f32)
+                    .product::<f32>();
+                (cost * weight, weight)
+            }
+        }
+    }
+
+    /// Adds a way to the ways so far, `min` taking each metric of the cheaper.
+    fn reduce<T: Add<Output = T>>(&self, x: (T, f32), y: (T, f32), min: fn(T, T) -> T) -> (T, f32) {
+        match self.choice {
+            ComboChoice::Min if x.1 > 0.0 => (min(x.0, y.0), 1.0),
+            ComboChoice::Min => y,
+            ComboChoice::Average | ComboChoice::Weighted => avg_reduce(x, y),
+        }
+    }
+
+    pub fn key(&self, x: Symbol) -> &[KeyCombo] {
         &self.keys[&x]
     }
+
+    pub fn try_key(&self, x: Symbol) -> Option<&[KeyCombo]> {
+        self.keys.get(&x).map(Vec::as_slice)
+    }
+
+    pub fn keyboard(&self) -> &'a Keyboard {
+        self.kb
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
 }
 
+/// Letter, bigram and trigram metrics of `info` over the counted corpus. Every caps
+/// word costs `letter` a tap of a shift key.
 pub fn evaluate(info: &KeyboardLayout, count: &CountOutcome) -> Evaluation {
+    let mut bigram = eval_bigrams(info, &count.bigrams);
+    bigram.sfs = eval_skipgrams(info, &count.skipgrams);
+    let caps_words = count
+        .caps_words
+        .values()
+        .map(|&freq| freq as f32)
+        .sum::<f32>();
+    let toggle = caps_word_toggle(info);
+    let caps_word = toggle.map_or(LetterEval::ZERO, |(_, cost)| cost * caps_words);
+    let mut finger = eval_fingers(info, &count.letter);
+    if let Some((key, _)) = toggle {
+        *finger.presses_mut(info.kb.key(key).finger()) += caps_words;
+    }
+    finger.load = finger_load(&finger, info.kb.finger_targets());
     Evaluation {
-        letter: eval_letters(info, &count.letter),
-        bigram: eval_bigrams(info, &count.bigrams),
+        letter: eval_letters(info, &count.letter) + caps_word,
+        bigram,
+        trigram: eval_trigrams(info, &count.trigrams),
+        finger,
+    }
+}
+
+/// [`evaluate`] split by physical key: every n-gram counts towards the key
+/// tapped for its last character, each way of typing it alike, and every
+/// press towards the finger of the key pressed. The keys add up to the whole
+/// but for `finger.load`, which is of the whole layout and left at 0, and
+/// the presses of a finger pressing two keys at once, counted twice.
+pub fn evaluate_per_key(info: &KeyboardLayout, count: &CountOutcome) -> Vec<Evaluation> {
+    let mut keys = vec![Evaluation::default(); info.kb.keys().len()];
+    // each way of typing an n-gram takes an even share of its frequency
+    let share = |ngram: &[Symbol], freq: u32| {
+        freq as f32 / ngram.iter().map(|&k| info.key(k).len()).product::<usize>() as f32
+    };
+    for (&letter, &freq) in &count.letter {
+        let weight = share(&letter, freq);
+        one_letter_any(
+            info,
+            (),
+            |info, [c]| {
+                keys[c.key].letter += info.cached_cost(c) * weight;
+                for index in combo_keys(c) {
+                    *keys[index].finger.presses_mut(info.kb.key(index).finger()) += weight;
+                }
+            },
+            |_, _| (),
+            letter,
+        );
+    }
+    if let Some((key, cost)) = caps_word_toggle(info) {
+        let caps_words = count
+            .caps_words
+            .values()
+            .map(|&freq| freq as f32)
+            .sum::<f32>();
+        keys[key].letter += cost * caps_words;
+        *keys[key].finger.presses_mut(info.kb.key(key).finger()) += caps_words;
+    }
+    for (&bigram, &freq) in &count.bigrams {
+        let weight = share(&bigram, freq);
+        let add = |info: &KeyboardLayout, c: [&KeyCombo; 2]| {
+            keys[c[1].key].bigram += info.cached_pair_cost(c) * weight;
+        };
+        one_bigram_any(info, (), add, |_, _| (), bigram);
+    }
+    for (&skipgram, &freq) in &count.skipgrams {
+        let weight = share(&skipgram, freq);
+        let add = |info: &KeyboardLayout, c: [&KeyCombo; 2]| {
+            keys[c[1].key].bigram.sfs += info.cached_pair_cost(c).sfb * weight;
+        };
+        one_bigram_any(info, (), add, |_, _| (), skipgram);
+    }
+    for (&trigram, &freq) in &count.trigrams {
+        let weight = share(&trigram, freq);
+        let add = |info: &KeyboardLayout, c: [&KeyCombo; 3]| {
+            keys[c[2].key].trigram += combo_triple_cost(info, c) * weight;
+        };
+        one_trigram_any(info, (), add, |_, _| (), trigram);
+    }
+    keys
+}
+
+/// Milliseconds per keystroke in `bigram.time` of `eval`, the raw evaluation
+/// of `count`.
+pub fn ms_per_keystroke(eval: &Evaluation, count: &CountOutcome) -> f32 {
+    eval.bigram.time / count.bigrams.values().sum::<u32>() as f32
+}
+
+/// The shift key tapped to toggle caps word and what the tap costs, the
+/// cheapest of them by `letter.base`. `None` without a shift key.
+pub fn caps_word_toggle(info: &KeyboardLayout) -> Option<(usize, LetterEval)> {
+    info.layout
+        .find_on_base(|behavior| behavior == Behavior::Shift)
+        .map(|key| {
+            let key = key.index();
+            let tap = KeyCombo::new(None, None, key, 0);
+            let cost =
+                letter_cost(info, &convert_fingers(info, &tap)) + calibration_cost(info, &tap);
+            (key, cost)
+        })
+        .min_by(|(_, a), (_, b)| a.base.element_sum().total_cmp(&b.base.element_sum()))
+}
+
+/// The share of [`evaluate`] that falls on the fingers of `hand`: the metrics
+/// of each finger (`letter.base`, `letter.stretch`, `letter.tap_hold`,
+/// `letter.lateral`, `letter.effort`, `bigram.sfb`, `bigram.sfs`, `bigram.movement`,
+/// `bigram.coupling`, `bigram.scissors` and the presses of `finger`) sum over
+/// both hands to the whole, and the rest are zero.
+pub fn evaluate_hand(info: &KeyboardLayout, count: &CountOutcome, hand: Hand) -> Evaluation {
+    let on_hand = |index: usize| info.kb.key(index).finger().hand == hand;
+    let hand_fingers = |combo: &KeyCombo| {
+        let mut fingers = convert_fingers(info, combo);
+        for other in Hand::ALL.into_iter().filter(|&other| other != hand) {
+            for finger in Finger::ALL {
+                fingers[HandFinger::new(other, finger)] = FingerState::NONE;
+            }
+        }
+        fingers
+    };
+
+    let letter = weighted_sum(count.letter.iter().map(|(&l, &f)| (l, f)), |letter| {
+        one_letter_any(
+            info,
+            (LetterEval::ZERO, 0.0),
+            |info, [c]| {
+                let offset = combo_keys(c)
+                    .filter(|&i| on_hand(i))
+                    .map(|i| info.kb.offset(i))
+                    .sum::<f32>();
+                let mut cost = letter_cost(info, &hand_fingers(c))
+                    + lateral_cost(info, c, on_hand)
+                    + effort_cost(info, c, on_hand);
+                cost.base.z += offset;
+                // a same finger chord and a tap-hold key both belong to the tapped key
+                if on_hand(c.key) {
+                    cost = cost + chord_cost(info, c) + tap_hold_cost(info, c);
+                }
+                info.way(cost, &[c])
+            },
+            |x, y| info.reduce(x, y, LetterEval::min),
+            letter,
+        )
+    });
+    let hand_bigram = |bigram| {
+        one_bigram_any(
+            info,
+            (BigramEval::ZERO, 0.0),
+            |_, [c1, c2]| {
+                let (h1, h2) = (hand_fingers(c1), hand_fingers(c2));
+                let cost = BigramEval {
+                    scissors: scissor_cost(info.kb.hand_model(), &info.base, &h1, &h2),
+                    ..bigram_cost(
+                        info.kb.hand_model(),
+                        &h1,
+                        &h2,
+                        0.0,
+                        c1.tap_layer != c2.tap_layer,
+                    )
+                };
+                info.way(cost, &[c1, c2])
+            },
+            |x, y| info.reduce(x, y, BigramEval::min),
+            bigram,
+        )
+    };
+    let mut bigram = weighted_sum(count.bigrams.iter().map(|(&b, &f)| (b, f)), hand_bigram);
+    bigram.sfs = weighted_sum(count.skipgrams.iter().map(|(&s, &f)| (s, f)), |s| {
+        let (cost, ways) = hand_bigram(s);
+        (cost.sfb, ways)
+    });
+    let caps_words = count
+        .caps_words
+        .values()
+        .map(|&freq| freq as f32)
+        .sum::<f32>();
+    let toggle = caps_word_toggle(info).filter(|&(key, _)| on_hand(key));
+    let caps_word = toggle.map_or(LetterEval::ZERO, |(_, cost)| cost * caps_words);
+    let mut finger = eval_fingers(info, &count.letter);
+    if let Some((key, _)) = toggle {
+        *finger.presses_mut(info.kb.key(key).finger()) += caps_words;
+    }
+    for other in Hand::ALL.into_iter().filter(|&other| other != hand) {
+        for each in Finger::ALL {
+            *finger.presses_mut(HandFinger::new(other, each)) = 0.0;
+        }
+    }
+    Evaluation {
+        letter: letter + caps_word,
+        bigram,
         trigram: TrigramEval::ZERO,
+        finger,
     }
 }
 
 pub fn eval_letters(info: &KeyboardLayout, letters: &Letters) -> LetterEval {
-    letters
-        .iter()
-        .map(|(&letter, &freq)| avg_apply(one_letter(info, letter)) * freq as f32)
-        .sum()
+    sum_ngrams(letters, |l| one_letter(info, l))
+}
+
+/// Presses of each finger over `letters`, without [`finger_load`].
+pub fn eval_fingers(info: &KeyboardLayout, letters: &Letters) -> FingerEval {
+    sum_ngrams(letters, |l| one_letter_fingers(info, l))
+}
+
+/// The fingers pressing or holding a key for `letter`, summed over the ways
+/// of typing it.
+pub fn one_letter_fingers(info: &KeyboardLayout, letter: [Symbol; 1]) -> (FingerEval, f32) {
+    one_letter_any(
+        info,
+        (FingerEval::ZERO, 0.0),
+        |info, [c]| {
+            let mut fingers = FingerEval::ZERO;
+            for (finger, pressed) in convert_fingers(info, c).iter() {
+                if pressed.is_pressing() {
+                    *fingers.presses_mut(finger) += 1.0;
+                }
+            }
+            (fingers, 1.0)
+        },
+        avg_reduce,
+        letter,
+    )
 }
 
 pub fn eval_bigrams(info: &KeyboardLayout, bigrams: &Bigrams) -> BigramEval {
-    bigrams
-        .iter()
-        .map(|(&bigram, &freq)| avg_apply(one_bigram(info, bigram)) * freq as f32)
-        .sum()
+    sum_ngrams(bigrams, |b| one_bigram(info, b))
+}
+
+/// Skipgrams typed with one finger, counted like `sfb` of a bigram.
+pub fn eval_skipgrams(info: &KeyboardLayout, skipgrams: &Skipgrams) -> f32 {
+    sum_ngrams(skipgrams, |s| {
+        let (cost, ways) = one_bigram(info, s);
+        (cost.sfb, ways)
+    })
 }
 
 pub fn eval_trigrams(info: &KeyboardLayout, trigrams: &Trigrams) -> TrigramEval {
-    trigrams
-        .iter()
-        .map(|(&trigram, &freq)| one_trigram(info, trigram) * freq as f32)
-        .sum()
+    sum_ngrams(trigrams, |t| one_trigram(info, t))
 }
 
-fn avg_reduce<T: std::ops::Add<Output = T>>(x: (T, f32), y: (T, f32)) -> (T, f32) {
-    (x.0 + y.0, x.1 + y.1)
+/// Tables at least this long are summed across threads with the `parallel`
+/// feature.
+pub const PARALLEL_MIN_NGRAMS: usize = 4096;
+
+/// N-grams each thread sums before the parts are added up in order, which
+/// keeps the total the same from run to run whatever the threads do.
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK: usize = 1024;
+
+/// [`weighted_sum`] over a table of counted n-grams.
+fn sum_ngrams<const N: usize, T>(
+    ngrams: &FxHashMap<[Symbol; N], u32>,
+    cost: impl Fn([Symbol; N]) -> (T, f32) + Sync,
+) -> T
+where
+    T: Div<f32, Output = T> + Mul<f32, Output = T> + std::iter::Sum + Send,
+{
+    #[cfg(feature = "parallel")]
+    if ngrams.len() >= PARALLEL_MIN_NGRAMS {
+        use rayon::prelude::*;
+
+        let ngrams = ngrams.iter().map(|(&n, &f)| (n, f)).collect::<Vec<_>>();
+        let parts = ngrams
+            .par_chunks(PARALLEL_CHUNK)
+            .map(|chunk| weighted_sum(chunk.iter().copied(), &cost))
+            .collect::<Vec<_>>();
+        return parts.into_iter().sum();
+    }
+    weighted_sum(ngrams.iter().map(|(&n, &f)| (n, f)), cost)
 }
 
-fn avg_apply<T: std::ops::Div<f32>>(x: (T, f32)) -> T::Output {
-    x.0 / x.1
+/// What the n-grams with a `changed` character add on the second layout
+/// over the first.
+fn ngram_delta<const N: usize, T>(
+    ngrams: &FxHashMap<[Symbol; N], u32>,
+    changed: &FxHashSet<Symbol>,
+    [old, new]: [&KeyboardLayout; 2],
+    cost: impl Fn(&KeyboardLayout, [Symbol; N]) -> (T, f32),
+) -> T
+where
+    T: Div<f32, Output = T> + Mul<f32, Output = T> + Sub<Output = T> + std::iter::Sum,
+{
+    let touched = ngrams
+        .iter()
+        .filter(|(ngram, _)| ngram.iter().any(|symbol| changed.contains(symbol)))
+        .map(|(&ngram, &freq)| (ngram, freq))
+        .collect::<Vec<_>>();
+    weighted_sum(touched.iter().copied(), |n| cost(new, n))
+        - weighted_sum(touched.iter().copied(), |n| cost(old, n))
 }
 
-fn convert_fingers(
-    info: &KeyboardLayout,
-    combo: &KeyCombo,
-) -> ArrayMap<HandFinger, Option<(Vec2, bool)>, 10> {
-    let mut position = ArrayMap::new([None; 10]);
+fn avg_reduce<T: Add<Output = T>>(x: (T, f32), y: (T, f32)) -> (T, f32) {
+    (x.0 + y.0, x.1 + y.1)
+}
+
+/// Where each finger taps and holds for `combo`.
+pub fn convert_fingers(info: &KeyboardLayout, combo: &KeyCombo) -> FingerPositions {
+    let mut position = ArrayMap::new([FingerState::NONE; 10]);
     for (index, hold) in std::iter::once((combo.key, false))
+        .chain(combo.chord.map(|x| (x, false)))
         .chain(combo.shift.map(|x| (x, true)))
         .chain(combo.layer.map(|x| (x, true)))
     {
         let key = info.kb.key(index);
-        position[key.finger()] = Some((key.pos(), hold));
+        let press = position[key.finger()].press_mut(hold);
+        // one finger tapping both keys of a chord, or holding two keys, sits
+        // between them
+        *press = Some(press.map_or(key.pos(), |pos| (pos + key.pos()) / 2.0));
     }
     position
 }
 
-pub fn finger_strength(finger: Finger) -> f32 {
-    match finger {
-        Finger::Thumb => 0.8,
-        Finger::Index => 1.0,
-        Finger::Middle => 1.0,
-        Finger::Ring => 0.4,
-        Finger::Pinky => 0.3,
+/// Extra effort of one finger pressing two keys of a combo at once, both
+/// keys of a chord or a tap and a held shift or layer key, charged to `base`.
+/// Combos on two fingers are costed by the fingers they press, with the gap
+/// between them as stretch, like any other letter.
+pub fn chord_cost(info: &KeyboardLayout, combo: &KeyCombo) -> LetterEval {
+    let holds = combo.shift.into_iter().chain(combo.layer);
+    let pairs = combo
+        .chord
+        .map(|chord| (combo.key, chord))
+        .into_iter()
+        .chain(
+            std::iter::once(combo.key)
+                .chain(combo.chord)
+                .flat_map(|tap| holds.clone().map(move |hold| (tap, hold))),
+        );
+    let mut base = Vec2::ZERO;
+    for (a, b) in pairs {
+        let (a, b) = (info.kb.key(a), info.kb.key(b));
+        if a.finger() == b.finger() {
+            base +=
+                same_finger_chord_cost(info.kb.hand_model(), a.finger().finger, a.pos(), b.pos());
+        }
+    }
+    LetterEval {
+        base: base.extend(0.0),
+        ..LetterEval::ZERO
     }
 }
 
-pub fn gap_costs(a: Finger, b: Finger) -> f32 {
-    match (a, b) {
-        (Finger::Thumb, _) => 0.0,
-        (Finger::Index, Finger::Middle) => 0.6,
-        (Finger::Index, Finger::Ring) => 0.5,
-        (Finger::Index, Finger::Pinky) => 0.4,
-        (Finger::Middle, Finger::Ring) => 1.0,
-        (Finger::Middle, Finger::Pinky) => 0.9,
-        (Finger::Ring, Finger::Pinky) => 1.5,
+/// Every key pressed or held for `combo`.
+fn combo_keys(combo: &KeyCombo) -> impl Iterator<Item = usize> {
+    std::iter::once(combo.key)
+        .chain(combo.chord)
+        .chain(combo.shift)
+        .chain(combo.layer)
+}
 
-        (_, Finger::Thumb)
-        | (Finger::Index, Finger::Index)
-        | (Finger::Middle, Finger::Index)
-        | (Finger::Ring, Finger::Index)
-        | (Finger::Pinky, Finger::Index)
-        | (Finger::Middle, Finger::Middle)
-        | (Finger::Ring, Finger::Middle)
-        | (Finger::Pinky, Finger::Middle)
-        | (Finger::Ring, Finger::Ring)
-        | (Finger::Pinky, Finger::Ring)
-        | (Finger::Pinky, Finger::Pinky) => unreachable!(),
+/// The sideways reach of every key pressed or held for `combo`, see
+/// [`Key::lateral`](crate::keyboard::Key::lateral).
+pub fn lateral_cost(
+    info: &KeyboardLayout,
+    combo: &KeyCombo,
+    on_hand: impl Fn(usize) -> bool,
+) -> LetterEval {
+    LetterEval {
+        lateral: combo_keys(combo)
+            .filter(|&i| on_hand(i))
+            .map(|i| info.kb.key(i).lateral())
+            .sum(),
+        ..LetterEval::ZERO
     }
 }
 
-pub fn hold_multiplier(hold: bool) -> f32 {
-    match hold {
-        true => 1.8,
-        false => 1.0,
+/// The flat effort of every key pressed or held for `combo`, see
+/// [`Key::effort`](crate::keyboard::Key::effort).
+pub fn effort_cost(
+    info: &KeyboardLayout,
+    combo: &KeyCombo,
+    on_hand: impl Fn(usize) -> bool,
+) -> LetterEval {
+    LetterEval {
+        effort: combo_keys(combo)
+            .filter(|&i| on_hand(i))
+            .map(|i| info.kb.key(i).effort())
+            .sum(),
+        ..LetterEval::ZERO
     }
 }
 
-pub fn finger_axis(finger: HandFinger) -> Vec2 {
-    let flip = finger.hand != Hand::Right;
-    let vec = match finger.finger {
-        Finger::Thumb => Vec2::X,
-        Finger::Index => Vec2::new(-1.0, 4.0).normalize(),
-        Finger::Middle | Finger::Ring | Finger::Pinky => Vec2::Y,
-    };
-    match flip {
-        true => Vec2::new(vec.x, vec.y),
-        false => Vec2::new(-vec.x, vec.y),
+/// Learned offsets of every key pressed or held for `combo`, charged to `base.z`.
+pub fn calibration_cost(info: &KeyboardLayout, combo: &KeyCombo) -> LetterEval {
+    let offset = combo_keys(combo).map(|index| info.kb.offset(index)).sum();
+    LetterEval {
+        base: Vec3::new(0.0, 0.0, offset),
+        ..LetterEval::ZERO
+    }
+}
+
+/// Whether `combo` is a plain tap of a key that also holds.
+fn taps_hold_key(info: &KeyboardLayout, combo: &KeyCombo) -> bool {
+    combo.tap_layer == 0 && combo.chord.is_none() && info.layout.base_hold()[combo.key].is_some()
+}
+
+/// 1 for tapping a key that also holds, charged to `tap_hold`. Every tap of
+/// such a key waits on the release to tell it from a hold, and is one more
+/// chance to misfire, so frequent letters are better kept off them.
+pub fn tap_hold_cost(info: &KeyboardLayout, combo: &KeyCombo) -> LetterEval {
+    LetterEval {
+        tap_hold: f32::from(u8::from(taps_hold_key(info, combo))),
+        ..LetterEval::ZERO
     }
 }
 
-fn alignment_to_multiplier(hand: HandFinger, delta: Vec2) -> f32 {
-    let axis = finger_axis(hand);
-    let align = delta.normalize_or_zero().dot(axis);
+/// 1 if `c1` taps a key that also holds, and a key of `c2` is pressed nearby
+/// by another finger of the same hand: rolled quickly, that press lands
+/// before the release and fires the hold instead of the tap.
+pub fn misfire(info: &KeyboardLayout, c1: &KeyCombo, c2: &KeyCombo) -> f32 {
+    if !taps_hold_key(info, c1) {
+        return 0.0;
+    }
+    let from = info.kb.key(c1.key);
+    let rolled = combo_keys(c2)
+        .filter(|&index| index != c1.key)
+        .map(|index| info.kb.key(index))
+        .any(|to| {
+            to.finger().hand == from.finger().hand
+                && to.finger() != from.finger()
+                && to.pos().distance(from.pos()) < info.kb.misfire_reach()
+        });
+    f32::from(u8::from(rolled))
+}
+
+/// 1 if `c1` taps a key that also holds and `c2` holds that same key, like a
+/// space followed by a symbol on the layer space holds: pressed again right
+/// away the key repeats its tap, so the hold has to wait out the quick tap
+/// term.
+pub fn retap(info: &KeyboardLayout, c1: &KeyCombo, c2: &KeyCombo) -> f32 {
+    let held = [c2.shift, c2.layer].contains(&Some(c1.key));
+    f32::from(u8::from(held && taps_hold_key(info, c1)))
+}
+
+/// The key each finger taps and holds for `combo`, `None` when one finger
+/// taps or holds two of its keys.
+pub fn finger_keys(info: &KeyboardLayout, combo: &KeyCombo) -> Option<FingerKeys> {
+    let mut keys = ArrayMap::new([FingerState::NONE; 10]);
+    for (index, hold) in std::iter::once((combo.key, false))
+        .chain(combo.chord.map(|x| (x, false)))
+        .chain(combo.shift.map(|x| (x, true)))
+        .chain(combo.layer.map(|x| (x, true)))
+    {
+        let press = keys[info.kb.key(index).finger()].press_mut(hold);
+        if press.is_some() {
+            return None;
+        }
+        *press = Some(index);
+    }
+    Some(keys)
+}
 
-    1.0 + (-align) * 0.5
+/// [`letter_cost`] of pressing `combo`, from the keyboard's tables unless
+/// one finger taps or holds two of its keys.
+pub fn combo_letter_cost(info: &KeyboardLayout, combo: &KeyCombo) -> LetterEval {
+    match finger_keys(info, combo) {
+        Some(keys) => info.kb.tables().letter_cost(&keys),
+        None => letter_cost(info, &convert_fingers(info, combo)),
+    }
 }
 
-pub fn one_letter(info: &KeyboardLayout, letter: [u8; 1]) -> (LetterEval, f32) {
+pub fn letter_cost(info: &KeyboardLayout, h: &FingerPositions) -> LetterEval {
+    crate::core::letter_cost(info.kb.hand_model(), &info.base, h)
+}
+
+pub fn one_letter(info: &KeyboardLayout, letter: [Symbol; 1]) -> (LetterEval, f32) {
     one_letter_any(
         info,
         (LetterEval::ZERO, 0.0),
-        |info, [c]| {
-            let h = convert_fingers(info, c);
-            let bases = info.base;
-            let mut base = Vec3::ZERO;
-
-            for ((h, a), b) in h.iter().zip(bases.values()) {
-                let hold = a.map(|x| x.1).unwrap_or(false);
-                let a = a.map(|x| x.0);
-                if let Some(a) = a {
-                    let strength = finger_strength(h.finger);
-                    let delta = (a - b).abs();
-                    let align = alignment_to_multiplier(h, delta);
-
-                    let delta = Vec3::new(delta.x, delta.y, Z)
-                        * strength.recip()
-                        * hold_multiplier(hold)
-                        * align;
-                    const Z: f32 = 2.0;
-                    base += delta;
-                }
-            }
-
-            let mut stretch = Vec2::ZERO;
-            for hand in Hand::ALL {
-                let mut last_finger = None;
-                for finger in Finger::ALL {
-                    let hf = HandFinger::new(hand, finger);
-                    let Some((pos, hold)) = h[hf] else {
-                        continue;
-                    };
-
-                    if let Some((last_finger, last_pos, last_hold)) = last_finger {
-                        let cost = gap_costs(last_finger, finger);
-                        let base_pos = bases[hf];
-                        let last_base = bases[HandFinger::new(hand, last_finger)];
-
-                        let base_dist = base_pos - last_base;
-                        let now_dist: Vec2 = pos - last_pos;
-
-                        let ratio = (now_dist / base_dist).abs()
-                            * cost
-                            * hold_multiplier(hold)
-                            * hold_multiplier(last_hold);
-
-                        stretch += ratio;
-                    } else {
-                        last_finger = Some((finger, pos, hold));
-                    }
-                }
-            }
-
-            (LetterEval { base, stretch }, 1.0)
-        },
-        avg_reduce,
+        |info, [c]| info.way(info.cached_cost(c), &[c]),
+        |x, y| info.reduce(x, y, LetterEval::min),
         letter,
     )
 }
 
-pub fn one_bigram(info: &KeyboardLayout, bigram: [u8; 2]) -> (BigramEval, f32) {
+/// Every letter metric of typing one way of a letter.
+pub fn combo_cost(info: &KeyboardLayout, c: &KeyCombo) -> LetterEval {
+    combo_letter_cost(info, c)
+        + calibration_cost(info, c)
+        + chord_cost(info, c)
+        + tap_hold_cost(info, c)
+        + lateral_cost(info, c, |_| true)
+        + effort_cost(info, c, |_| true)
+}
+
+pub fn one_bigram(info: &KeyboardLayout, bigram: [Symbol; 2]) -> (BigramEval, f32) {
     one_bigram_any(
         info,
         (BigramEval::ZERO, 0.0),
-        |info, [c1, c2]| {
-            let h1 = convert_fingers(info, c1);
-            let h2 = convert_fingers(info, c2);
-
-            let sfb = h1
-                .values()
-                .zip(h2.values())
-                .map(|(x1, x2)| match (x1, x2) {
-                    (Some(x1), Some(x2)) if x1 != x2 => 1.0,
-                    _ => 0.0,
-                })
-                .sum();
-
-            let mut movement = Vec2::ZERO;
-            for pair in h1.iter().zip(h2.values()) {
-                match pair {
-                    ((hand, &Some((x, _))), &Some((y, hy))) => {
-                        let delta =
-                            (x - y).abs() / finger_strength(hand.finger) * hold_multiplier(hy);
-                        movement += delta;
-                    }
-                    _ => {}
-                }
+        |info, c| info.way(info.cached_pair_cost(c), &c),
+        |x, y| info.reduce(x, y, BigramEval::min),
+        bigram,
+    )
+}
+
+/// Every bigram metric of typing one way of each of two letters.
+pub fn combo_pair_cost(info: &KeyboardLayout, [c1, c2]: [&KeyCombo; 2]) -> BigramEval {
+    let [s1, s2] = [(c1.layer, c2.layer), (c1.shift, c2.shift)].map(|(x, y)| u8::from(x != y));
+    let staccato = (s1 + s2) as f32;
+    let crossing = c1.tap_layer != c2.tap_layer;
+
+    let moved = match (finger_keys(info, c1), finger_keys(info, c2)) {
+        (Some(k1), Some(k2)) => info.kb.tables().bigram_cost(&k1, &k2, staccato, crossing),
+        _ => {
+            let (h1, h2) = (convert_fingers(info, c1), convert_fingers(info, c2));
+            BigramEval {
+                scissors: scissor_cost(info.kb.hand_model(), &info.base, &h1, &h2),
+                time: bigram_time(info.kb.hand_model(), &info.base, &h1, &h2),
+                ..bigram_cost(info.kb.hand_model(), &h1, &h2, staccato, crossing)
             }
+        }
+    };
+    let thumbs = thumb_cost(info, c1, c2);
+    BigramEval {
+        misfire: misfire(info, c1, c2),
+        retap: retap(info, c1, c2),
+        same_thumb: thumbs.same_thumb,
+        thumb_travel: thumbs.thumb_travel,
+        thumb_alternation: thumbs.thumb_alternation,
+        ..moved
+    }
+}
 
-            let [s1, s2] =
-                [(c1.layer, c2.layer), (c1.shift, c2.shift)].map(|(x, y)| u8::from(x != y));
-            let staccato = (s1 + s2) as f32;
+/// The thumb key each hand presses or holds for `combo`, left then right.
+fn thumb_presses(info: &KeyboardLayout, combo: &KeyCombo) -> [Option<usize>; 2] {
+    let mut thumbs = [None; 2];
+    for index in combo_keys(combo) {
+        let finger = info.kb.key(index).finger();
+        if finger.finger == Finger::Thumb {
+            thumbs[usize::from(u8::from(finger.hand))].get_or_insert(index);
+        }
+    }
+    thumbs
+}
 
-            (
-                BigramEval {
-                    sfb,
-                    movement,
-                    staccato,
-                },
-                1.0,
-            )
-        },
-        avg_reduce,
-        bigram,
-    )
+/// The thumb metrics of going from `c1` to `c2`, the rest zero. A thumb
+/// pressing one key then another, such as holding a layer key then tapping
+/// space, counts to `same_thumb` and the millimetres between the keys to
+/// `thumb_travel`. `thumb_alternation` is 1 when both press a thumb key and
+/// no thumb presses for both.
+pub fn thumb_cost(info: &KeyboardLayout, c1: &KeyCombo, c2: &KeyCombo) -> BigramEval {
+    let (t1, t2) = (thumb_presses(info, c1), thumb_presses(info, c2));
+    let mut cost = BigramEval::ZERO;
+    for (k1, k2) in t1.into_iter().zip(t2) {
+        if let (Some(k1), Some(k2)) = (k1, k2) {
+            if k1 != k2 {
+                cost.same_thumb += 1.0;
+                cost.thumb_travel += info.kb.key(k1).pos().distance(info.kb.key(k2).pos());
+            }
+        }
+    }
+    let pressing = |thumbs: [Option<usize>; 2]| thumbs.iter().any(Option::is_some);
+    let shared = t1.iter().zip(&t2).any(|(a, b)| a.is_some() && b.is_some());
+    cost.thumb_alternation = f32::from(u8::from(pressing(t1) && pressing(t2) && !shared));
+    cost
 }
 
 pub fn one_letter_any<T>(
@@ -483,7 +985,7 @@ pub fn one_letter_any<T>(
     init: T,
     mut op: impl FnMut(&KeyboardLayout, [&KeyCombo; 1]) -> T,
     mut reduce: impl FnMut(T, T) -> T,
-    letter: [u8; 1],
+    letter: [Symbol; 1],
 ) -> T {
     let [combo1] = letter.map(|k| info.key(k));
     let mut result = init;
@@ -499,7 +1001,7 @@ pub fn one_bigram_any<T>(
     init: T,
     mut op: impl FnMut(&KeyboardLayout, [&KeyCombo; 2]) -> T,
     mut reduce: impl FnMut(T, T) -> T,
-    bigram: [u8; 2],
+    bigram: [Symbol; 2],
 ) -> T {
     let [combo1, combo2] = bigram.map(|k| info.key(k));
     let mut result = init;
@@ -517,7 +1019,7 @@ pub fn one_trigram_any<T>(
     init: T,
     mut op: impl FnMut(&KeyboardLayout, [&KeyCombo; 3]) -> T,
     mut reduce: impl FnMut(T, T) -> T,
-    trigram: [u8; 3],
+    trigram: [Symbol; 3],
 ) -> T {
     let [combo1, combo2, combo3] = trigram.map(|k| info.key(k));
     let mut result = init;
@@ -532,20 +1034,328 @@ pub fn one_trigram_any<T>(
     result
 }
 
-pub fn one_trigram(info: &KeyboardLayout, trigram: [u8; 3]) -> TrigramEval {
+/// Like [`one_trigram_any`], for patterns longer than three keys such as runs
+/// on one hand.
+pub fn one_quadgram_any<T>(
+    info: &KeyboardLayout,
+    init: T,
+    mut op: impl FnMut(&KeyboardLayout, [&KeyCombo; 4]) -> T,
+    mut reduce: impl FnMut(T, T) -> T,
+    quadgram: [Symbol; 4],
+) -> T {
+    let [combo1, combo2, combo3, combo4] = quadgram.map(|k| info.key(k));
+    let mut result = init;
+    for c1 in combo1 {
+        for c2 in combo2 {
+            for c3 in combo3 {
+                for c4 in combo4 {
+                    let this = op(info, [c1, c2, c3, c4]);
+                    result = reduce(result, this);
+                }
+            }
+        }
+    }
+    result
+}
+
+pub fn one_trigram(info: &KeyboardLayout, trigram: [Symbol; 3]) -> (TrigramEval, f32) {
     one_trigram_any(
         info,
-        TrigramEval::NAN,
-        |_, _| {
-            // fuck what do i do about multifinger shit
-            TrigramEval::splat(0.0)
-        },
-        TrigramEval::min,
+        (TrigramEval::ZERO, 0.0),
+        |info, c| info.way(combo_triple_cost(info, c), &c),
+        |x, y| info.reduce(x, y, TrigramEval::min),
         trigram,
     )
 }
 
-/// sum squared evaluation
-pub fn sse<const N: usize>(combos: [(f32, f32); N]) -> f32 {
-    combos.into_iter().map(|(w, x)| w * x.powi(2)).sum()
+/// Classifies a trigram by the fingers tapping its keys, holds aside. A roll
+/// is a pair on one hand with two fingers next to a key of the other hand,
+/// inward when it runs from the pinky towards the index finger. Trigrams
+/// with a thumb tap are none of these.
+pub fn combo_triple_cost(info: &KeyboardLayout, combos: [&KeyCombo; 3]) -> TrigramEval {
+    let fingers = combos.map(|c| info.kb.key(c.key).finger());
+    let mut cost = TrigramEval::ZERO;
+    if fingers.iter().any(|f| f.finger == Finger::Thumb) {
+        return cost;
+    }
+    let [f1, f2, f3] = fingers;
+    match (f1.hand == f2.hand, f2.hand == f3.hand) {
+        (false, false) => cost.alternates = 1.0,
+        (true, true) => {
+            let [a, b, c] = fingers.map(|f| f.finger as u8);
+            if a != b && b != c && (a < b) != (b < c) {
+                cost.redirects = 1.0;
+            }
+        }
+        (same_first, _) => {
+            let (from, to) = if same_first { (f1, f2) } else { (f2, f3) };
+            // the index finger is 1 and the pinky 4
+            if (to.finger as u8) < (from.finger as u8) {
+                cost.inward_rolls = 1.0;
+            } else if to.finger != from.finger {
+                cost.outward_rolls = 1.0;
+            }
+        }
+    }
+    cost
+}
+
+/// Weights of the squared metrics in the scalar objective, one for every
+/// metric of an [`Evaluation`]. The ones the objective has no use for by
+/// default, such as `bigram.sfb` which `movement` already covers, are 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalWeights {
+    pub base: Vec3,
+    pub stretch: Vec2,
+    /// taps of keys that also hold
+    pub tap_hold: f32,
+    /// keys the finger reaches sideways for, such as the inner index column
+    pub lateral: f32,
+    /// keys awkward to press however close they are, such as the bottom row
+    /// under the ring finger
+    pub effort: f32,
+    pub sfb: f32,
+    /// same finger skipgrams, where a layout that spreads a finger's keys
+    /// over both sides of a hand change hides its sfbs
+    pub sfs: f32,
+    pub movement: Vec2,
+    pub staccato: f32,
+    pub coupling: f32,
+    /// rolls off tap-hold keys
+    pub misfire: f32,
+    /// holding a key right after tapping it
+    pub retap: f32,
+    /// neighbouring fingers more than a row apart
+    pub scissors: f32,
+    /// a thumb going from one key to another, such as a layer hold then space
+    pub same_thumb: f32,
+    /// millimetres of those moves, which `movement` already weighs by strength
+    pub thumb_travel: f32,
+    /// one thumb then the other, negative to reward it; 0 by default, as a
+    /// reward with no bound has the optimizer move letters onto the thumbs
+    pub thumb_alternation: f32,
+    /// milliseconds by Fitts's law, the only weight of `eval.model = "fitts"`
+    pub time: f32,
+    pub redirects: f32,
+    /// rolls from the pinky towards the index finger, negative to reward them
+    pub inward_rolls: f32,
+    /// rolls from the index finger towards the pinky, rewarded a little less
+    pub outward_rolls: f32,
+    pub alternates: f32,
+    /// the presses of every finger alike, see `finger_load` to weigh them by
+    /// finger
+    pub presses: f32,
+    /// fingers over their share of the presses
+    pub finger_load: f32,
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        Self {
+            base: Vec3::new(2.0, 1.0, 5.0),
+            stretch: Vec2::new(5.0, 3.0),
+            tap_hold: 1.0,
+            lateral: 1.0,
+            effort: 1.0,
+            sfb: 0.0,
+            sfs: 1.0,
+            movement: Vec2::new(3.0, 2.0),
+            staccato: 20.0,
+            coupling: 2.0,
+            misfire: 1.0,
+            retap: 1.0,
+            scissors: 1.0,
+            same_thumb: 1.0,
+            thumb_travel: 0.0,
+            thumb_alternation: 0.0,
+            time: 0.0,
+            redirects: 0.0,
+            inward_rolls: -1.0,
+            outward_rolls: -0.5,
+            alternates: 0.0,
+            presses: 0.0,
+            finger_load: 1.0,
+        }
+    }
+}
+
+impl EvalWeights {
+    /// The weights of `eval.model = "fitts"`: `bigram.time` alone, so the
+    /// objective follows the milliseconds spent typing the corpus.
+    pub fn fitts() -> Self {
+        Self {
+            base: Vec3::ZERO,
+            stretch: Vec2::ZERO,
+            tap_hold: 0.0,
+            lateral: 0.0,
+            effort: 0.0,
+            sfb: 0.0,
+            sfs: 0.0,
+            movement: Vec2::ZERO,
+            staccato: 0.0,
+            coupling: 0.0,
+            misfire: 0.0,
+            retap: 0.0,
+            scissors: 0.0,
+            same_thumb: 0.0,
+            thumb_travel: 0.0,
+            thumb_alternation: 0.0,
+            time: 1.0,
+            redirects: 0.0,
+            inward_rolls: 0.0,
+            outward_rolls: 0.0,
+            alternates: 0.0,
+            presses: 0.0,
+            finger_load: 0.0,
+        }
+    }
+
+    /// The weights laid out as an evaluation, so that [`Evaluation::fields`]
+    /// names each weight like the metric it weighs.
+    pub fn as_evaluation(&self) -> Evaluation {
+        Evaluation {
+            letter: LetterEval {
+                base: self.base,
+                stretch: self.stretch,
+                tap_hold: self.tap_hold,
+                lateral: self.lateral,
+                effort: self.effort,
+            },
+            bigram: BigramEval {
+                sfb: self.sfb,
+                sfs: self.sfs,
+                movement: self.movement,
+                staccato: self.staccato,
+                coupling: self.coupling,
+                misfire: self.misfire,
+                retap: self.retap,
+                scissors: self.scissors,
+                same_thumb: self.same_thumb,
+                thumb_travel: self.thumb_travel,
+                thumb_alternation: self.thumb_alternation,
+                time: self.time,
+            },
+            trigram: TrigramEval {
+                redirects: self.redirects,
+                inward_rolls: self.inward_rolls,
+                outward_rolls: self.outward_rolls,
+                alternates: self.alternates,
+            },
+            finger: FingerEval {
+                left_pinky: self.presses,
+                left_ring: self.presses,
+                left_middle: self.presses,
+                left_index: self.presses,
+                left_thumb: self.presses,
+                right_thumb: self.presses,
+                right_index: self.presses,
+                right_middle: self.presses,
+                right_ring: self.presses,
+                right_pinky: self.presses,
+                load: self.finger_load,
+            },
+        }
+    }
+
+    /// Scalar objective of an evaluation already scaled against the reference.
+    pub fn score(&self, scaled: &Evaluation) -> f32 {
+        let (letter, bigram, trigram, finger) = (
+            &scaled.letter,
+            &scaled.bigram,
+            &scaled.trigram,
+            &scaled.finger,
+        );
+        sse([
+            (self.base.x, letter.base.x),
+            (self.base.y, letter.base.y),
+            (self.base.z, letter.base.z),
+            (self.stretch.x, letter.stretch.x),
+            (self.stretch.y, letter.stretch.y),
+            (self.tap_hold, letter.tap_hold),
+            (self.lateral, letter.lateral),
+            (self.effort, letter.effort),
+            (self.sfb, bigram.sfb),
+            (self.sfs, bigram.sfs),
+            (self.movement.x, bigram.movement.x),
+            (self.movement.y, bigram.movement.y),
+            (self.staccato, bigram.staccato),
+            (self.coupling, bigram.coupling),
+            (self.misfire, bigram.misfire),
+            (self.retap, bigram.retap),
+            (self.scissors, bigram.scissors),
+            (self.same_thumb, bigram.same_thumb),
+            (self.thumb_travel, bigram.thumb_travel),
+            (self.thumb_alternation, bigram.thumb_alternation),
+            (self.time, bigram.time),
+            (self.redirects, trigram.redirects),
+            (self.inward_rolls, trigram.inward_rolls),
+            (self.outward_rolls, trigram.outward_rolls),
+            (self.alternates, trigram.alternates),
+            (self.presses, finger.left_pinky),
+            (self.presses, finger.left_ring),
+            (self.presses, finger.left_middle),
+            (self.presses, finger.left_index),
+            (self.presses, finger.left_thumb),
+            (self.presses, finger.right_thumb),
+            (self.presses, finger.right_index),
+            (self.presses, finger.right_middle),
+            (self.presses, finger.right_ring),
+            (self.presses, finger.right_pinky),
+            (self.finger_load, finger.load),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+    use crate::{
+        counter::{self, NgramDepth, TextOptions},
+        ferris,
+        layout::Edit,
+        optimization,
+    };
+
+    #[test]
+    fn delta_matches_fresh_evaluation() {
+        let keyboard = Keyboard::ferris_sweep();
+        let text = "The quick brown fox jumps over the lazy dog; fn main() { x[0] = 1 }\n";
+        let count = counter::count_reader(
+            text.repeat(4).as_bytes(),
+            NgramDepth::Quadgrams,
+            &TextOptions::default(),
+        )
+        .unwrap();
+        let mut rng = optimization::seeded_rng(1);
+
+        let mut layout = ferris::qwerty();
+        let mut old =
+            KeyboardLayout::generate_owned(layout.clone(), &keyboard, None, None).unwrap();
+        let mut eval = evaluate(&old, &count);
+        for _ in 0..200 {
+            let layer = rng.random_range(0..layout.layer_count());
+            let [a, b] =
+                [(); 2].map(|_| KeyLoc::new(layer, rng.random_range(0..layout.layer_size())));
+            layout.apply(Edit::SwapKeys(a, b));
+            let Ok(info) = KeyboardLayout::generate_owned(layout.clone(), &keyboard, None, None)
+            else {
+                layout.apply(Edit::SwapKeys(a, b));
+                continue;
+            };
+
+            eval = info.evaluate_delta(&old, &eval, &count);
+            let fresh = evaluate(&info, &count);
+            for ((name, patched), (_, fresh)) in eval.fields().into_iter().zip(fresh.fields()) {
+                let close = (patched - fresh).abs() <= 1e-3 * fresh.abs().max(1.0);
+                assert!(
+                    close || patched == fresh,
+                    "{name}: {patched} against {fresh}"
+                );
+            }
+            old = info;
+        }
+    }
 }
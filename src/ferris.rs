@@ -1,6 +1,9 @@
 use std::num::NonZeroU8;
 
-use crate::layout::{Behavior, Layout, LayoutLayer};
+use crate::{
+    layout::{Behavior, Layout, LayoutLayer},
+    symbol::Symbol,
+};
 
 const fn u(x: u8) -> NonZeroU8 {
     match NonZeroU8::new(x) {
@@ -14,6 +17,9 @@ pub fn make_hold(mods: &[u8; 34]) -> Vec<Option<Behavior>> {
     for &m in mods {
         let hold = match m {
             b'S' => Some(Behavior::Shift),
+            b'C' => Some(Behavior::Ctrl),
+            b'A' => Some(Behavior::Alt),
+            b'G' => Some(Behavior::Gui),
             c if c.is_ascii_digit() => Some(Behavior::Layer(u(c - b'0'))),
             _ => None,
         };
@@ -28,9 +34,9 @@ fn layer_simple(lay: &[u8]) -> LayoutLayer {
     for &key in lay {
         let tap = match key {
             b' ' => None,
-            ch => Some(NonZeroU8::new(ch)),
+            ch => Symbol::from_ascii(ch),
         };
-        layer1.push(tap.flatten());
+        layer1.push(tap);
     }
     for _ in layer1.len()..34 {
         layer1.push(None);
@@ -46,12 +52,12 @@ pub fn layout_any(base: &[u8; 30]) -> Layout {
     const LAY4: &[u8; 30] = b"1  2  3  4  5  6  7  8  9  0  ";
     let base_hold = make_hold(MODS);
     let mut layer0 = layer_simple(base);
-    layer0.set_key(31, Some(u(b' ')));
-    layer0.set_key(32, Some(u(b' ')));
+    layer0.set_key(31, Some(Symbol::SPACE));
+    layer0.set_key(32, Some(Symbol::SPACE));
     let layer1 = layer_simple(LAY1);
     let layer2 = layer_simple(LAY2);
     let mut layer3 = layer_simple(LAY3);
-    layer3.set_key(31, Some(u(b'\t')));
+    layer3.set_key(31, Some(Symbol::TAB));
     let layer4 = layer_simple(LAY4);
 
     Layout::new(base_hold, vec![layer0, layer1, layer2, layer3, layer4])
@@ -72,6 +78,26 @@ pub fn canary() -> Layout {
     layout_any(KEYS)
 }
 
+/// Dvorak with `/` in place of `'`, which the symbol layer already has, so
+/// that `?` stays on the base layer like in the other layouts.
+pub fn dvorak() -> Layout {
+    const KEYS: &[u8; 30] = b"/a;,oq.ejpukyixfdbghmctwrnvlsz";
+    layout_any(KEYS)
+}
+
+/// Every built-in layout, for benchmarking against.
+pub const BUILTIN: [&str; 4] = ["qwerty", "colemak_dh", "canary", "dvorak"];
+
+pub fn by_name(name: &str) -> Option<Layout> {
+    match name {
+        "qwerty" => Some(qwerty()),
+        "colemak_dh" => Some(colemak_dh()),
+        "canary" => Some(canary()),
+        "dvorak" => Some(dvorak()),
+        _ => None,
+    }
+}
+
 fn flip_internal<T: Copy>(buffer: &[T]) -> Vec<T> {
     const NEW_SHAPE: [usize; 34] = [
         27, 28, 29, 24, 25, 26, 21, 22, 23, 18, 19, 20, 15, 16, 17, 12, 13, 14, 9, 10, 11, 6, 7, 8,
@@ -85,8 +111,7 @@ pub fn flip_layout(layout: &Layout) -> Layout {
         flip_internal(layout.base_hold()),
         layout
             .layers()
-            .iter()
-            .map(|layer| LayoutLayer::new(flip_internal(layer.keys())))
+            .map(|layer| LayoutLayer::new(flip_internal(layer)))
             .collect(),
     )
 }
@@ -0,0 +1,109 @@
+use std::{
+    collections::HashMap,
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+};
+
+use anyhow::{anyhow, Result as AnyResult};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{self, KeyboardLayout},
+    keyboard::Keyboard,
+    layout::Layout,
+    symbol::{self, Symbol},
+};
+
+/// N-gram counts keyed by the n-gram itself, e.g. `{"bigrams": {"th": 120}}`.
+#[derive(Deserialize)]
+struct CorpusJson {
+    #[serde(default)]
+    letters: HashMap<String, u32>,
+    #[serde(default)]
+    bigrams: HashMap<String, u32>,
+    #[serde(default)]
+    trigrams: HashMap<String, u32>,
+    #[serde(default)]
+    skipgrams: HashMap<String, u32>,
+    #[serde(default)]
+    quadgrams: HashMap<String, u32>,
+    #[serde(default)]
+    caps_words: HashMap<String, u32>,
+}
+
+fn ngrams<const N: usize>(counts: HashMap<String, u32>) -> AnyResult<FxHashMap<[Symbol; N], u32>> {
+    counts
+        .into_iter()
+        .map(|(ngram, freq)| {
+            let key = symbol::ngram(&ngram)
+                .ok_or_else(|| anyhow!("{ngram:?} is not {N} characters with a symbol each"))?;
+            Ok((key, freq))
+        })
+        .collect()
+}
+
+fn evaluate_json(corpus_json: &str, layout_json: &str) -> AnyResult<String> {
+    let corpus: CorpusJson = serde_json::from_str(corpus_json)?;
+    let count = CountOutcome {
+        letter: ngrams(corpus.letters)?,
+        bigrams: ngrams(corpus.bigrams)?,
+        trigrams: ngrams(corpus.trigrams)?,
+        skipgrams: ngrams(corpus.skipgrams)?,
+        quadgrams: ngrams(corpus.quadgrams)?,
+        caps_words: ngrams(corpus.caps_words)?,
+    };
+    let layout: Layout = serde_json::from_str(layout_json)?;
+    let keyboard = Keyboard::ferris_sweep();
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    Ok(serde_json::to_string(&evaluate::evaluate(&info, &count))?)
+}
+
+/// Evaluates a layout json on the Ferris Sweep against corpus counts, and
+/// returns the raw `Evaluation` as json, or `{"error": "..."}` on failure,
+/// panics included.
+/// Returns null only if either argument is null.
+///
+/// # Safety
+///
+/// Both arguments must be null or point to nul-terminated strings. The result
+/// must be released with [`keyboard_free_string`].
+#[no_mangle]
+pub unsafe extern "C" fn evaluate_layout_json(
+    corpus_json: *const c_char,
+    layout_json: *const c_char,
+) -> *mut c_char {
+    if corpus_json.is_null() || layout_json.is_null() {
+        return std::ptr::null_mut();
+    }
+    let corpus = CStr::from_ptr(corpus_json).to_string_lossy();
+    let layout = CStr::from_ptr(layout_json).to_string_lossy();
+
+    // a panic must not unwind into the caller
+    let result = panic::catch_unwind(AssertUnwindSafe(|| evaluate_json(&corpus, &layout)))
+        .unwrap_or_else(|panic| {
+            let message = panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(anyhow!("panicked: {message}"))
+        });
+    let json =
+        result.unwrap_or_else(|err| serde_json::json!({ "error": format!("{err:#}") }).to_string());
+    // serde_json escapes any nul inside strings, so this cannot fail
+    CString::new(json).unwrap().into_raw()
+}
+
+/// Frees a string returned by this library.
+///
+/// # Safety
+///
+/// `s` must be null or a pointer returned by this library, not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn keyboard_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result as AnyResult};
+use rustc_hash::{FxHashMap, FxHasher};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::EvalConfig, counter::CountOutcome, evaluate::Evaluation, keyboard::Keyboard,
+    layout::Layout, symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GalleryEntry {
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub layout: Layout,
+    /// raw evaluations keyed by the [`cache_key`] they were computed under
+    #[serde(default)]
+    pub evaluations: HashMap<String, Evaluation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct GalleryIndex {
+    champion: Option<String>,
+}
+
+/// A directory of named layouts, one JSON file per entry.
+pub struct Gallery {
+    dir: PathBuf,
+    index: GalleryIndex,
+}
+
+const INDEX_FILE: &str = "index.json";
+
+impl Gallery {
+    pub fn open(dir: impl AsRef<Path>) -> AnyResult<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        let index = match std::fs::read_to_string(dir.join(INDEX_FILE)) {
+            Ok(data) => serde_json::from_str(&data)?,
+            Err(_) => GalleryIndex::default(),
+        };
+        Ok(Self { dir, index })
+    }
+
+    fn entry_path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{name}.json"))
+    }
+
+    fn write_index(&self) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(&self.index)?;
+        std::fs::write(self.dir.join(INDEX_FILE), json)?;
+        Ok(())
+    }
+
+    pub fn save(&self, name: &str, tags: Vec<String>, layout: Layout) -> AnyResult<()> {
+        if name.is_empty() || name.contains(['/', '\\']) || name == "index" {
+            bail!("invalid gallery name {name:?}");
+        }
+        self.write(&GalleryEntry {
+            name: name.to_string(),
+            tags,
+            layout,
+            evaluations: HashMap::new(),
+        })
+    }
+
+    pub fn write(&self, entry: &GalleryEntry) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(entry)?;
+        std::fs::write(self.entry_path(&entry.name), json)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> AnyResult<GalleryEntry> {
+        let path = self.entry_path(name);
+        let data =
+            std::fs::read_to_string(&path).with_context(|| format!("no gallery entry {name:?}"))?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn entries(&self) -> AnyResult<Vec<GalleryEntry>> {
+        let mut entries = vec![];
+        for item in std::fs::read_dir(&self.dir)? {
+            let path = item?.path();
+            let is_entry = path.extension().is_some_and(|e| e == "json")
+                && path.file_name().is_some_and(|f| f != INDEX_FILE);
+            if is_entry {
+                let data = std::fs::read_to_string(&path)?;
+                let entry = serde_json::from_str(&data)
+                    .with_context(|| format!("bad gallery entry {}", path.display()))?;
+                entries.push(entry);
+            }
+        }
+        entries.sort_by(|a: &GalleryEntry, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Returns the cached evaluation under `key`, computing and storing it if missing.
+    pub fn evaluation(
+        &self,
+        entry: &mut GalleryEntry,
+        key: &str,
+        eval: impl FnOnce(&Layout) -> Option<Evaluation>,
+    ) -> AnyResult<Option<Evaluation>> {
+        if let Some(&cached) = entry.evaluations.get(key) {
+            return Ok(Some(cached));
+        }
+        let Some(result) = eval(&entry.layout) else {
+            return Ok(None);
+        };
+        entry.evaluations.insert(key.to_string(), result);
+        self.write(entry)?;
+        Ok(Some(result))
+    }
+
+    pub fn champion(&self) -> Option<&str> {
+        self.index.champion.as_deref()
+    }
+
+    pub fn set_champion(&mut self, name: &str) -> AnyResult<()> {
+        self.get(name)?;
+        self.index.champion = Some(name.to_string());
+        self.write_index()
+    }
+}
+
+/// Hash of everything a raw evaluation depends on: the counted n-grams, the
+/// keyboard, the eval config and the names of the metrics, so that an
+/// evaluation cached before a metric was added is not read with it as zero.
+pub fn cache_key(count: &CountOutcome, keyboard: &Keyboard, config: &EvalConfig) -> String {
+    // by character, since symbol ids differ between runs
+    fn table<const N: usize>(hasher: &mut FxHasher, table: &FxHashMap<[Symbol; N], u32>) {
+        let mut entries = table
+            .iter()
+            .map(|(ngram, &freq)| (ngram.map(Symbol::char), freq))
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
+        entries.hash(hasher);
+    }
+
+    let mut hasher = FxHasher::default();
+    table(&mut hasher, &count.letter);
+    table(&mut hasher, &count.bigrams);
+    table(&mut hasher, &count.trigrams);
+    table(&mut hasher, &count.skipgrams);
+    table(&mut hasher, &count.quadgrams);
+    table(&mut hasher, &count.caps_words);
+    for (i, key) in keyboard.keys().iter().enumerate() {
+        format!("{key:?} {}", keyboard.offset(i)).hash(&mut hasher);
+    }
+    serde_json::to_string(config)
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    for (name, _) in Evaluation::default().fields() {
+        name.hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
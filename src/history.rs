@@ -0,0 +1,137 @@
+use std::{
+    fs::OpenOptions,
+    hash::Hasher as _,
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result as AnyResult;
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+
+use crate::layout::Layout;
+
+/// One finished optimization run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    pub profile: String,
+    pub config: serde_json::Value,
+    pub seed: u64,
+    pub trajectory: Vec<(u32, f32)>,
+    /// the annealing score, relative to the start layout of this run only
+    pub score: f32,
+    /// the weighted score of the result in percent of the reference layout,
+    /// comparable between runs on the same corpus and reference; missing in
+    /// records from before it was logged
+    #[serde(default)]
+    pub weighted_score: Option<f32>,
+    pub layout_hash: String,
+    pub output: PathBuf,
+}
+
+/// Append-only JSON-lines log of every optimization run.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, record: &RunRecord) -> AnyResult<()> {
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
+    }
+
+    pub fn records(&self) -> AnyResult<Vec<RunRecord>> {
+        let Ok(file) = std::fs::File::open(&self.path) else {
+            return Ok(vec![]);
+        };
+        let mut records = vec![];
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                records.push(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(records)
+    }
+
+    pub fn for_profile(&self, profile: Option<&str>) -> AnyResult<Vec<RunRecord>> {
+        let mut records = self.records()?;
+        records.retain(|r| profile.is_none_or(|p| r.profile == p));
+        Ok(records)
+    }
+
+    /// The run with the lowest weighted score, optionally restricted to one
+    /// profile. Runs without a weighted score are left out, since the score
+    /// of each run is relative to its own start layout.
+    pub fn best(&self, profile: Option<&str>) -> AnyResult<Option<RunRecord>> {
+        Ok(self
+            .for_profile(profile)?
+            .into_iter()
+            .filter_map(|r| Some((r.weighted_score?, r)))
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, r)| r))
+    }
+}
+
+pub fn layout_hash(layout: &Layout) -> String {
+    let mut hasher = FxHasher::default();
+    hasher.write(serde_json::to_string(layout).unwrap_or_default().as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+pub fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(profile: &str, score: f32, weighted_score: Option<f32>) -> RunRecord {
+        RunRecord {
+            timestamp: 0,
+            profile: profile.to_string(),
+            config: serde_json::Value::Null,
+            seed: 0,
+            trajectory: vec![],
+            score,
+            weighted_score,
+            layout_hash: String::new(),
+            output: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn best_compares_weighted_scores() {
+        let dir = std::env::temp_dir().join(format!("keyboard-history-{}", std::process::id()));
+        let history = History::new(dir.join("kb").join("history.jsonl"));
+        history.append(&record("rust", -5.0, Some(90.0))).unwrap();
+        history.append(&record("rust", 2.0, Some(-10.0))).unwrap();
+        history.append(&record("rust", -50.0, None)).unwrap();
+        history.append(&record("prose", 1.0, Some(-20.0))).unwrap();
+
+        let best = history.best(Some("rust")).unwrap().unwrap();
+        assert_eq!(best.weighted_score, Some(-10.0));
+        let best = history.best(None).unwrap().unwrap();
+        assert_eq!(best.profile, "prose");
+        assert_eq!(history.records().unwrap().len(), 4);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{anyhow, bail, Result as AnyResult};
+use serde_json::{json, Value};
+use std::{fmt::Write as _, str::FromStr, sync::LazyLock};
+
+use crate::{
+    keyboard::{KeyGrid, Keyboard},
+    layout::{Behavior, Layout},
+    shift::ShiftTable,
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostTarget {
+    Kanata,
+    Keyd,
+    Karabiner,
+}
+
+impl FromStr for HostTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "kanata" => HostTarget::Kanata,
+            "keyd" => HostTarget::Keyd,
+            "karabiner" => HostTarget::Karabiner,
+            _ => bail!("unknown host target {s:?}"),
+        })
+    }
+}
+
+/// Physical key on a regular US keyboard that a layout index is bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HostKey {
+    Char(Symbol),
+    LeftAlt,
+    RightAlt,
+    RightCtrl,
+}
+
+// the finger rows sit on the qwerty block, and a number row above it, lined
+// up at the bottom and split between the hands; the thumbs sit around space
+const HOST_ROWS: [&[u8; 10]; 4] = [b"1234567890", b"qwertyuiop", b"asdfghjkl;", b"zxcvbnm,./"];
+const HOST_THUMBS: [HostKey; 4] = [
+    HostKey::LeftAlt,
+    HostKey::Char(Symbol::SPACE),
+    HostKey::RightAlt,
+    HostKey::RightCtrl,
+];
+
+fn host_key(grid: &KeyGrid, index: usize) -> Option<HostKey> {
+    let (column, row) = grid.pos(index);
+    // the column in a row of `len` host keys split between the hands
+    let column_in = |len: usize| {
+        (column + len / 2)
+            .checked_sub(grid.middle())
+            .filter(|&c| c < len)
+    };
+    match row.checked_sub(grid.finger_rows()) {
+        Some(_) => column_in(HOST_THUMBS.len()).map(|c| HOST_THUMBS[c]),
+        None => {
+            let row = (row + HOST_ROWS.len()).checked_sub(grid.finger_rows())?;
+            column_in(10).map(|c| HostKey::Char(Symbol::byte(HOST_ROWS[row][c])))
+        }
+    }
+}
+
+/// Splits a character into the US key that types it and whether shift is needed.
+fn us_key(ch: Symbol) -> (Symbol, bool) {
+    static US: LazyLock<ShiftTable> = LazyLock::new(ShiftTable::us);
+    (US.unshifted(ch), US.is_shifted(ch))
+}
+
+fn kanata_name(key: HostKey) -> AnyResult<&'static str> {
+    Ok(match key {
+        HostKey::LeftAlt => "lalt",
+        HostKey::RightAlt => "ralt",
+        HostKey::RightCtrl => "rctl",
+        HostKey::Char(ch) => match ch.char() {
+            ' ' => "spc",
+            '\t' => "tab",
+            '\n' => "ret",
+            '-' => "min",
+            '=' => "eql",
+            '[' => "lbrc",
+            ']' => "rbrc",
+            '\\' => "bksl",
+            ';' => "scln",
+            '\'' => "apo",
+            '`' => "grv",
+            ',' => "comm",
+            '.' => ".",
+            '/' => "/",
+            _ => alnum_name(ch)?,
+        },
+    })
+}
+
+fn keyd_name(key: HostKey) -> AnyResult<&'static str> {
+    Ok(match key {
+        HostKey::LeftAlt => "leftalt",
+        HostKey::RightAlt => "rightalt",
+        HostKey::RightCtrl => "rightcontrol",
+        HostKey::Char(ch) => match ch.char() {
+            ' ' => "space",
+            '\t' => "tab",
+            '\n' => "enter",
+            '-' => "minus",
+            '=' => "equal",
+            '[' => "leftbrace",
+            ']' => "rightbrace",
+            '\\' => "backslash",
+            ';' => "semicolon",
+            '\'' => "apostrophe",
+            '`' => "grave",
+            ',' => "comma",
+            '.' => "dot",
+            '/' => "slash",
+            _ => alnum_name(ch)?,
+        },
+    })
+}
+
+fn karabiner_name(key: HostKey) -> AnyResult<&'static str> {
+    Ok(match key {
+        HostKey::LeftAlt => "left_option",
+        HostKey::RightAlt => "right_option",
+        HostKey::RightCtrl => "right_control",
+        HostKey::Char(ch) => match ch.char() {
+            ' ' => "spacebar",
+            '\t' => "tab",
+            '\n' => "return_or_enter",
+            '-' => "hyphen",
+            '=' => "equal_sign",
+            '[' => "open_bracket",
+            ']' => "close_bracket",
+            '\\' => "backslash",
+            ';' => "semicolon",
+            '\'' => "quote",
+            '`' => "grave_accent_and_tilde",
+            ',' => "comma",
+            '.' => "period",
+            '/' => "slash",
+            _ => alnum_name(ch)?,
+        },
+    })
+}
+
+fn alnum_name(ch: Symbol) -> AnyResult<&'static str> {
+    const NAMES: [&str; 36] = [
+        "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r",
+        "s", "t", "u", "v", "w", "x", "y", "z", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    ];
+    match ch.char() {
+        c @ 'a'..='z' => Ok(NAMES[c as usize - 'a' as usize]),
+        c @ '0'..='9' => Ok(NAMES[26 + c as usize - '0' as usize]),
+        _ => Err(anyhow!("no host key for {ch:?}")),
+    }
+}
+
+/// Binds the keys of `layout` on `keyboard` to the keys of a US keyboard
+/// where they sit alike.
+pub fn export(layout: &Layout, keyboard: &Keyboard, target: HostTarget) -> AnyResult<String> {
+    layout.reachability().check()?;
+    let sources = sources(layout, keyboard)?;
+    match target {
+        HostTarget::Kanata => export_kanata(layout, &sources),
+        HostTarget::Keyd => export_keyd(layout, &sources),
+        HostTarget::Karabiner => export_karabiner(layout, &sources),
+    }
+}
+
+/// The host key of every layout index, `None` for keys with no place on the
+/// US keyboard, which must be left empty.
+fn sources(layout: &Layout, keyboard: &Keyboard) -> AnyResult<Vec<Option<HostKey>>> {
+    if layout.layer_size() > keyboard.keys().len() {
+        bail!(
+            "the layout has {} keys a layer, more than the {} of the keyboard",
+            layout.layer_size(),
+            keyboard.keys().len()
+        );
+    }
+    let grid = keyboard.grid(layout.layer_size());
+    (0..layout.layer_size())
+        .map(|i| {
+            let used =
+                layout.base_hold()[i].is_some() || layout.layers().any(|layer| layer[i].is_some());
+            match host_key(&grid, i) {
+                None if used => Err(anyhow!("no host key for index {i}")),
+                key => Ok(key),
+            }
+        })
+        .collect()
+}
+
+fn kanata_tap(key: Option<Symbol>) -> AnyResult<String> {
+    let Some(key) = key else {
+        return Ok("_".to_string());
+    };
+    let (base, shift) = us_key(key);
+    let name = kanata_name(HostKey::Char(base))?;
+    Ok(match shift {
+        true => format!("S-{name}"),
+        false => name.to_string(),
+    })
+}
+
+fn export_kanata(layout: &Layout, sources: &[Option<HostKey>]) -> AnyResult<String> {
+    let mut out = String::new();
+    writeln!(out, "(defcfg process-unmapped-keys yes)")?;
+
+    write!(out, "(defsrc")?;
+    for &key in sources.iter().flatten() {
+        write!(out, " {}", kanata_name(key)?)?;
+    }
+    writeln!(out, ")")?;
+
+    for (li, layer) in layout.layers().enumerate() {
+        write!(out, "(deflayer l{li}")?;
+        for (i, &key) in layer.iter().enumerate() {
+            if sources[i].is_none() {
+                continue;
+            }
+            let tap = kanata_tap(key)?;
+            let hold = match (li, layout.base_hold()[i]) {
+                (0, Some(Behavior::Shift)) => Some("lsft".to_string()),
+                (0, Some(Behavior::Ctrl)) => Some("lctl".to_string()),
+                (0, Some(Behavior::Alt)) => Some("lalt".to_string()),
+                (0, Some(Behavior::Gui)) => Some("lmet".to_string()),
+                (0, Some(Behavior::Layer(l))) => Some(format!("(layer-while-held l{l})")),
+                _ => None,
+            };
+            match hold {
+                Some(hold) if key.is_none() => write!(out, " {hold}")?,
+                Some(hold) => write!(out, " (tap-hold 200 200 {tap} {hold})")?,
+                None => write!(out, " {tap}")?,
+            }
+        }
+        writeln!(out, ")")?;
+    }
+
+    Ok(out)
+}
+
+fn keyd_tap(key: Symbol) -> AnyResult<String> {
+    let (base, shift) = us_key(key);
+    let name = keyd_name(HostKey::Char(base))?;
+    Ok(match shift {
+        true => format!("S-{name}"),
+        false => name.to_string(),
+    })
+}
+
+fn export_keyd(layout: &Layout, sources: &[Option<HostKey>]) -> AnyResult<String> {
+    let mut out = String::new();
+    writeln!(out, "[ids]\n*")?;
+
+    for (li, layer) in layout.layers().enumerate() {
+        match li {
+            0 => writeln!(out, "\n[main]")?,
+            _ => writeln!(out, "\n[l{li}]")?,
+        }
+
+        for (i, &key) in layer.iter().enumerate() {
+            let Some(src) = sources[i] else {
+                continue;
+            };
+            let hold = match (li, layout.base_hold()[i]) {
+                (0, Some(Behavior::Shift)) => Some("shift".to_string()),
+                (0, Some(Behavior::Ctrl)) => Some("control".to_string()),
+                (0, Some(Behavior::Alt)) => Some("alt".to_string()),
+                (0, Some(Behavior::Gui)) => Some("meta".to_string()),
+                (0, Some(Behavior::Layer(l))) => Some(format!("l{l}")),
+                _ => None,
+            };
+            let src = keyd_name(src)?;
+            match (key, hold) {
+                (Some(key), Some(hold)) => {
+                    writeln!(out, "{src} = overload({hold}, {})", keyd_tap(key)?)?
+                }
+                (None, Some(hold)) => writeln!(out, "{src} = layer({hold})")?,
+                (Some(key), None) => writeln!(out, "{src} = {}", keyd_tap(key)?)?,
+                // unbound base keys are disabled, unbound layer keys fall through
+                (None, None) if li == 0 => writeln!(out, "{src} = noop")?,
+                (None, None) => {}
+            }
+        }
+    }
+
+    Ok(out)
+}
+
+fn karabiner_to(key: Symbol) -> AnyResult<Value> {
+    let (base, shift) = us_key(key);
+    let name = karabiner_name(HostKey::Char(base))?;
+    Ok(match shift {
+        true => json!({ "key_code": name, "modifiers": ["left_shift"] }),
+        false => json!({ "key_code": name }),
+    })
+}
+
+fn export_karabiner(layout: &Layout, sources: &[Option<HostKey>]) -> AnyResult<String> {
+    let mut manipulators = vec![];
+
+    // karabiner takes the first matching manipulator, so layers go before base
+    for (li, layer) in layout.layers().enumerate().skip(1).rev() {
+        for (i, &key) in layer.iter().enumerate() {
+            let (Some(key), Some(src)) = (key, sources[i]) else {
+                continue;
+            };
+            manipulators.push(json!({
+                "type": "basic",
+                "from": { "key_code": karabiner_name(src)?, "modifiers": { "optional": ["any"] } },
+                "to": [karabiner_to(key)?],
+                "conditions": [{ "type": "variable_if", "name": "layer", "value": li }],
+            }));
+        }
+    }
+
+    for (i, &key) in layout.first_layer().iter().enumerate() {
+        let Some(src) = sources[i] else {
+            continue;
+        };
+        let from =
+            json!({ "key_code": karabiner_name(src)?, "modifiers": { "optional": ["any"] } });
+        let held = match layout.base_hold()[i] {
+            Some(Behavior::Shift) => Some(json!({ "key_code": "left_shift" })),
+            Some(Behavior::Ctrl) => Some(json!({ "key_code": "left_control" })),
+            Some(Behavior::Alt) => Some(json!({ "key_code": "left_option" })),
+            Some(Behavior::Gui) => Some(json!({ "key_code": "left_command" })),
+            Some(Behavior::Layer(l)) => {
+                Some(json!({ "set_variable": { "name": "layer", "value": l.get() } }))
+            }
+            None => None,
+        };
+
+        let mut manipulator = json!({ "type": "basic", "from": from });
+        match (key, held) {
+            (key, Some(held)) => {
+                manipulator["to"] = json!([held]);
+                if matches!(layout.base_hold()[i], Some(Behavior::Layer(_))) {
+                    manipulator["to_after_key_up"] =
+                        json!([{ "set_variable": { "name": "layer", "value": 0 } }]);
+                }
+                if let Some(key) = key {
+                    manipulator["to_if_alone"] = json!([karabiner_to(key)?]);
+                }
+            }
+            (Some(key), None) => manipulator["to"] = json!([karabiner_to(key)?]),
+            (None, None) => manipulator["to"] = json!([{ "key_code": "vk_none" }]),
+        }
+        manipulators.push(manipulator);
+    }
+
+    let config = json!({
+        "title": "optimized layout",
+        "rules": [{ "description": "optimized layout", "manipulators": manipulators }],
+    });
+    Ok(serde_json::to_string_pretty(&config)?)
+}
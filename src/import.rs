@@ -0,0 +1,139 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{bail, Result as AnyResult};
+use rustc_hash::FxHashMap;
+use serde::Deserialize;
+
+use crate::{
+    counter::{CountOutcome, Transliteration, NORMALIZED_TOTAL},
+    symbol::Symbol,
+};
+
+/// Frequencies as published, before they are turned into counts.
+#[derive(Debug, Default)]
+struct Frequencies {
+    letters: HashMap<String, f64>,
+    bigrams: HashMap<String, f64>,
+    trigrams: HashMap<String, f64>,
+    skipgrams: HashMap<String, f64>,
+}
+
+/// The language data json of Oxeylyzer, where every table holds fractions.
+#[derive(Deserialize)]
+struct OxeylyzerJson {
+    characters: HashMap<String, f64>,
+    #[serde(default)]
+    bigrams: HashMap<String, f64>,
+    #[serde(default)]
+    trigrams: HashMap<String, f64>,
+    #[serde(default)]
+    skipgrams: HashMap<String, f64>,
+}
+
+/// Reads `ngram<tab>count` lines such as Norvig's `count_2l.txt`, where the
+/// length of each n-gram decides its table, so the letter, bigram and trigram
+/// files can be concatenated into one. Commas separate as well as tabs, and
+/// lines whose second field is not a number (headers) are skipped.
+pub fn load_norvig(
+    path: impl AsRef<Path>,
+    transliteration: &Transliteration,
+) -> AnyResult<CountOutcome> {
+    let mut freqs = Frequencies::default();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let mut fields = line.split(['\t', ',']);
+        let (Some(ngram), Some(count)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let Ok(count) = count.trim().parse::<f64>() else {
+            continue;
+        };
+        let table = match ngram.chars().count() {
+            1 => &mut freqs.letters,
+            2 => &mut freqs.bigrams,
+            3 => &mut freqs.trigrams,
+            _ => continue,
+        };
+        *table.entry(ngram.to_string()).or_default() += count;
+    }
+    if freqs.letters.is_empty() && freqs.bigrams.is_empty() && freqs.trigrams.is_empty() {
+        bail!("no n-gram counts found");
+    }
+    Ok(freqs.into_count(transliteration))
+}
+
+/// Reads an Oxeylyzer language json (`{"language": ..., "characters": {"e":
+/// 0.1}, "bigrams": ..., "skipgrams": ..., "trigrams": ...}`).
+pub fn load_oxeylyzer(
+    path: impl AsRef<Path>,
+    transliteration: &Transliteration,
+) -> AnyResult<CountOutcome> {
+    let json: OxeylyzerJson = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    let freqs = Frequencies {
+        letters: json.characters,
+        bigrams: json.bigrams,
+        trigrams: json.trigrams,
+        skipgrams: json.skipgrams,
+    };
+    Ok(freqs.into_count(transliteration))
+}
+
+impl Frequencies {
+    /// Each table scaled to [`NORMALIZED_TOTAL`], so that counts in the
+    /// hundreds of billions and fractions alike fit. Characters outside the
+    /// alphabet are transliterated where that keeps the n-gram as long,
+    /// otherwise the n-gram is dropped. Tables without a single lowercase
+    /// letter, such as Norvig's, are read as lowercase rather than as all
+    /// shifted.
+    fn into_count(self, transliteration: &Transliteration) -> CountOutcome {
+        let capitals_only = [&self.letters, &self.bigrams, &self.trigrams]
+            .iter()
+            .flat_map(|table| table.keys())
+            .all(|ngram| !ngram.chars().any(|ch| ch.is_lowercase()));
+        let key = |ch: char| {
+            let ch = match capitals_only {
+                true => ch.to_ascii_lowercase(),
+                false => ch,
+            };
+            let mut typed = match ch.is_ascii() {
+                true => ch.to_string(),
+                false => transliteration.get(ch)?.to_string(),
+            }
+            .into_bytes()
+            .into_iter();
+            match (typed.next(), typed.next()) {
+                (Some(byte), None) => Symbol::from_ascii(byte).filter(|key| key.in_alphabet()),
+                _ => None,
+            }
+        };
+
+        CountOutcome {
+            letter: scale(&self.letters, key),
+            bigrams: scale(&self.bigrams, key),
+            trigrams: scale(&self.trigrams, key),
+            skipgrams: scale(&self.skipgrams, key),
+            ..CountOutcome::default()
+        }
+    }
+}
+
+fn scale<const N: usize>(
+    table: &HashMap<String, f64>,
+    key: impl Fn(char) -> Option<Symbol>,
+) -> FxHashMap<[Symbol; N], u32> {
+    let total = table.values().sum::<f64>();
+    let mut counts = FxHashMap::<[Symbol; N], u32>::default();
+    if total <= 0.0 {
+        return counts;
+    }
+    for (ngram, &freq) in table {
+        let typed = ngram.chars().map(&key).collect::<Option<Vec<_>>>();
+        let Some(Ok(ngram)) = typed.map(<[Symbol; N]>::try_from) else {
+            continue;
+        };
+        let count = (freq / total * NORMALIZED_TOTAL).round() as u32;
+        if count > 0 {
+            *counts.entry(ngram).or_default() += count;
+        }
+    }
+    counts
+}
@@ -6,7 +6,7 @@ pub enum OneIter<I: Iterator> {
 impl<I: Iterator + Clone> Clone for OneIter<I> {
     fn clone(&self) -> Self {
         match self {
-            Self::One(arg0) => Self::One(arg0.clone()),
+            Self::One(arg0) => Self::One(*arg0),
             Self::Iter(arg0) => Self::Iter(arg0.clone()),
         }
     }
@@ -41,36 +41,3 @@ impl<I: Iterator> Iterator for OneIter<I> {
         }
     }
 }
-
-pub trait Step: Copy {
-    fn next_element(self) -> Option<Self>;
-}
-
-#[derive(Debug, Clone, Copy)]
-pub struct Range<T> {
-    at: Option<T>,
-    end: T,
-}
-
-impl<T> Range<T> {
-    pub fn new(at: T, end: T) -> Self {
-        Self { at: Some(at), end }
-    }
-}
-
-impl<T: Step + Eq> Iterator for Range<T> {
-    type Item = T;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let out = self.at;
-        if let Some(now) = out {
-            let next = now.next_element();
-            if next == Some(self.end) {
-                self.at = None;
-            } else {
-                self.at = next;
-            }
-        }
-        out
-    }
-}
@@ -0,0 +1,35 @@
+use crate::{counter::Letters, evaluate::KeyboardLayout, keyboard::Keyboard};
+
+/// Share of typed keys, by corpus frequency, that are pressed by the same
+/// finger on `info`'s keyboard and on `other`. Held shift and layer keys count
+/// as presses too.
+pub fn finger_consistency(info: &KeyboardLayout, other: &Keyboard, letters: &Letters) -> f32 {
+    let keyboard = info.keyboard();
+    let mut same = 0.0;
+    let mut total = 0.0;
+
+    for (&[ch], &freq) in letters {
+        let Some(combos) = info.try_key(ch) else {
+            continue;
+        };
+        let weight = freq as f32 / combos.len() as f32;
+        for combo in combos {
+            for index in std::iter::once(combo.key())
+                .chain(combo.chord())
+                .chain(combo.shift())
+                .chain(combo.layer())
+            {
+                total += weight;
+                if keyboard.key(index).finger() == other.key(index).finger() {
+                    same += weight;
+                }
+            }
+        }
+    }
+
+    if total > 0.0 {
+        same / total
+    } else {
+        1.0
+    }
+}
@@ -1,157 +1,249 @@
-use array_map::Indexable;
+use std::path::Path;
+
+use anyhow::{Context as _, Result as AnyResult};
+use array_map::ArrayMap;
 use glam::Vec2;
-use num_enum::{FromPrimitive, IntoPrimitive};
+use serde::Deserialize;
 
-use crate::iter::Step;
+use crate::calibration::Calibration;
+use crate::core::{BasePositions, FingerTargets, HandModel, KeyTables};
+pub use crate::core::{Finger, Hand, HandFinger, HandFingerIter};
+use crate::evaluate::ComboChoice;
+use crate::shift::ShiftTable;
 
-#[derive(Debug, Clone, Copy, Default, Indexable, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
-#[repr(u8)]
-pub enum Hand {
-    #[default]
-    Left = 0,
-    Right = 1,
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Key {
+    pos: Vec2,
+    finger: HandFinger,
+    #[serde(default)]
+    is_base: bool,
+    /// how far sideways off its own column the finger reaches for this key,
+    /// such as the inner index column; 0 for none
+    #[serde(default)]
+    lateral: f32,
+    /// flat effort of pressing this key at all, for awkward keys that are
+    /// close to the finger's base, such as the bottom row under the ring
+    #[serde(default)]
+    effort: f32,
 }
 
-impl Hand {
-    pub const ALL: [Self; 2] = [Self::Left, Self::Right];
-}
+impl Key {
+    pub fn pos(&self) -> Vec2 {
+        self.pos
+    }
 
-#[derive(Debug, Clone, Copy, Default, Indexable, PartialEq, Eq, IntoPrimitive, FromPrimitive)]
-#[repr(u8)]
-pub enum Finger {
-    #[default]
-    Thumb = 0,
-    Index = 1,
-    Middle = 2,
-    Ring = 3,
-    Pinky = 4,
-}
+    pub fn finger(&self) -> HandFinger {
+        self.finger
+    }
 
-impl Step for Finger {
-    fn next_element(self) -> Option<Self> {
-        match self {
-            Finger::Thumb => Some(Finger::Index),
-            Finger::Index => Some(Finger::Middle),
-            Finger::Middle => Some(Finger::Ring),
-            Finger::Ring => Some(Finger::Pinky),
-            Finger::Pinky => None
-        }
+    pub fn is_base(&self) -> bool {
+        self.is_base
+    }
+
+    pub fn lateral(&self) -> f32 {
+        self.lateral
+    }
+
+    pub fn effort(&self) -> f32 {
+        self.effort
     }
 }
 
-impl Finger {
-    pub const ALL: [Self; 5] = [
-        Self::Thumb,
-        Self::Index,
-        Self::Middle,
-        Self::Ring,
-        Self::Pinky,
-    ];
+/// Geometries bundled with the binary, by name. Their first 34 keys are the
+/// sweep's, in its order, so a 34 key layout fits every one of them; keys
+/// such as an outer pinky column or a number row come after.
+pub const GEOMETRIES: [(&str, &str); 6] = [
+    ("corne", include_str!("../keyboards/corne.toml")),
+    ("kyria", include_str!("../keyboards/kyria.toml")),
+    ("sofle", include_str!("../keyboards/sofle.toml")),
+    ("lily58", include_str!("../keyboards/lily58.toml")),
+    ("piantor", include_str!("../keyboards/piantor.toml")),
+    ("totem", include_str!("../keyboards/totem.toml")),
+];
+
+#[derive(Deserialize)]
+struct KeyboardFile {
+    keys: Vec<Key>,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct HandFinger {
-    pub hand: Hand,
-    pub finger: Finger,
+/// Keys placed on rows and columns, for drawing a layout of a keyboard: the
+/// keys of each column from the bottom up, and the thumb keys left to right
+/// on a row of their own under them.
+#[derive(Debug, Clone)]
+pub struct KeyGrid {
+    /// column and row of every key
+    cells: Vec<(usize, usize)>,
+    columns: usize,
+    rows: usize,
+    /// the rows above the thumb row
+    finger_rows: usize,
+    /// the first column of the right hand
+    middle: usize,
 }
 
-impl HandFinger {
-    pub fn new(hand: Hand, finger: Finger) -> Self {
-        Self { hand, finger }
+impl KeyGrid {
+    pub fn pos(&self, index: usize) -> (usize, usize) {
+        self.cells[index]
+    }
+
+    /// The key at a cell, `None` where there is none.
+    pub fn index(&self, column: usize, row: usize) -> Option<usize> {
+        self.cells.iter().position(|&cell| cell == (column, row))
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn finger_rows(&self) -> usize {
+        self.finger_rows
+    }
+
+    pub fn middle(&self) -> usize {
+        self.middle
     }
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct HandFingerIter {
-    done: bool,
-    hand: Hand,
-    finger: Finger,
+/// Physical keys, indexed in the same order as layout indices.
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    keys: Vec<Key>,
+    /// learned per-key cost on top of the geometry, see `calibration`
+    offsets: Vec<f32>,
+    /// keys of the same hand closer than this to a tap-hold key are rolled
+    /// onto fast enough to trigger its hold
+    misfire_reach: f32,
+    /// share of the presses each finger takes before it is overloaded
+    finger_targets: FingerTargets,
+    /// which characters are typed by shifting which keys
+    shift_table: ShiftTable,
+    hand_model: HandModel,
+    /// how layouts on this keyboard reduce the ways of typing an n-gram
+    combo_choice: ComboChoice,
+    /// costs of every key and pair of keys, rebuilt with the hand model
+    tables: KeyTables,
 }
 
-impl Iterator for HandFingerIter {
-    type Item = HandFinger;
+impl Keyboard {
+    /// Millimetres within which a roll off a tap-hold key counts as a misfire.
+    pub const DEFAULT_MISFIRE_REACH: f32 = 40.0;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.done {
-            return None;
+    pub fn new(keys: Vec<Key>) -> Self {
+        let offsets = vec![0.0; keys.len()];
+        Self {
+            keys,
+            offsets,
+            misfire_reach: Self::DEFAULT_MISFIRE_REACH,
+            finger_targets: FingerTargets::default(),
+            shift_table: ShiftTable::default(),
+            hand_model: HandModel::default(),
+            combo_choice: ComboChoice::default(),
+            tables: KeyTables::default(),
         }
+        .with_tables()
+    }
 
-        let current = HandFinger {
-            hand: self.hand,
-            finger: self.finger,
-        };
-
-        let (next_finger, next_hand) = match self.finger {
-            Finger::Thumb => (Finger::Index, false),
-            Finger::Index => (Finger::Middle, false),
-            Finger::Middle => (Finger::Ring, false),
-            Finger::Ring => (Finger::Pinky, false),
-            Finger::Pinky => (Finger::Thumb, true),
-        };
+    pub fn with_calibration(mut self, calibration: &Calibration) -> Self {
+        for (offset, &learned) in self.offsets.iter_mut().zip(&calibration.offsets) {
+            *offset = learned;
+        }
+        self
+    }
 
-        let (next_hand, alive) = match (self.hand, next_hand) {
-            (Hand::Left, true) => (Hand::Right, true),
-            (Hand::Left, false) => (Hand::Left, true),
-            (Hand::Right, true) => (Hand::Right, false),
-            (Hand::Right, false) => (Hand::Right, true),
-        };
+    pub fn offset(&self, index: usize) -> f32 {
+        self.offsets[index]
+    }
 
-        self.finger = next_finger;
-        self.hand = next_hand;
+    pub fn with_misfire_reach(mut self, reach: f32) -> Self {
+        self.misfire_reach = reach;
+        self
+    }
 
-        if !alive {
-            self.done = true;
-        }
+    pub fn misfire_reach(&self) -> f32 {
+        self.misfire_reach
+    }
 
-        Some(current)
+    pub fn with_finger_targets(mut self, targets: FingerTargets) -> Self {
+        self.finger_targets = targets;
+        self
     }
-}
 
-unsafe impl Indexable for HandFinger {
-    const SIZE: usize = 10;
+    pub fn finger_targets(&self) -> &FingerTargets {
+        &self.finger_targets
+    }
 
-    const SET_SIZE: usize = array_map::set_size(Self::SIZE);
+    pub fn with_shift_table(mut self, table: ShiftTable) -> Self {
+        self.shift_table = table;
+        self
+    }
 
-    type Iter = HandFingerIter;
+    pub fn shift_table(&self) -> &ShiftTable {
+        &self.shift_table
+    }
 
-    fn index(self) -> usize {
-        usize::from(5 * u8::from(self.hand) + u8::from(self.finger))
+    pub fn with_combo_choice(mut self, choice: ComboChoice) -> Self {
+        self.combo_choice = choice;
+        self
     }
 
-    fn iter() -> Self::Iter {
-        HandFingerIter::default()
+    pub fn combo_choice(&self) -> ComboChoice {
+        self.combo_choice
     }
-}
 
-#[derive(Debug, Clone, Copy)]
-pub struct Key {
-    pos: Vec2,
-    finger: HandFinger,
-    is_base: bool,
-}
+    pub fn with_hand_model(mut self, hands: HandModel) -> Self {
+        self.hand_model = hands;
+        self.with_tables()
+    }
 
-impl Key {
-    pub fn pos(&self) -> Vec2 {
-        self.pos
+    fn with_tables(mut self) -> Self {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| (key.pos, key.finger))
+            .collect::<Vec<_>>();
+        self.tables = KeyTables::new(&self.hand_model, &self.base_positions(), &keys);
+        self
     }
 
-    pub fn finger(&self) -> HandFinger {
-        self.finger
+    pub fn tables(&self) -> &KeyTables {
+        &self.tables
     }
 
-    pub fn is_base(&self) -> bool {
-        self.is_base
+    pub fn hand_model(&self) -> &HandModel {
+        &self.hand_model
     }
-}
 
-#[derive(Debug, Clone)]
-pub struct Keyboard {
-    keys: Vec<Key>,
-}
+    /// Reads a geometry file: `{"keys": [{"pos": [x, y], "finger": {...}, "is_base": true,
+    /// "lateral": 1.0, "effort": 0.5}]}` with positions in millimetres, in layout index order.
+    /// Files ending in `.toml` hold the same `keys`, as the bundled [`GEOMETRIES`] do.
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)?;
+        let file: KeyboardFile = match path.extension() {
+            Some(ext) if ext == "toml" => toml::from_str(&data)?,
+            _ => serde_json::from_str(&data)?,
+        };
+        Ok(Self::new(file.keys))
+    }
 
-impl Keyboard {
-    pub fn new(keys: Vec<Key>) -> Self {
-        Self { keys }
+    /// A built-in or bundled geometry by name, or a geometry file.
+    pub fn by_name(name: &str) -> AnyResult<Self> {
+        match name {
+            "ferris_sweep" | "sweep" => Ok(Self::ferris_sweep()),
+            "laptop" => Ok(Self::laptop()),
+            _ => match GEOMETRIES.iter().find(|&&(n, _)| n == name) {
+                Some(&(name, text)) => {
+                    let file: KeyboardFile =
+                        toml::from_str(text).with_context(|| format!("bundled keyboard {name}"))?;
+                    Ok(Self::new(file.keys))
+                }
+                None => Self::load(name),
+            },
+        }
     }
 
     pub fn keys(&self) -> &[Key] {
@@ -162,10 +254,102 @@ impl Keyboard {
         self.keys[index]
     }
 
+    /// Where each finger rests, NaN for a finger without a base key.
+    pub fn base_positions(&self) -> BasePositions {
+        let mut base = ArrayMap::new([Vec2::NAN; 10]);
+        for key in &self.keys {
+            if key.is_base {
+                base[key.finger] = key.pos;
+            }
+        }
+        base
+    }
+
+    /// The key whose centre is closest to `point`.
+    pub fn nearest_key(&self, point: Vec2) -> usize {
+        (0..self.keys.len())
+            .min_by(|&a, &b| {
+                let a = self.keys[a].pos.distance_squared(point);
+                let b = self.keys[b].pos.distance_squared(point);
+                a.total_cmp(&b)
+            })
+            .unwrap()
+    }
+
+    /// Indices of every key pressed by a thumb.
+    pub fn thumb_keys(&self) -> Vec<usize> {
+        (0..self.keys.len())
+            .filter(|&i| self.keys[i].finger.finger == Finger::Thumb)
+            .collect()
+    }
+
+    /// Where the first `keys` keys, as many as a layout has, are drawn. The
+    /// keys a finger reaches as far sideways for make up a column.
+    pub fn grid(&self, keys: usize) -> KeyGrid {
+        let keys = &self.keys[..keys.min(self.keys.len())];
+        let mut thumbs = vec![];
+        let mut columns: Vec<Vec<usize>> = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            if key.finger.finger == Finger::Thumb {
+                thumbs.push(i);
+                continue;
+            }
+            let column = columns.iter_mut().find(|column| {
+                let other = &keys[column[0]];
+                other.finger == key.finger && other.lateral == key.lateral
+            });
+            match column {
+                Some(column) => column.push(i),
+                None => columns.push(vec![i]),
+            }
+        }
+        let mean_x = |column: &[usize]| {
+            column.iter().map(|&i| keys[i].pos.x).sum::<f32>() / column.len() as f32
+        };
+        columns.sort_by(|a, b| mean_x(a).total_cmp(&mean_x(b)));
+        thumbs.sort_by(|&a, &b| keys[a].pos.x.total_cmp(&keys[b].pos.x));
+
+        let rows = columns.iter().map(Vec::len).max().unwrap_or(0);
+        // both rows meet where the hands do
+        let left = |part: &[usize]| part.partition_point(|&i| keys[i].finger.hand == Hand::Left);
+        let left_columns =
+            columns.partition_point(|column| keys[column[0]].finger.hand == Hand::Left);
+        let middle = left_columns.max(left(&thumbs));
+        let width = middle + (columns.len() - left_columns).max(thumbs.len() - left(&thumbs));
+
+        let mut cells = vec![(0, 0); keys.len()];
+        for (c, column) in columns.iter_mut().enumerate() {
+            column.sort_by(|&a, &b| keys[a].pos.y.total_cmp(&keys[b].pos.y));
+            // a short column lines up with the bottom of the others
+            let top = rows - column.len();
+            for (r, &i) in column.iter().enumerate() {
+                cells[i] = (middle - left_columns + c, top + r);
+            }
+        }
+        for (c, &i) in thumbs.iter().enumerate() {
+            cells[i] = (middle - left(&thumbs) + c, rows);
+        }
+        KeyGrid {
+            cells,
+            columns: width,
+            rows: rows + usize::from(!thumbs.is_empty()),
+            finger_rows: rows,
+            middle,
+        }
+    }
+
     pub fn ferris_sweep() -> Self {
         const X_SPACING: f32 = 18.0;
         const Y_SPACING: f32 = 17.0;
         const Y_STAGGER: [f32; 5] = [19.0, 7.0, 0.0, 5.5, 8.0];
+        // the index finger reaches sideways for the inner column
+        const LATERAL: [f32; 5] = [0.0, 0.0, 0.0, 0.0, 1.0];
+        // curling the ring and pinky under is awkward however close the key
+        const EFFORT: [[f32; 5]; 3] = [
+            [0.6, 0.4, 0.2, 0.2, 0.4],
+            [0.2, 0.1, 0.0, 0.0, 0.3],
+            [0.8, 0.8, 0.4, 0.2, 0.5],
+        ];
         const FINGERS: [Finger; 5] = [
             Finger::Pinky,
             Finger::Ring,
@@ -192,6 +376,8 @@ impl Keyboard {
                             pos,
                             finger: HandFinger::new(hand, finger),
                             is_base: iy == 1 && ix != 4 && ix != 5,
+                            lateral: LATERAL[finger_index],
+                            effort: EFFORT[iy][finger_index],
                         }
                     })
                 })
@@ -202,8 +388,108 @@ impl Keyboard {
                         Finger::Thumb,
                     ),
                     is_base: i == 1 || i == 2,
+                    lateral: 0.0,
+                    effort: 0.0,
+                }))
+                .collect(),
+        )
+    }
+
+    /// A row-staggered laptop keyboard typed with the usual fingering. The
+    /// alpha indices sit on the qwerty block and the thumbs on left alt,
+    /// space, right alt and right ctrl, like the host exports.
+    pub fn laptop() -> Self {
+        const UNIT: f32 = 19.0;
+        const ROW_STAGGER: [f32; 3] = [0.0, 0.25, 0.75];
+        const LATERAL: [f32; 5] = [0.0, 0.0, 0.0, 0.0, 1.0];
+        const EFFORT: [[f32; 5]; 3] = [
+            [0.6, 0.4, 0.2, 0.2, 0.4],
+            [0.2, 0.1, 0.0, 0.0, 0.3],
+            [1.0, 0.8, 0.4, 0.2, 0.5],
+        ];
+        const FINGERS: [Finger; 5] = [
+            Finger::Pinky,
+            Finger::Ring,
+            Finger::Middle,
+            Finger::Index,
+            Finger::Index,
+        ];
+        const THUMBS: [(f32, Hand); 4] = [
+            (2.5, Hand::Left),
+            (4.0, Hand::Left),
+            (7.0, Hand::Right),
+            (8.0, Hand::Right),
+        ];
+
+        Self::new(
+            (0..10)
+                .flat_map(|ix| {
+                    let (finger_index, hand) = match ix {
+                        0..5 => (ix, Hand::Left),
+                        5..10 => (9 - ix, Hand::Right),
+                        _ => unreachable!(),
+                    };
+                    let finger = FINGERS[finger_index];
+                    (0..3).map(move |iy| Key {
+                        pos: Vec2::new(ix as f32 + ROW_STAGGER[iy], iy as f32) * UNIT,
+                        finger: HandFinger::new(hand, finger),
+                        is_base: iy == 1 && ix != 4 && ix != 5,
+                        lateral: LATERAL[finger_index],
+                        effort: EFFORT[iy][finger_index],
+                    })
+                })
+                .chain(THUMBS.iter().enumerate().map(|(i, &(x, hand))| Key {
+                    pos: Vec2::new(x, 3.0) * UNIT,
+                    finger: HandFinger::new(hand, Finger::Thumb),
+                    is_base: i == 1 || i == 2,
+                    lateral: 0.0,
+                    effort: 0.0,
                 }))
                 .collect(),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        counter::{self, NgramDepth, TextOptions},
+        evaluate::{self, EvalWeights, KeyboardLayout},
+        ferris,
+    };
+
+    #[test]
+    fn every_geometry_evaluates_and_draws() {
+        let text = "The quick brown fox jumps over the lazy dog; fn main() { x[0] = 1 }\n";
+        let count = counter::count_reader(
+            text.as_bytes(),
+            NgramDepth::Trigrams,
+            &TextOptions::default(),
+        )
+        .unwrap();
+        let layout = ferris::qwerty();
+        let names = ["sweep", "laptop"]
+            .into_iter()
+            .chain(GEOMETRIES.iter().map(|&(name, _)| name));
+        for name in names {
+            let keyboard = Keyboard::by_name(name).unwrap();
+            let info = KeyboardLayout::generate(&layout, &keyboard).unwrap();
+            let score = EvalWeights::default().score(&evaluate::evaluate(&info, &count));
+            assert!(score.is_finite(), "{name} scores {score}");
+
+            let grid = keyboard.grid(keyboard.keys().len());
+            let mut cells: Vec<_> = (0..keyboard.keys().len()).map(|i| grid.pos(i)).collect();
+            assert!(cells
+                .iter()
+                .all(|&(c, r)| c < grid.columns() && r < grid.rows()));
+            cells.sort();
+            cells.dedup();
+            assert_eq!(
+                cells.len(),
+                keyboard.keys().len(),
+                "{name} draws keys over each other"
+            );
+        }
+    }
+}
@@ -1,25 +1,33 @@
+use anyhow::{bail, Context as _, Result as AnyResult};
 use serde::{Deserialize, Serialize};
-use std::{char::TryFromCharError, num::NonZeroU8};
+use std::{num::NonZeroU8, path::Path, slice::ChunksExact};
+
+use crate::symbol::Symbol;
 
 #[derive(Serialize, Deserialize)]
 struct SerdeBehaviors(String);
 
-impl From<SerdeBehaviors> for BaseBehavior {
-    fn from(value: SerdeBehaviors) -> Self {
-        Self(
-            value
-                .0
-                .chars()
-                .map(|ch| match ch {
-                    ' ' => None,
-                    'S' => Some(Behavior::Shift),
-                    c if c.is_ascii_digit() && c != '0' => Some(Behavior::Layer(
-                        NonZeroU8::new(c.to_digit(10).unwrap() as u8).unwrap(),
-                    )),
-                    _ => unreachable!(),
-                })
-                .collect(),
-        )
+impl TryFrom<SerdeBehaviors> for BaseBehavior {
+    type Error = String;
+
+    fn try_from(value: SerdeBehaviors) -> Result<Self, Self::Error> {
+        value
+            .0
+            .chars()
+            .enumerate()
+            .map(|(i, ch)| match ch {
+                ' ' => Ok(None),
+                'S' => Ok(Some(Behavior::Shift)),
+                'C' => Ok(Some(Behavior::Ctrl)),
+                'A' => Ok(Some(Behavior::Alt)),
+                'G' => Ok(Some(Behavior::Gui)),
+                '1'..='9' => Ok(NonZeroU8::new(ch as u8 - b'0').map(Behavior::Layer)),
+                _ => Err(format!(
+                    "base_hold position {i}: {ch:?} is no hold, expected ' ', S, C, A, G or a layer 1-9"
+                )),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
     }
 }
 
@@ -31,6 +39,9 @@ impl From<BaseBehavior> for SerdeBehaviors {
                 .into_iter()
                 .map(|b| match b {
                     Some(Behavior::Shift) => 'S',
+                    Some(Behavior::Ctrl) => 'C',
+                    Some(Behavior::Alt) => 'A',
+                    Some(Behavior::Gui) => 'G',
                     Some(Behavior::Layer(layer)) => char::from(b'0' + layer.get()),
                     None => ' ',
                 })
@@ -39,9 +50,13 @@ impl From<BaseBehavior> for SerdeBehaviors {
     }
 }
 
+/// What a key does while held.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Behavior {
     Shift,
+    Ctrl,
+    Alt,
+    Gui,
     Layer(NonZeroU8),
 }
 
@@ -56,9 +71,9 @@ impl From<LayoutLayer> for SerdeLayer {
                 .keys
                 .into_iter()
                 .map(|x| {
-                    x.map_or(' ', |v| match char::from(v.get()) {
-                        ' ' => 'S',
-                        x => x,
+                    x.map_or(' ', |v| match v {
+                        Symbol::SPACE => 'S',
+                        v => v.char(),
                     })
                 })
                 .collect(),
@@ -67,70 +82,159 @@ impl From<LayoutLayer> for SerdeLayer {
 }
 
 impl TryFrom<SerdeLayer> for LayoutLayer {
-    type Error = TryFromCharError;
+    type Error = String;
 
     fn try_from(value: SerdeLayer) -> Result<Self, Self::Error> {
         value
             .0
             .chars()
-            .map(|x| {
-                u8::try_from(match x {
-                    'S' => ' ',
-                    ' ' => '\0',
-                    _ => x,
-                })
-                .map(NonZeroU8::new)
+            .enumerate()
+            .map(|(i, ch)| match ch {
+                'S' => Ok(Some(Symbol::SPACE)),
+                ' ' => Ok(None),
+                _ => match Symbol::from_char(ch).filter(|symbol| symbol.in_alphabet()) {
+                    Some(symbol) => Ok(Some(symbol)),
+                    None => Err(format!("position {i}: {ch:?} is not a key of the alphabet")),
+                },
             })
             .collect::<Result<_, _>>()
-            .map(|v| Self::new(v))
+            .map(Self::new)
     }
 }
 
+/// The tapped key at every index of one layer, `None` if transparent.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(try_from = "SerdeLayer", into = "SerdeLayer")]
 pub struct LayoutLayer {
-    keys: Vec<Option<NonZeroU8>>,
+    keys: Vec<Option<Symbol>>,
 }
 
 impl LayoutLayer {
-    pub fn new(keys: Vec<Option<NonZeroU8>>) -> Self {
+    pub fn new(keys: Vec<Option<Symbol>>) -> Self {
         Self { keys }
     }
 
-    pub fn into_keys(self) -> Vec<Option<NonZeroU8>> {
+    pub fn into_keys(self) -> Vec<Option<Symbol>> {
         self.keys
     }
 
-    pub fn set_key(&mut self, index: usize, key: Option<NonZeroU8>) {
+    pub fn set_key(&mut self, index: usize, key: Option<Symbol>) {
         self.keys[index] = key;
     }
 
-    pub fn keys(&self) -> &[Option<NonZeroU8>] {
+    pub fn keys(&self) -> &[Option<Symbol>] {
         &self.keys
     }
 
-    pub fn keys_mut(&mut self) -> &mut Vec<Option<NonZeroU8>> {
-        &mut self.keys
-    }
-
     pub fn len(&self) -> usize {
         self.keys.len()
     }
 
-    pub fn key_mut(&mut self, index: usize) -> &mut Option<NonZeroU8> {
-        &mut self.keys[index]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
     }
 }
 
+/// Two base layer keys pressed together to type `output`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combo {
+    pub keys: [usize; 2],
+    pub output: Symbol,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(from = "SerdeBehaviors", into = "SerdeBehaviors")]
+#[serde(try_from = "SerdeBehaviors", into = "SerdeBehaviors")]
 struct BaseBehavior(Vec<Option<Behavior>>);
 
+/// A layout as written, checked before it becomes a [`Layout`].
+#[derive(Serialize, Deserialize)]
+struct SerdeLayout {
+    size: usize,
+    base_hold: SerdeBehaviors,
+    layers: Vec<SerdeLayer>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    combos: Vec<Combo>,
+}
+
+impl From<Layout> for SerdeLayout {
+    fn from(value: Layout) -> Self {
+        let layers = value
+            .layers()
+            .map(|layer| LayoutLayer::new(layer.to_vec()).into())
+            .collect();
+        Self {
+            size: value.size,
+            base_hold: BaseBehavior(value.base_hold.into_vec()).into(),
+            layers,
+            combos: value.combos,
+        }
+    }
+}
+
+impl TryFrom<SerdeLayout> for Layout {
+    type Error = String;
+
+    fn try_from(value: SerdeLayout) -> Result<Self, Self::Error> {
+        let size = value.size;
+        let base_hold = BaseBehavior::try_from(value.base_hold)?;
+        if base_hold.0.len() != size {
+            return Err(format!(
+                "base_hold has {} keys, not size {size}",
+                base_hold.0.len()
+            ));
+        }
+        if value.layers.is_empty() {
+            return Err("no layers, not even the base layer".to_string());
+        }
+        let layers = value
+            .layers
+            .into_iter()
+            .enumerate()
+            .map(|(li, layer)| {
+                let layer =
+                    LayoutLayer::try_from(layer).map_err(|err| format!("layer {li} {err}"))?;
+                match layer.len() == size {
+                    true => Ok(layer),
+                    false => Err(format!(
+                        "layer {li} has {} keys, not size {size}",
+                        layer.len()
+                    )),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (i, hold) in base_hold.0.iter().enumerate() {
+            if let Some(Behavior::Layer(layer)) = hold {
+                if usize::from(layer.get()) >= layers.len() {
+                    return Err(format!(
+                        "base_hold position {i} holds layer {layer}, past the last layer"
+                    ));
+                }
+            }
+        }
+        for combo in &value.combos {
+            if let Some(&key) = combo.keys.iter().find(|&&key| key >= size) {
+                return Err(format!(
+                    "combo for {:?} presses key {key}, past size {size}",
+                    combo.output
+                ));
+            }
+        }
+        let mut layout = Self::new(base_hold.0, layers);
+        layout.combos = value.combos;
+        Ok(layout)
+    }
+}
+
+/// A stack of layers over the same keys, plus the hold behaviors of the base
+/// layer. The keys of all layers share one slice, so a clone is a few copies.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "SerdeLayout", into = "SerdeLayout")]
 pub struct Layout {
     size: usize,
-    base_hold: BaseBehavior,
-    layers: Vec<LayoutLayer>,
+    base_hold: Box<[Option<Behavior>]>,
+    /// the `size` keys of each layer in turn
+    keys: Box<[Option<Symbol>]>,
+    combos: Vec<Combo>,
 }
 
 impl Layout {
@@ -141,29 +245,49 @@ impl Layout {
             .for_each(|layer| assert_eq!(layer.len(), size));
         Self {
             size,
-            base_hold: BaseBehavior(base_hold),
-            layers,
+            base_hold: base_hold.into(),
+            keys: layers
+                .into_iter()
+                .flat_map(LayoutLayer::into_keys)
+                .collect(),
+            combos: vec![],
         }
     }
 
-    pub fn into_parts(self) -> (Vec<Option<Behavior>>, Vec<LayoutLayer>) {
-        (self.base_hold.0, self.layers)
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("cannot read {}", path.display()))?;
+        serde_json::from_str(&data).with_context(|| format!("bad layout {}", path.display()))
     }
 
-    pub fn layers(&self) -> &[LayoutLayer] {
-        &self.layers
+    pub fn save(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+        Ok(())
     }
 
-    pub fn layers_mut(&mut self) -> &mut Vec<LayoutLayer> {
-        &mut self.layers
+    pub fn into_parts(self) -> (Vec<Option<Behavior>>, Vec<LayoutLayer>) {
+        let layers = self
+            .layers()
+            .map(|layer| LayoutLayer::new(layer.to_vec()))
+            .collect();
+        (self.base_hold.into_vec(), layers)
+    }
+
+    /// The keys of each layer, `None` where it is transparent.
+    pub fn layers(&self) -> ChunksExact<'_, Option<Symbol>> {
+        self.keys.chunks_exact(self.size.max(1))
     }
 
-    pub fn layer(&self, layer: u8) -> &LayoutLayer {
-        &self.layers[usize::from(layer)]
+    pub fn layer(&self, layer: u8) -> &[Option<Symbol>] {
+        let start = usize::from(layer) * self.size;
+        &self.keys[start..start + self.size]
     }
 
-    pub fn layer_mut(&mut self, layer: u8) -> &mut LayoutLayer {
-        &mut self.layers[usize::from(layer)]
+    pub fn layer_mut(&mut self, layer: u8) -> &mut [Option<Symbol>] {
+        let start = usize::from(layer) * self.size;
+        &mut self.keys[start..start + self.size]
     }
 
     pub fn layer_count(&self) -> u8 {
@@ -171,64 +295,295 @@ impl Layout {
     }
 
     pub fn layer_size(&self) -> usize {
-        self.layers[0].len()
+        self.size
+    }
+
+    pub fn key(&self, layer: u8, index: usize) -> Option<Symbol> {
+        self.layer(layer)[index]
+    }
+
+    /// Like [`Layout::key_at`], but an error naming what is missing where
+    /// [`Layout::key_at`] would panic, for locations from another layout.
+    pub fn checked_key_at(&self, loc: KeyLoc) -> AnyResult<Option<Symbol>> {
+        if loc.layer >= self.layer_count() {
+            bail!(
+                "no layer {} in a layout of {} layers",
+                loc.layer,
+                self.layer_count()
+            );
+        }
+        if loc.index >= self.size {
+            bail!(
+                "no key {} in a layout of {} keys a layer",
+                loc.index,
+                self.size
+            );
+        }
+        Ok(self.key_at(loc))
     }
 
-    pub fn key(&self, layer: u8, index: usize) -> Option<NonZeroU8> {
-        self.layers[layer as usize].keys[index]
+    pub fn key_at(&self, loc: KeyLoc) -> Option<Symbol> {
+        self.key(loc.layer, loc.index)
     }
 
-    pub fn first_layer(&self) -> &LayoutLayer {
-        self.layers().first().unwrap()
+    pub fn set_key_at(&mut self, loc: KeyLoc, key: Option<Symbol>) {
+        self.layer_mut(loc.layer)[loc.index] = key;
+    }
+
+    /// Every key location, empty or not, layer by layer.
+    pub fn locs(&self) -> impl Iterator<Item = KeyLoc> + use<> {
+        let size = self.layer_size();
+        (0..self.layer_count()).flat_map(move |layer| (0..size).map(move |i| KeyLoc::new(layer, i)))
+    }
+
+    /// Every key that types a symbol, layer by layer.
+    pub fn iter_keys(&self) -> impl Iterator<Item = (KeyLoc, Symbol)> + '_ {
+        self.layers().enumerate().flat_map(|(li, layer)| {
+            layer
+                .iter()
+                .enumerate()
+                .filter_map(move |(i, key)| key.map(|key| (KeyLoc::new(li as u8, i), key)))
+        })
+    }
+
+    /// Every base key with a hold and its index.
+    pub fn iter_holds(&self) -> impl Iterator<Item = (usize, Behavior)> + '_ {
+        self.base_hold()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, hold)| hold.map(|hold| (i, hold)))
+    }
+
+    pub fn first_layer(&self) -> &[Option<Symbol>] {
+        self.layer(0)
     }
 
     pub fn find_on_base<F: FnMut(Behavior) -> bool + Copy>(
         &self,
         mut func: F,
     ) -> impl Iterator<Item = KeyLoc> + use<'_, F> {
-        self.base_hold()
-            .iter()
-            .enumerate()
-            .filter_map(move |(i, l_key)| {
-                l_key
-                    .as_ref()
-                    .copied()
-                    .is_some_and(&mut func)
-                    .then_some(KeyLoc::new(0, i))
-            })
+        self.iter_holds()
+            .filter(move |&(_, hold)| func(hold))
+            .map(|(i, _)| KeyLoc::new(0, i))
     }
 
-    pub fn find_all_key<F: FnMut(NonZeroU8) -> bool + Copy>(
+    pub fn find_all_key<F: FnMut(Symbol) -> bool + Copy>(
         &self,
         mut func: F,
     ) -> impl Iterator<Item = KeyLoc> + use<'_, F> {
-        self.layers()
+        self.iter_keys()
+            .filter(move |&(_, key)| func(key))
+            .map(|(loc, _)| loc)
+    }
+
+    pub fn base_hold(&self) -> &[Option<Behavior>] {
+        &self.base_hold
+    }
+
+    pub fn base_hold_mut(&mut self) -> &mut [Option<Behavior>] {
+        &mut self.base_hold
+    }
+
+    pub fn combos(&self) -> &[Combo] {
+        &self.combos
+    }
+
+    pub fn combos_mut(&mut self) -> &mut Vec<Combo> {
+        &mut self.combos
+    }
+
+    /// This layout on a keyboard of `map.len()` keys, where key `i` gets the
+    /// taps and hold of key `map[i]` here, or nothing if `None`. Combos follow
+    /// their keys and are dropped with them.
+    pub fn remap(&self, map: &[Option<usize>]) -> Self {
+        let pick = |keys: &[Option<Symbol>]| {
+            map.iter()
+                .map(|&old| old.and_then(|old| keys[old]))
+                .collect()
+        };
+        let base_hold = map
             .iter()
-            .enumerate()
-            .flat_map(move |(li, layer)| {
-                layer
-                    .keys()
-                    .iter()
-                    .enumerate()
-                    .filter_map(move |(i, l_key)| {
-                        l_key
-                            .as_ref()
-                            .copied()
-                            .is_some_and(&mut func)
-                            .then_some(KeyLoc::new(li as u8, i))
-                    })
+            .map(|&old| old.and_then(|old| self.base_hold()[old]))
+            .collect();
+        let layers = self
+            .layers()
+            .map(|layer| LayoutLayer::new(pick(layer)))
+            .collect();
+        let mut layout = Self::new(base_hold, layers);
+        let new_index = |old: usize| map.iter().position(|&i| i == Some(old));
+        layout.combos = self
+            .combos
+            .iter()
+            .filter_map(|combo| {
+                let [a, b] = combo.keys;
+                Some(Combo {
+                    keys: [new_index(a)?, new_index(b)?],
+                    output: combo.output,
+                })
             })
+            .collect();
+        layout
     }
 
-    pub fn base_hold(&self) -> &[Option<Behavior>] {
-        &self.base_hold.0
+    /// Adds an empty layer on top, reached by no key until one holds it.
+    pub fn add_layer(&mut self) -> AnyResult<()> {
+        // holds are written as one digit
+        if self.layer_count() >= 10 {
+            bail!("a layout holds at most 9 layers over the base");
+        }
+        let mut keys = std::mem::take(&mut self.keys).into_vec();
+        keys.resize(keys.len() + self.size, None);
+        self.keys = keys.into();
+        Ok(())
     }
 
-    pub fn base_hold_mut(&mut self) -> &mut Vec<Option<Behavior>> {
-        &mut self.base_hold.0
+    /// Removes `layer` and the holds to it, renumbering the holds to the
+    /// layers above it.
+    pub fn remove_layer(&mut self, layer: u8) -> AnyResult<()> {
+        if layer == 0 || layer >= self.layer_count() {
+            bail!("no layer {layer} to remove, the base layer stays");
+        }
+        let start = usize::from(layer) * self.size;
+        let mut keys = std::mem::take(&mut self.keys).into_vec();
+        keys.drain(start..start + self.size);
+        self.keys = keys.into();
+        for hold in self.base_hold_mut() {
+            if let Some(Behavior::Layer(held)) = *hold {
+                *hold = match held.get().cmp(&layer) {
+                    std::cmp::Ordering::Less => *hold,
+                    std::cmp::Ordering::Equal => None,
+                    std::cmp::Ordering::Greater => {
+                        NonZeroU8::new(held.get() - 1).map(Behavior::Layer)
+                    }
+                };
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a key map for [`Layout::remap`]: for each new key in order, the
+/// index of the old key it takes or `-` for an empty one, separated by commas,
+/// with `a..b` for a run of old keys. `0..27,30..34` drops the three keys
+/// from 27 of a 34 key layout, and `0..30,-,30..34,-` adds a thumb key at
+/// each end of the thumb cluster.
+pub fn parse_key_map(spec: &str, old_size: usize) -> AnyResult<Vec<Option<usize>>> {
+    let mut map = vec![];
+    for part in spec.split(',').map(str::trim) {
+        let index = |text: &str| -> AnyResult<usize> {
+            let index = text
+                .parse::<usize>()
+                .with_context(|| format!("bad key index {text:?}"))?;
+            if index >= old_size {
+                bail!("key {index} is past the {old_size} keys of the layout");
+            }
+            Ok(index)
+        };
+        match part.split_once("..") {
+            _ if part == "-" => map.push(None),
+            Some((start, end)) => {
+                let (start, end) = (index(start)?, end.parse::<usize>().unwrap_or(usize::MAX));
+                if end > old_size || end < start {
+                    bail!("bad key range {part:?}");
+                }
+                map.extend((start..end).map(Some));
+            }
+            None => map.push(Some(index(part)?)),
+        }
+    }
+    Ok(map)
+}
+
+/// How the layers of a layout are reached from the base layer, see
+/// [`Layout::reachability`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reachability {
+    /// every key that activates a layer, and the layer
+    pub activations: Vec<(KeyLoc, u8)>,
+    /// layers with keys on them that no chain of activations from the base
+    /// layer gets to; empty layers, as [`Layout::add_layer`] makes them, are
+    /// left out
+    pub unreachable: Vec<u8>,
+    /// layers that activate one another in a loop, each loop once
+    pub cycles: Vec<Vec<u8>>,
+}
+
+impl Reachability {
+    /// Fails naming the stranded layers and the loops.
+    pub fn check(&self) -> AnyResult<()> {
+        let list = |layers: &[u8]| {
+            layers
+                .iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !self.unreachable.is_empty() {
+            bail!(
+                "no key activates layer {} from the base layer",
+                list(&self.unreachable)
+            );
+        }
+        if let Some(cycle) = self.cycles.first() {
+            bail!("layers {} activate one another in a loop", list(cycle));
+        }
+        Ok(())
+    }
+}
+
+impl Layout {
+    /// Which layers activate which and what is left out. Only the holds of
+    /// the base layer activate layers so far, which cannot loop.
+    pub fn reachability(&self) -> Reachability {
+        let layers = usize::from(self.layer_count());
+        let activations = self
+            .iter_holds()
+            .filter_map(|(i, hold)| match hold {
+                Behavior::Layer(layer) => Some((KeyLoc::new(0, i), layer.get())),
+                _ => None,
+            })
+            .filter(|&(_, layer)| usize::from(layer) < layers)
+            .collect::<Vec<_>>();
+
+        // reach[a][b]: a chain of one activation or more goes from layer a to b
+        let mut reach = vec![vec![false; layers]; layers];
+        for &(loc, layer) in &activations {
+            reach[usize::from(loc.layer)][usize::from(layer)] = true;
+        }
+        for via in 0..layers {
+            let onward = reach[via].clone();
+            for row in reach.iter_mut().filter(|row| row[via]) {
+                row.iter_mut()
+                    .zip(&onward)
+                    .for_each(|(to, &onward)| *to |= onward);
+            }
+        }
+
+        let unreachable = (1..layers)
+            .filter(|&layer| {
+                !reach[0][layer] && self.layer(layer as u8).iter().any(Option::is_some)
+            })
+            .map(|layer| layer as u8)
+            .collect();
+        let cycles = (0..layers)
+            .filter(|&layer| reach[layer][layer])
+            .filter_map(|layer| {
+                let cycle = (0..layers)
+                    .filter(|&other| reach[layer][other] && reach[other][layer])
+                    .collect::<Vec<_>>();
+                // listed from its lowest layer
+                (cycle[0] == layer).then(|| cycle.into_iter().map(|l| l as u8).collect())
+            })
+            .collect();
+        Reachability {
+            activations,
+            unreachable,
+            cycles,
+        }
     }
 }
 
+/// A key index on a specific layer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct KeyLoc {
     layer: u8,
@@ -248,3 +603,117 @@ impl KeyLoc {
         self.index
     }
 }
+
+/// A change to a layout that [`Layout::apply`] can undo.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    SetKey(KeyLoc, Option<Symbol>),
+    SwapKeys(KeyLoc, KeyLoc),
+    SetHold(usize, Option<Behavior>),
+    SwapHolds(usize, usize),
+    /// moves combo `.0` onto the keys `.1`
+    MoveCombo(usize, [usize; 2]),
+}
+
+impl Layout {
+    /// Makes `edit`, returning the edit that undoes it.
+    pub fn apply(&mut self, edit: Edit) -> Edit {
+        match edit {
+            Edit::SetKey(loc, key) => {
+                let old = self.key_at(loc);
+                self.set_key_at(loc, key);
+                Edit::SetKey(loc, old)
+            }
+            Edit::SwapKeys(a, b) => {
+                let (ka, kb) = (self.key_at(a), self.key_at(b));
+                self.set_key_at(a, kb);
+                self.set_key_at(b, ka);
+                edit
+            }
+            Edit::SetHold(index, hold) => Edit::SetHold(
+                index,
+                std::mem::replace(&mut self.base_hold_mut()[index], hold),
+            ),
+            Edit::SwapHolds(a, b) => {
+                self.base_hold_mut().swap(a, b);
+                edit
+            }
+            Edit::MoveCombo(combo, keys) => {
+                Edit::MoveCombo(combo, std::mem::replace(&mut self.combos[combo].keys, keys))
+            }
+        }
+    }
+}
+
+/// The edits made to a layout through it, to undo and redo. Edits between
+/// [`Self::begin`] and [`Self::commit`] are undone together, or thrown away
+/// with [`Self::rollback`]; any other edit is undone on its own.
+#[derive(Debug, Clone, Default)]
+pub struct EditLog {
+    /// the edits undoing each group, in the order they were made
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    open: Option<Vec<Edit>>,
+}
+
+impl EditLog {
+    pub fn apply(&mut self, layout: &mut Layout, edit: Edit) {
+        let inverse = layout.apply(edit);
+        self.redo.clear();
+        match &mut self.open {
+            Some(group) => group.push(inverse),
+            None => self.undo.push(vec![inverse]),
+        }
+    }
+
+    pub fn begin(&mut self) {
+        self.open.get_or_insert_with(Vec::new);
+    }
+
+    pub fn commit(&mut self) {
+        if let Some(group) = self.open.take().filter(|group| !group.is_empty()) {
+            self.undo.push(group);
+        }
+    }
+
+    /// Undoes the edits since [`Self::begin`].
+    pub fn rollback(&mut self, layout: &mut Layout) {
+        if let Some(group) = self.open.take() {
+            revert(layout, group);
+        }
+    }
+
+    /// Undoes the last group, returning whether there was one.
+    pub fn undo(&mut self, layout: &mut Layout) -> bool {
+        self.commit();
+        let Some(group) = self.undo.pop() else {
+            return false;
+        };
+        self.redo.push(revert(layout, group));
+        true
+    }
+
+    /// Makes the last undone group again, returning whether there was one.
+    pub fn redo(&mut self, layout: &mut Layout) -> bool {
+        let Some(group) = self.redo.pop() else {
+            return false;
+        };
+        self.undo.push(revert(layout, group));
+        true
+    }
+
+    /// Forgets every edit, keeping the layout as it is.
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Makes `edits` last to first, returning the edits that undo that, in the
+/// order they were made.
+fn revert(layout: &mut Layout, edits: Vec<Edit>) -> Vec<Edit> {
+    edits
+        .into_iter()
+        .rev()
+        .map(|edit| layout.apply(edit))
+        .collect()
+}
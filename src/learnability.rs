@@ -0,0 +1,180 @@
+use std::{io, path::Path};
+
+use anyhow::{Context as _, Result as AnyResult};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    keyboard::Keyboard,
+    layout::{KeyLoc, Layout},
+    symbol::Symbol,
+};
+
+/// The json at `path`, or the default if there is no file there.
+fn load_or_default<T: DeserializeOwned + Default>(path: &Path) -> AnyResult<T> {
+    let data = match std::fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(T::default()),
+        Err(error) => return Err(error).with_context(|| format!("cannot read {}", path.display())),
+    };
+    serde_json::from_str(&data).with_context(|| format!("bad json in {}", path.display()))
+}
+
+/// Pairs of characters that should share a physical key on different layers,
+/// like `(` over `9`, to make the layers easier to memorize.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PositionAffinity {
+    pairs: Vec<(char, char)>,
+}
+
+impl PositionAffinity {
+    pub fn new(pairs: Vec<(char, char)>) -> Self {
+        Self { pairs }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// No pairs if there is no file at `path`; a file that does not parse is
+    /// an error.
+    pub fn load_or_default(path: impl AsRef<Path>) -> AnyResult<Self> {
+        load_or_default(path.as_ref())
+    }
+
+    pub fn pairs(&self) -> &[(char, char)] {
+        &self.pairs
+    }
+
+    pub fn is_satisfied(layout: &Layout, a: char, b: char) -> bool {
+        let (Some(a), Some(b)) = (Symbol::from_char(a), Symbol::from_char(b)) else {
+            return false;
+        };
+        layout.find_all_key(move |k| k == a).any(|la| {
+            layout
+                .find_all_key(move |k| k == b)
+                .any(|lb| la.index() == lb.index() && la.layer() != lb.layer())
+        })
+    }
+
+    /// Number of pairs that are not stacked on the same key.
+    pub fn penalty(&self, layout: &Layout) -> f32 {
+        self.pairs
+            .iter()
+            .filter(|&&(a, b)| !Self::is_satisfied(layout, a, b))
+            .count() as f32
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssociationKind {
+    /// every character on the same key, each on a different layer (`[{`)
+    Stacked,
+    /// consecutive characters on neighbouring keys of one layer (`0123`)
+    Sequence,
+    /// each consecutive pair on mirrored keys of the two hands (`()[]`)
+    Mirrored,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssociationGroup {
+    pub kind: AssociationKind,
+    pub chars: String,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Configurable association groups rewarding layouts that are easy to learn.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Learnability {
+    groups: Vec<AssociationGroup>,
+}
+
+const NEIGHBOUR_DISTANCE: f32 = 20.0;
+
+impl Learnability {
+    pub fn new(groups: Vec<AssociationGroup>) -> Self {
+        Self { groups }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    /// No groups if there is no file at `path`; a file that does not parse
+    /// is an error.
+    pub fn load_or_default(path: impl AsRef<Path>) -> AnyResult<Self> {
+        load_or_default(path.as_ref())
+    }
+
+    pub fn groups(&self) -> &[AssociationGroup] {
+        &self.groups
+    }
+
+    /// Weighted share of satisfied links, summed over all groups.
+    pub fn bonus(&self, layout: &Layout, keyboard: &Keyboard) -> f32 {
+        self.groups
+            .iter()
+            .map(|group| group.weight * group_score(group, layout, keyboard))
+            .sum()
+    }
+}
+
+fn locations(layout: &Layout, ch: char) -> Vec<KeyLoc> {
+    match Symbol::from_char(ch) {
+        Some(ch) => layout.find_all_key(move |k| k == ch).collect(),
+        None => vec![],
+    }
+}
+
+fn group_score(group: &AssociationGroup, layout: &Layout, keyboard: &Keyboard) -> f32 {
+    let locs = group
+        .chars
+        .chars()
+        .map(|ch| locations(layout, ch))
+        .collect::<Vec<_>>();
+
+    let linked = |a: &[KeyLoc], b: &[KeyLoc], test: &dyn Fn(KeyLoc, KeyLoc) -> bool| {
+        a.iter().any(|&la| b.iter().any(|&lb| test(la, lb)))
+    };
+
+    let links = match group.kind {
+        AssociationKind::Stacked | AssociationKind::Sequence => {
+            locs.windows(2).map(|w| (&w[0], &w[1])).collect::<Vec<_>>()
+        }
+        AssociationKind::Mirrored => locs.chunks_exact(2).map(|w| (&w[0], &w[1])).collect(),
+    };
+    if links.is_empty() {
+        return 0.0;
+    }
+
+    let satisfied = links
+        .iter()
+        .filter(|(a, b)| match group.kind {
+            AssociationKind::Stacked => linked(a, b, &|la, lb| {
+                la.index() == lb.index() && la.layer() != lb.layer()
+            }),
+            AssociationKind::Sequence => linked(a, b, &|la, lb| {
+                let (ka, kb) = (keyboard.key(la.index()), keyboard.key(lb.index()));
+                la.layer() == lb.layer()
+                    && la.index() != lb.index()
+                    && ka.pos().distance(kb.pos()) <= NEIGHBOUR_DISTANCE
+            }),
+            AssociationKind::Mirrored => linked(a, b, &|la, lb| {
+                let (ka, kb) = (keyboard.key(la.index()), keyboard.key(lb.index()));
+                la.layer() == lb.layer()
+                    && ka.finger().hand != kb.finger().hand
+                    && ka.finger().finger == kb.finger().finger
+                    && (ka.pos().y - kb.pos().y).abs() < 1.0
+            }),
+        })
+        .count();
+
+    satisfied as f32 / links.len() as f32
+}
@@ -0,0 +1,145 @@
+//! Evaluating and optimizing layered keyboard layouts.
+//!
+//! A [`layout::Layout`] is placed on a [`keyboard::Keyboard`] with
+//! [`evaluate::KeyboardLayout::generate`], scored against the n-grams from
+//! [`counter::count`] with [`evaluate::evaluate`], and improved with
+//! [`optimization::anneal`], or step by step with [`optimization::Annealer`].
+//!
+//! Without the `std` feature only [`core`] is built, for no_std targets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+/// Constructive beam search over key placements.
+#[cfg(feature = "full")]
+pub mod beam;
+/// Per-key cost offsets fitted to a typing recording.
+#[cfg(feature = "full")]
+pub mod calibration;
+/// Counting the keys typed on the desktop into a personal corpus.
+#[cfg(feature = "capture")]
+pub mod capture;
+/// Modifier chords such as `ctrl+shift+p`.
+#[cfg(feature = "full")]
+pub mod chord;
+/// The `keyboard.toml` run configuration.
+#[cfg(feature = "full")]
+pub mod config;
+/// The pure cost model: finger types, metrics and per-n-gram costs, with no
+/// filesystem, hashing or OS access, so it builds without std.
+pub mod core;
+/// Counting letter, bigram and trigram frequencies over a corpus.
+#[cfg(feature = "full")]
+pub mod counter;
+/// Scoring a layout on a keyboard against counted n-grams.
+#[cfg(feature = "full")]
+pub mod evaluate;
+/// Built-in reference layouts for the Ferris Sweep.
+#[cfg(feature = "full")]
+pub mod ferris;
+/// C ABI over the evaluator.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Directory of saved layouts with cached evaluations.
+#[cfg(feature = "full")]
+pub mod gallery;
+/// Log of past optimization runs.
+#[cfg(feature = "full")]
+pub mod history;
+/// Export to host-side remappers.
+#[cfg(feature = "full")]
+pub mod host;
+/// N-gram frequencies published by other tools.
+#[cfg(feature = "full")]
+pub mod import;
+#[cfg(feature = "full")]
+mod iter;
+/// Scoring one layout across two keyboards.
+#[cfg(feature = "full")]
+pub mod joint;
+/// Physical keyboard geometry and finger assignment.
+#[cfg(feature = "full")]
+pub mod keyboard;
+/// Layered layouts and their hold behaviors.
+#[cfg(feature = "full")]
+pub mod layout;
+/// Penalties and bonuses for how easy a layout is to learn.
+#[cfg(feature = "full")]
+pub mod learnability;
+/// Integer program export of the base layer placement problem.
+#[cfg(feature = "full")]
+pub mod lp;
+/// Export to Markdown tables for READMEs.
+#[cfg(feature = "full")]
+pub mod markdown;
+/// Markov-chain estimate of the expected cost per keystroke.
+#[cfg(feature = "full")]
+pub mod markov;
+/// Left-hand navigation layer for right-hand-on-mouse use.
+#[cfg(feature = "full")]
+pub mod nav;
+/// Simulated annealing over layouts.
+#[cfg(feature = "full")]
+pub mod optimization;
+/// Terminal printing of layouts and reports.
+#[cfg(feature = "full")]
+pub mod output;
+/// Turning prose and markdown into the keys that typed it.
+#[cfg(feature = "full")]
+pub mod prose;
+/// Python bindings for notebook experiments.
+#[cfg(feature = "pyo3")]
+pub mod python;
+/// Export to QMK keymap json.
+#[cfg(feature = "full")]
+pub mod qmk;
+/// Analysis reports over an evaluated layout.
+#[cfg(feature = "full")]
+pub mod report;
+/// Evaluation over http.
+#[cfg(feature = "server")]
+pub mod server;
+/// Shareable n-gram counts of a corpus, without its text.
+#[cfg(feature = "full")]
+pub mod shared;
+/// Commands read from bash, zsh and fish history files.
+#[cfg(feature = "full")]
+pub mod shell_history;
+/// Which characters are typed by shifting which keys.
+#[cfg(feature = "full")]
+pub mod shift;
+/// Key-by-key typing of a text on a layout.
+#[cfg(feature = "full")]
+pub mod simulate;
+/// Leaving comments and string contents out of source code.
+#[cfg(feature = "full")]
+pub mod strip;
+/// Interned ids of the typed symbols.
+#[cfg(feature = "full")]
+pub mod symbol;
+/// Typing through one layout with muscle memory of another.
+#[cfg(feature = "full")]
+pub mod translate;
+/// Interactive layout editor.
+#[cfg(feature = "tui")]
+pub mod tui;
+/// Normal mode commands of vim mixed into a corpus.
+#[cfg(feature = "full")]
+pub mod vim;
+
+#[cfg(feature = "full")]
+pub const ALPHABET: &[u8; 97] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 \t\n\\\"<>(){}[]:!;.,/?=+&*^%@#_|'`$-~";
+#[cfg(feature = "full")]
+pub fn in_alphabet(x: u8) -> bool {
+    const LUT: [bool; 256] = {
+        let mut lut = [false; 256];
+        let mut i = 0;
+        while i < ALPHABET.len() {
+            lut[ALPHABET[i] as usize] = true;
+            i += 1;
+        }
+        lut
+    };
+    LUT[usize::from(x)]
+}
@@ -0,0 +1,204 @@
+use std::io::{self, Write};
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{
+        calibration_cost, convert_fingers, letter_cost, EvalWeights, KeyCombo, KeyboardLayout,
+    },
+    keyboard::HandFinger,
+    symbol::Symbol,
+};
+
+/// Terms written per line, well under the line limit of LP readers.
+const TERMS_PER_LINE: usize = 8;
+
+/// Placing the base layer keys of a layout back onto its own slots, as a
+/// quadratic assignment: every key has a cost per slot, and every pair of keys
+/// sharing a finger pays for the bigrams between them.
+#[derive(Debug, Clone)]
+pub struct PlacementProblem {
+    pub keys: Vec<Symbol>,
+    pub slots: Vec<usize>,
+    /// weighted letter cost of each key on each slot, `[key][slot]`
+    pub cost: Vec<Vec<f32>>,
+    /// `(key, key, weight)` for every pair typed after one another
+    pub pairs: Vec<(usize, usize, f32)>,
+    /// slots grouped by the finger that presses them
+    pub fingers: Vec<Vec<usize>>,
+}
+
+impl PlacementProblem {
+    /// The base layer of `info`, where letter costs are weighted like the
+    /// annealing objective and every same-finger pair costs `sfb_weight` per
+    /// bigram. Shifted characters count towards the key they are typed with.
+    pub fn new(
+        info: &KeyboardLayout,
+        count: &CountOutcome,
+        weights: &EvalWeights,
+        sfb_weight: f32,
+    ) -> Self {
+        let layer = info.layout().first_layer();
+        let (keys, slots): (Vec<_>, Vec<_>) = layer
+            .iter()
+            .enumerate()
+            .filter_map(|(i, key)| key.map(|key| (key, i)))
+            .unzip();
+        let shift = info.keyboard().shift_table();
+        let key_index = |ch: Symbol| keys.iter().position(|&k| k == shift.unshifted(ch));
+
+        let mut freq = vec![0.0; keys.len()];
+        for (&[ch], &f) in &count.letter {
+            if let Some(k) = key_index(ch) {
+                freq[k] += f as f32;
+            }
+        }
+        let slot_cost = slots
+            .iter()
+            .map(|&slot| {
+                let combo = KeyCombo::new(None, None, slot, 0);
+                let cost = letter_cost(info, &convert_fingers(info, &combo))
+                    + calibration_cost(info, &combo);
+                weights.base.dot(cost.base) + weights.stretch.dot(cost.stretch)
+            })
+            .collect::<Vec<_>>();
+        let cost = freq
+            .iter()
+            .map(|f| slot_cost.iter().map(|c| f * c).collect())
+            .collect();
+
+        let mut pair_freq = vec![vec![0.0; keys.len()]; keys.len()];
+        for (&[a, b], &f) in &count.bigrams {
+            if let (Some(a), Some(b)) = (key_index(a), key_index(b)) {
+                if a != b {
+                    pair_freq[a.min(b)][a.max(b)] += f as f32;
+                }
+            }
+        }
+        let mut pairs = vec![];
+        for (a, row) in pair_freq.iter().enumerate() {
+            for (b, &f) in row.iter().enumerate() {
+                if f > 0.0 {
+                    pairs.push((a, b, f * sfb_weight));
+                }
+            }
+        }
+
+        let mut fingers: Vec<(HandFinger, Vec<usize>)> = vec![];
+        for (s, &slot) in slots.iter().enumerate() {
+            let finger = info.keyboard().key(slot).finger();
+            match fingers.iter_mut().find(|(f, _)| *f == finger) {
+                Some((_, group)) => group.push(s),
+                None => fingers.push((finger, vec![s])),
+            }
+        }
+
+        Self {
+            keys,
+            slots,
+            cost,
+            pairs,
+            fingers: fingers.into_iter().map(|(_, group)| group).collect(),
+        }
+    }
+
+    /// Objective of placing key `k` on slot `assignment[k]`, the value a
+    /// solver reports for that placement.
+    pub fn objective(&self, assignment: &[usize]) -> f32 {
+        let finger_of = |s: usize| self.fingers.iter().position(|group| group.contains(&s));
+        let letters = assignment
+            .iter()
+            .enumerate()
+            .map(|(k, &s)| self.cost[k][s])
+            .sum::<f32>();
+        let sfbs = self
+            .pairs
+            .iter()
+            .filter(|&&(a, b, _)| finger_of(assignment[a]) == finger_of(assignment[b]))
+            .map(|&(_, _, w)| w)
+            .sum::<f32>();
+        letters + sfbs
+    }
+
+    /// Writes the problem in CPLEX LP format. `x_<key>_<slot>` is 1 if the
+    /// key (as its ascii code) is on the slot (as its key index), and
+    /// `s_<key>_<key>` is 1 if two keys share a finger.
+    pub fn write_lp(&self, out: &mut impl Write) -> io::Result<()> {
+        let x = |k: usize, s: usize| format!("x_{}_{}", self.keys[k].id(), self.slots[s]);
+        let sfb = |a: usize, b: usize| format!("s_{}_{}", self.keys[a].id(), self.keys[b].id());
+        let (n_keys, n_slots) = (self.keys.len(), self.slots.len());
+
+        writeln!(
+            out,
+            "\\ placement of {n_keys} keys on {n_slots} base layer slots"
+        )?;
+        writeln!(out, "Minimize")?;
+        let mut objective = vec![];
+        for k in 0..n_keys {
+            for s in 0..n_slots {
+                objective.push((self.cost[k][s], x(k, s)));
+            }
+        }
+        for &(a, b, w) in &self.pairs {
+            objective.push((w, sfb(a, b)));
+        }
+        writeln!(out, " obj: {}", linear(&objective))?;
+
+        writeln!(out, "Subject To")?;
+        for k in 0..n_keys {
+            let terms = (0..n_slots).map(|s| (1.0, x(k, s))).collect::<Vec<_>>();
+            writeln!(out, " key_{}: {} = 1", self.keys[k].id(), linear(&terms))?;
+        }
+        for s in 0..n_slots {
+            let terms = (0..n_keys).map(|k| (1.0, x(k, s))).collect::<Vec<_>>();
+            writeln!(out, " slot_{}: {} <= 1", self.slots[s], linear(&terms))?;
+        }
+        // s_ab >= 1 whenever a and b both sit on slots of the same finger
+        for &(a, b, _) in &self.pairs {
+            for (f, group) in self.fingers.iter().enumerate() {
+                let mut terms = group
+                    .iter()
+                    .flat_map(|&s| [(1.0, x(a, s)), (1.0, x(b, s))])
+                    .collect::<Vec<_>>();
+                terms.push((-1.0, sfb(a, b)));
+                let name = format!("sfb_{}_{}_{f}", self.keys[a].id(), self.keys[b].id());
+                writeln!(out, " {name}: {} <= 1", linear(&terms))?;
+            }
+        }
+
+        writeln!(out, "Bounds")?;
+        for &(a, b, _) in &self.pairs {
+            writeln!(out, " 0 <= {} <= 1", sfb(a, b))?;
+        }
+        writeln!(out, "Binary")?;
+        let binaries = (0..n_keys)
+            .flat_map(|k| (0..n_slots).map(move |s| (k, s)))
+            .map(|(k, s)| x(k, s))
+            .collect::<Vec<_>>();
+        for line in binaries.chunks(TERMS_PER_LINE) {
+            writeln!(out, " {}", line.join(" "))?;
+        }
+        writeln!(out, "End")
+    }
+}
+
+/// `c1 v1 + c2 v2 - ...`, wrapped every few terms.
+fn linear(terms: &[(f32, String)]) -> String {
+    let mut out = String::new();
+    for (i, (coefficient, var)) in terms.iter().enumerate() {
+        if i > 0 && i % TERMS_PER_LINE == 0 {
+            out.push_str("\n  ");
+        }
+        let sign = match (i, coefficient.is_sign_negative()) {
+            (0, false) => "",
+            (0, true) => "-",
+            (_, false) => " + ",
+            (_, true) => " - ",
+        };
+        let coefficient = coefficient.abs();
+        match coefficient == 1.0 {
+            true => out.push_str(&format!("{sign}{var}")),
+            false => out.push_str(&format!("{sign}{coefficient} {var}")),
+        }
+    }
+    out
+}
@@ -1,129 +1,1094 @@
-#![allow(unused_imports)]
-
-use evaluate::{Evaluation, KeyboardLayout};
-use keyboard::Keyboard;
-use layout::{Behavior, KeyLoc, Layout};
+use anyhow::{anyhow, bail, Context as _, Result as AnyResult};
+use clap::{Parser, Subcommand, ValueEnum};
+use keyboard::{
+    beam, calibration, chord,
+    config::{Config, CostModel, EvalBackend, EvalConfig, JointConfig},
+    counter,
+    evaluate::{self, Evaluation, KeyboardLayout},
+    ferris, gallery, history, host, import, joint,
+    keyboard::{Hand, Keyboard},
+    layout::Layout,
+    learnability, lp, markdown, markov, nav, optimization, output,
+    qmk::QmkKeymap,
+    report, shared, shell_history, simulate, translate,
+};
 use notify_rust::Notification;
-use qmk::QmkKeymap;
 use rand::Rng as _;
 use std::{
-    cmp::Ordering,
-    collections::{HashMap, HashSet},
-    num::NonZeroU8,
-    sync::LazyLock,
+    cell::RefCell,
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
 };
 
-mod counter;
-mod evaluate;
-mod ferris;
-mod iter;
-mod keyboard;
-mod layout;
-mod optimization;
-mod output;
-mod qmk;
-
-pub const ALPHABET: &[u8; 97] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 \t\n\\\"<>(){}[]:!;.,/?=+&*^%@#_|'`$-~";
-pub fn in_alphabet(x: u8) -> bool {
-    static LUT: LazyLock<[bool; 256]> =
-        LazyLock::new(|| std::array::from_fn(|x| ALPHABET.iter().any(|&k| usize::from(k) == x)));
-    LUT[usize::from(x)]
-}
-
-fn main() {
-    let (count, err) = counter::count("..");
+#[derive(Parser)]
+#[command(about = "Evaluate and optimize layered keyboard layouts")]
+struct Cli {
+    #[arg(long, global = true, default_value = "keyboard.toml")]
+    config: PathBuf,
+    /// directory walked for the n-gram corpus, a file of counts, `-` for
+    /// text on stdin, or `preset:english`, `preset:rust`, `preset:python`,
+    /// `preset:shell` or `preset:numeric` for a bundled corpus, or `history:`
+    /// for the shell history (`history:<file>` for one file), overriding the config
+    #[arg(long, global = true)]
+    corpus: Option<PathBuf>,
+    /// keyboard geometry: `ferris_sweep`, `laptop`, one of the bundled
+    /// `corne`, `kyria`, `sofle`, `lily58`, `piantor` or `totem`, or a
+    /// geometry file, overriding the config
+    #[arg(long, global = true)]
+    keyboard: Option<String>,
+    /// extension of the counted files, repeatable, overriding the config
+    #[arg(long = "ext", global = true)]
+    extensions: Vec<String>,
+    /// name or glob of files and directories left out of the corpus,
+    /// repeatable, overriding the config
+    #[arg(long, global = true)]
+    ignore: Vec<String>,
+    /// fixed seed, no corpus cache and no timestamps, for reproducible runs
+    #[arg(long, global = true)]
+    deterministic: bool,
+    /// colored output; `auto` colors a terminal unless NO_COLOR is set
+    #[arg(long, global = true, value_enum, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Count the corpus and print the most frequent n-grams
+    Count {
+        #[arg(long, default_value_t = 30)]
+        top: usize,
+        /// also write every count to a versioned `.json` or `.csv` file
+        #[arg(long)]
+        export: Option<PathBuf>,
+    },
+    /// Report how much of the corpus the alphabet types, what it rejects, and
+    /// how many characters each file extension adds
+    Stats {
+        /// rejected characters listed
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Report the share of each language in the corpus directory and propose
+    /// `[[eval.corpora]]` weighted by it
+    Languages {
+        /// languages with less of the bytes, in percent, are left out of the
+        /// proposal
+        #[arg(long, default_value_t = 1.0)]
+        min_share: f32,
+    },
+    /// Write the n-gram counts of the corpus without its text, to share
+    Share {
+        #[arg(short, long, default_value = "corpus.json")]
+        output: PathBuf,
+        /// leave out n-grams seen fewer times
+        #[arg(long, default_value_t = 2)]
+        min_count: u32,
+    },
+    /// Evaluate a layout against the corpus, next to qwerty
+    Eval {
+        layout: PathBuf,
+        /// also print the per-keystroke markov estimate next to the n-gram sums
+        #[arg(long)]
+        markov: bool,
+        /// modifier chord list to evaluate as well
+        #[arg(long)]
+        chords: Option<PathBuf>,
+        /// also split the vector metrics by hand
+        #[arg(long)]
+        hands: bool,
+    },
+    /// List the bigrams or trigrams adding the most to one metric of a layout
+    Offenders {
+        layout: PathBuf,
+        /// metric named as in `eval`, such as bigram.sfb or trigram.redirects
+        #[arg(long, default_value = "bigram.sfb")]
+        metric: String,
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Edit a layout interactively, swapping keys and watching the score
+    #[cfg(feature = "tui")]
+    Tui { layout: PathBuf },
+    /// Re-evaluate a layout every time its file changes
+    Watch { layout: PathBuf },
+    /// Type a text file key by key and report finger travel, sfbs, sfss and layer switches
+    Simulate { layout: PathBuf, text: PathBuf },
+    /// Answer `POST /evaluate` with the evaluation of the posted layout json
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Count the keys typed on the desktop until ctrl-c, adding to earlier sessions
+    #[cfg(feature = "capture")]
+    Capture {
+        #[arg(short, long, default_value = "kb/typed.json")]
+        output: PathBuf,
+    },
+    /// Compare the n-grams of two corpora and how a layout scores on each
+    Drift {
+        old: PathBuf,
+        new: PathBuf,
+        /// layout whose evaluation drift is shown, the optimize output if missing
+        #[arg(long)]
+        layout: Option<PathBuf>,
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+    /// Show two layouts side by side with the change in every metric
+    Compare { a: PathBuf, b: PathBuf },
+    /// Evaluate every layout json in a directory and rank them
+    Rank {
+        #[arg(default_value = "kb")]
+        dir: PathBuf,
+    },
+    /// Rank the built-in layouts and any given ones on the same corpus
+    Benchmark { layouts: Vec<PathBuf> },
+    /// Time placing a layout on the keyboard and evaluating it, the work of
+    /// every optimize iteration
+    Speed {
+        layout: Option<PathBuf>,
+        #[arg(long, default_value_t = 100)]
+        rounds: u32,
+    },
+    /// Anneal a starting layout and write the result
+    Optimize {
+        start: Option<PathBuf>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        #[arg(long)]
+        iterations: Option<u32>,
+        /// name the run is filed under in the history
+        #[arg(long)]
+        profile: Option<String>,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Anneal the starting layout several times from different seeds and
+    /// report how far the scores spread and how alike the layouts come out
+    Stability {
+        #[arg(long, default_value_t = 5)]
+        runs: u32,
+        /// a tenth of `optimize.iterations` by default
+        #[arg(long)]
+        iterations: Option<u32>,
+    },
+    /// Fill the key slots of a starting layout by beam search, most frequent key first
+    Beam {
+        start: Option<PathBuf>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// partial layouts kept after each key
+        #[arg(long)]
+        width: Option<usize>,
+    },
+    /// Print a layout
+    Print { layout: PathBuf },
+    /// Check that layout files load, reporting what is wrong with each
+    Validate { layouts: Vec<PathBuf> },
+    /// Draw the cost of tapping anywhere on the keyboard in braille, or with
+    /// a layout how much of the corpus lands around each key
+    Heatmap { layout: Option<PathBuf> },
+    /// Rank the digits of a layout as they are against a numpad and a row on
+    /// the same layer, e.g. with `--config presets/data-entry.toml`
+    Digits { layout: PathBuf },
+    /// Compare typing each symbol with shift against a key of its own, and
+    /// the other way round
+    Shifts { layout: PathBuf },
+    /// Score space, enter and tab on every thumb key, next to which hand
+    /// types the characters around them
+    Thumbs { layout: PathBuf },
+    /// Convert a layout to a firmware or host remapping config, or to
+    /// Markdown tables
+    Convert {
+        layout: PathBuf,
+        #[arg(long, value_enum, default_value_t = ConvertTarget::Qmk)]
+        target: ConvertTarget,
+        /// written to stdout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Write the base layer placement of a layout as an integer program in LP format
+    Lp {
+        layout: PathBuf,
+        /// cost of each bigram typed with one finger
+        #[arg(long, default_value_t = 1.0)]
+        sfb_weight: f32,
+        /// written to stdout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Move a layout to a keyboard with other keys, or add or remove a layer
+    Resize {
+        layout: PathBuf,
+        /// for each key of the new keyboard the old key it takes, or `-` for
+        /// none, e.g. `0..27,30..34` to drop a column of three
+        #[arg(long)]
+        map: Option<String>,
+        /// add an empty layer on top
+        #[arg(long)]
+        add_layer: bool,
+        /// remove a layer and the holds to it
+        #[arg(long)]
+        remove_layer: Option<u8>,
+        /// written over the layout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Echo stdin as it would come out of a layout typed with qwerty muscle memory
+    Translate {
+        layout: PathBuf,
+        /// translate from the layout back to qwerty instead
+        #[arg(long)]
+        reverse: bool,
+    },
+    /// Generate a left-hand nav layer on top of a layout and export both to QMK
+    Nav {
+        layout: PathBuf,
+        /// `{"key": "ctrl+z", "freq": 120}` entries for nav keys and shortcuts
+        #[arg(long, default_value = "kb/nav.json")]
+        usage: PathBuf,
+        /// base key that holds the layer, the first free left thumb if missing
+        #[arg(long)]
+        layer_key: Option<usize>,
+        /// written to stdout if missing
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Fit per-key cost offsets to a typing recording made on a layout
+    Calibrate {
+        /// one `{"time": ms, "key": "a"}` object per line
+        recording: PathBuf,
+        layout: PathBuf,
+        #[arg(short, long, default_value = "kb/calibration.json")]
+        output: PathBuf,
+    },
+    /// Query past optimization runs
+    History {
+        #[command(subcommand)]
+        command: Option<HistoryCommand>,
+        #[arg(long, global = true)]
+        profile: Option<String>,
+    },
+    /// Manage the saved layout gallery
+    Gallery {
+        #[command(subcommand)]
+        command: Option<GalleryCommand>,
+    },
+}
+
+#[derive(Subcommand)]
+enum GalleryCommand {
+    /// List saved layouts with their scores
+    List,
+    /// Save a layout under a name
+    Save {
+        name: String,
+        layout: PathBuf,
+        tags: Vec<String>,
+    },
+    /// Show or set the champion layout
+    Champion { name: Option<String> },
+}
+
+#[derive(Subcommand)]
+enum HistoryCommand {
+    /// List every recorded run
+    List,
+    /// Show the best run ever recorded
+    Best,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ConvertTarget {
+    Qmk,
+    Kanata,
+    Keyd,
+    Karabiner,
+    Markdown,
+}
+
+const GALLERY_PATH: &str = "kb/gallery";
+const HISTORY_PATH: &str = "kb/history.jsonl";
+
+fn count_corpus(config: &Config) -> AnyResult<counter::CountOutcome> {
+    let count = count_text(config)?;
+    Ok(match config.eval.vim > 0.0 {
+        true => {
+            keyboard::vim::with_normal_mode(&count, config.eval.vim.min(1.0), config.eval.depth)
+        }
+        false => count,
+    })
+}
+
+/// The corpus as typed, before any vim commands are mixed in.
+fn count_text(config: &Config) -> AnyResult<counter::CountOutcome> {
+    if !config.eval.corpora.is_empty() {
+        let parts = config
+            .eval
+            .corpora
+            .iter()
+            .map(|part| {
+                let mut config = config.clone();
+                config.eval = config.eval.for_part(part);
+                Ok((count_corpus(&config)?, part.weight))
+            })
+            .collect::<AnyResult<Vec<_>>>()?;
+        let parts = parts
+            .iter()
+            .map(|(count, weight)| (count, *weight))
+            .collect::<Vec<_>>();
+        return Ok(counter::CountOutcome::mix(&parts));
+    }
+    let path = &config.eval.corpus;
+    if let Some(name) = path.to_str().and_then(|path| path.strip_prefix("preset:")) {
+        let mut count = shared::SharedCorpus::preset(name)?.to_count()?;
+        count.truncate(config.eval.depth);
+        return Ok(count);
+    }
+    if path == Path::new("-") {
+        let text = config.eval.text_options();
+        return counter::count_reader(std::io::stdin().lock(), config.eval.depth, &text);
+    }
+    if let Some(file) = path.to_str().and_then(|path| path.strip_prefix("history:")) {
+        let files = match file {
+            "" => shell_history::default_files(),
+            file => vec![shell_history::expand_home(file)],
+        };
+        let history = shell_history::corpus_text(&files)?;
+        let text = config.eval.text_options();
+        return counter::count_reader(history.as_bytes(), config.eval.depth, &text);
+    }
+    if path.is_file() {
+        let text = config.eval.text_options();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let mut count = match extension {
+            Some("csv") if is_counts_csv(path)? => counter::import(path)?,
+            Some("csv" | "tsv" | "txt") => import::load_norvig(path, &text.transliteration)?,
+            _ => match shared::SharedCorpus::load(path) {
+                Ok(shared) => shared.to_count()?,
+                Err(_) => match import::load_oxeylyzer(path, &text.transliteration) {
+                    Ok(count) => count,
+                    Err(_) => counter::import(path)?,
+                },
+            },
+        };
+        count.truncate(config.eval.depth);
+        return Ok(count);
+    }
+    let filter = config.eval.corpus_filter()?;
+    let text = config.eval.text_options();
+    if config.deterministic {
+        return Ok(counter::count_uncached(
+            path,
+            config.eval.depth,
+            &filter,
+            &text,
+        ));
+    }
+    let (count, err) = counter::count(path, config.eval.depth, &filter, &text);
     if let Some(err) = err {
         println!("Cache failed: {err:?}");
     }
+    Ok(count)
+}
 
-    let keyboard = Keyboard::ferris_sweep();
-    // let start_layout = ferris::qwerty();
-    let reference_layout = ferris::qwerty();
-    let start_layout: Layout =
-        serde_json::from_str(&std::fs::read_to_string("kb/final.json").unwrap()).unwrap();
-    const THIS_PATH: &str = "kb/final2.json";
-
-    let layout2: Layout =
-        serde_json::from_str(&std::fs::read_to_string(THIS_PATH).unwrap()).unwrap();
-    output::print_ferris_layout(&layout2);
-    let l1 = KeyboardLayout::generate(&reference_layout, &keyboard)
-        .map_err(char::from)
-        .unwrap();
-    let l2 = KeyboardLayout::generate(&layout2, &keyboard).unwrap();
-    let eval = evaluate::evaluate(&l1, &count);
-    println!("qwerty: {eval:#?}");
-    let eval = evaluate::evaluate(&l2, &count);
+/// Whether `path` was written by `count --export` rather than by another tool.
+fn is_counts_csv(path: &Path) -> AnyResult<bool> {
+    let mut first = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut first)?;
+    Ok(first.starts_with("# keyboard-counts/"))
+}
+
+fn run_stats(config: &Config, top: usize) -> AnyResult<()> {
+    let eval = &config.eval;
+    let parts = match eval.corpora.is_empty() {
+        true => vec![eval.clone()],
+        false => eval
+            .corpora
+            .iter()
+            .map(|part| eval.for_part(part))
+            .collect(),
+    };
+    let mut stats = counter::CorpusStats::default();
+    for part in parts {
+        if !part.corpus.is_dir() {
+            bail!(
+                "stats reads the text of a corpus directory, {} is not one",
+                part.corpus.display()
+            );
+        }
+        stats.add_corpus(&part.corpus, &part.corpus_filter()?, &part.text_options());
+    }
+    output::print_corpus_stats(&stats, top);
+    Ok(())
+}
+
+fn run_languages(config: &Config, min_share: f32) -> AnyResult<()> {
+    let eval = &config.eval;
+    if !eval.corpus.is_dir() {
+        bail!(
+            "languages walks a corpus directory, {} is not one",
+            eval.corpus.display()
+        );
+    }
+    // every file, whatever the configured extensions
+    let filter = counter::CorpusFilter::new(vec![], &eval.ignore)?.with_gitignore(eval.gitignore);
+    let mix = report::language_mix(&counter::corpus_files(&eval.corpus, &filter));
+    output::print_language_mix(&mix);
+    println!();
+    let parts = report::propose_corpora(&eval.corpus, &mix, min_share / 100.0);
+    print!("{}", output::render_corpora_toml(&parts));
+    Ok(())
+}
+
+fn run_share(config: &Config, output: &Path, min_count: u32) -> AnyResult<()> {
+    let eval = &config.eval;
+    let count = count_corpus(config)?;
+    let mut files = vec![];
+    for part in &eval.corpora {
+        let part = eval.for_part(part);
+        files.extend(counter::corpus_files(&part.corpus, &part.corpus_filter()?));
+    }
+    if eval.corpora.is_empty() {
+        files = counter::corpus_files(&eval.corpus, &eval.corpus_filter()?);
+    }
+    let shared = shared::SharedCorpus::new(&count, &files, eval.mode, eval.depth, min_count);
+    shared.save(output)?;
+    println!(
+        "{} files, {} keystrokes; kept {} letters, {} bigrams, {} trigrams",
+        shared.meta.files,
+        shared.meta.keystrokes,
+        shared.counts.letters.len(),
+        shared.counts.bigrams.len(),
+        shared.counts.trigrams.len()
+    );
+    Ok(())
+}
+
+fn reference_eval(
+    config: &EvalConfig,
+    keyboard: &Keyboard,
+    count: &counter::CountOutcome,
+) -> AnyResult<Evaluation> {
+    let reference_layout = config.reference_layout()?;
+    KeyboardLayout::generate(&reference_layout, keyboard)
+        .map(|kl| evaluate::evaluate(&kl, count))
+        .map_err(|k| anyhow!("reference {k}"))
+}
+
+fn run_count(config: &Config, top: usize, export: Option<&Path>) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    if let Some(path) = export {
+        counter::export(&count, path)?;
+    }
+    println!(
+        "{} letters, {} bigrams, {} trigrams, {} skipgrams, ~{} KiB",
+        count.letter.len(),
+        count.bigrams.len(),
+        count.trigrams.len(),
+        count.skipgrams.len(),
+        count.memory_bytes() / 1024
+    );
+    if !count.letter.is_empty() {
+        output::render_frequency_table(count.letter, top, |x| x);
+    }
+    if !count.bigrams.is_empty() {
+        output::render_frequency_table(count.bigrams, top, |x| x);
+    }
+    if !count.trigrams.is_empty() {
+        output::render_frequency_table(count.trigrams, top, |x| x);
+    }
+    if !count.skipgrams.is_empty() {
+        println!("skipgrams");
+        output::render_frequency_table(count.skipgrams, top, |x| x);
+    }
+    if !count.quadgrams.is_empty() {
+        println!("{} quadgrams", count.quadgrams.len());
+        output::render_frequency_table(count.quadgrams, top, |x| x);
+    }
+    if !count.caps_words.is_empty() {
+        let total = count
+            .caps_words
+            .values()
+            .map(|&freq| u64::from(freq))
+            .sum::<u64>();
+        println!("{total} caps words, by first letter");
+        output::render_frequency_table(count.caps_words, top, |x| x);
+    }
+    Ok(())
+}
+
+fn run_eval(
+    config: &Config,
+    path: &Path,
+    chords: Option<&Path>,
+    markov: bool,
+    hands: bool,
+) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let layout = Layout::load(path)?;
+    output::print_layout(&layout, &keyboard);
+
+    println!(
+        "{}: {:#?}",
+        config.eval.reference,
+        reference_eval(&config.eval, &keyboard, &count)?
+    );
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    let eval = evaluate::evaluate(&info, &count);
     println!("??????: {eval:#?}");
+    if config.eval.model == CostModel::Fitts {
+        let ms = evaluate::ms_per_keystroke(&eval, &count);
+        println!(
+            "estimated {ms:.0} ms per keystroke, {:.0} wpm",
+            12_000.0 / ms
+        );
+    }
+    output::print_finger_load(&eval.finger, keyboard.finger_targets());
+    if markov {
+        let model = markov::MarkovModel::from_counts(&count, config.eval.markov_min_probability);
+        let keystrokes = count.letter.values().sum::<u32>() as f32;
+        println!("per keystroke: n-gram sums (a) against the markov estimate (b)");
+        output::print_eval_deltas(
+            &(eval * (1.0 / keystrokes)),
+            &markov::evaluate(&info, &model),
+            (f32::NAN, f32::NAN),
+            &config.eval.weights,
+        );
+    }
+    if hands {
+        let reference = config.eval.reference_layout()?;
+        let reference = KeyboardLayout::generate(&reference, &keyboard)
+            .map_err(|k| anyhow!("reference {k}"))?;
+        output::print_hand_split(
+            &Hand::ALL.map(|hand| evaluate::evaluate_hand(&info, &count, hand)),
+            &Hand::ALL.map(|hand| evaluate::evaluate_hand(&reference, &count, hand)),
+        );
+    }
+    output::print_cross_layer_bigrams(&report::cross_layer_bigrams(&info, &count.bigrams, 20));
+    output::print_layer_transitions(&report::layer_transitions(&info, &count.bigrams));
+    output::print_reachability(&layout.reachability());
 
-    let qmk_layout = QmkKeymap::from_layout(layout2).unwrap();
-    let json = serde_json::to_string_pretty(&qmk_layout).unwrap();
-    std::fs::write(THIS_PATH, json).unwrap();
-    return;
-
-    fn to_evaluation(scaled: &Evaluation) -> f32 {
-        evaluate::sse([
-            (2.0, scaled.letter.base.x),
-            (1.0, scaled.letter.base.y),
-            (5.0, scaled.letter.base.z),
-            (5.0, scaled.letter.stretch.x),
-            (3.0, scaled.letter.stretch.y),
-            (3.0, scaled.bigram.movement.x),
-            (2.0, scaled.bigram.movement.y),
-            (20.0, scaled.bigram.staccato),
-        ])
-    }
-
-    let kl = KeyboardLayout::generate(&reference_layout, &keyboard).unwrap();
-    let reference_eval = evaluate::evaluate(&kl, &count);
-    let scale_evaluation = |eval: Evaluation| eval / reference_eval.clone() * 100.0;
-
-    let start_kl = KeyboardLayout::generate(&start_layout, &keyboard).unwrap();
-    let start_eval = scale_evaluation(evaluate::evaluate(&start_kl, &count));
-    let start_evaluation = to_evaluation(&start_eval);
-    let eval_scaler = 1_000_000.0 / start_evaluation;
-
-    let (result, score) = optimization::anneal(
-        start_layout,
-        1000000,
-        |x| {
-                30.0 * (1.0 - x)
-        },
+    if let Some(chords) = chords {
+        let chords = chord::load(chords)?;
+        println!("chords: {:#?}", chord::eval_chords(&info, &chords));
+    }
+    Ok(())
+}
+
+fn run_offenders(config: &Config, path: &Path, metric: &str, top: usize) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let layout = Layout::load(path)?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    output::print_offenders(metric, &report::top_offenders(&info, &count, metric, top)?);
+    Ok(())
+}
+
+fn run_heatmap(config: &Config, layout: Option<&Path>) -> AnyResult<()> {
+    let keyboard = config.eval.keyboard()?;
+    let Some(path) = layout else {
+        output::print_braille_heatmap(&keyboard, |point| {
+            report::tap_cost_at(&keyboard, &config.eval.weights, point)
+        });
+        return Ok(());
+    };
+
+    let count = count_corpus(config)?;
+    let layout = Layout::load(path)?;
+    let info =
+        KeyboardLayout::generate_partial(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    let usage = report::key_usage(&info, &count.letter);
+    output::print_braille_heatmap(&keyboard, |point| {
+        report::usage_at(&keyboard, &usage, point)
+    });
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(config: &Config, path: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let app = keyboard::tui::App::new(
+        path.to_path_buf(),
+        &keyboard,
+        &count,
+        reference,
+        &config.eval.weights,
+    )?;
+    keyboard::tui::run(app)
+}
+
+#[cfg(feature = "server")]
+fn run_serve(config: &Config, addr: &str) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    keyboard::server::serve(addr, &keyboard, &count)
+}
+
+#[cfg(feature = "capture")]
+fn run_capture(config: &Config, output: &Path) -> AnyResult<()> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || handler_stop.store(true, Ordering::Relaxed))?;
+    println!(
+        "counting keystrokes into {}, ctrl-c to stop",
+        output.display()
+    );
+
+    let session = keyboard::capture::capture(config.eval.depth, &stop)?;
+    let mut count = match output.exists() {
+        true => counter::import(output)?,
+        false => counter::CountOutcome::default(),
+    };
+    count.merge(&session);
+    counter::export(&count, output)?;
+    println!(
+        "{} keystrokes this session, {} in total",
+        session.letter.values().sum::<u32>(),
+        count.letter.values().sum::<u32>()
+    );
+    Ok(())
+}
+
+fn run_watch(config: &Config, path: &Path) -> AnyResult<()> {
+    use notify::{RecursiveMode, Watcher as _};
+
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let weights = &config.eval.weights;
+
+    let evaluate_file = || -> AnyResult<(Layout, Evaluation)> {
+        let layout = Layout::load(path)?;
+        let info =
+            KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+        let scaled = evaluate::evaluate(&info, &count) / reference * 100.0;
+        Ok((layout, scaled))
+    };
+
+    // editors often save by replacing the file, so watch its directory instead
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty());
+    watcher.watch(dir.unwrap_or(Path::new(".")), RecursiveMode::NonRecursive)?;
+
+    let mut last: Option<Evaluation> = None;
+    loop {
+        match evaluate_file() {
+            Ok((layout, scaled)) => {
+                output::print_layout(&layout, &keyboard);
+                let previous = last.unwrap_or(scaled);
+                output::print_eval_deltas(
+                    &previous,
+                    &scaled,
+                    (weights.score(&previous), weights.score(&scaled)),
+                    weights,
+                );
+                last = Some(scaled);
+            }
+            Err(err) => println!("{err:#}"),
+        }
+        println!("watching {}", path.display());
+
+        loop {
+            let event = rx.recv()??;
+            let touched = event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == path.file_name());
+            if touched && (event.kind.is_modify() || event.kind.is_create()) {
+                break;
+            }
+        }
+        // let the rest of the save land before reading
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+fn run_compare(config: &Config, a: &Path, b: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let (a, b) = (Layout::load(a)?, Layout::load(b)?);
+
+    let scaled = |layout: &Layout| {
+        KeyboardLayout::generate(layout, &keyboard)
+            .map(|info| evaluate::evaluate(&info, &count) / reference * 100.0)
+            .map_err(|k| anyhow!("layout {k}"))
+    };
+    let (a_eval, b_eval) = (scaled(&a)?, scaled(&b)?);
+    let weights = &config.eval.weights;
+
+    // each layer of `a` next to the same layer of `b`
+    let layers = (0..a.layers().len().max(b.layers().len()))
+        .flat_map(|li| {
+            [&a, &b].map(|layout| match li < layout.layers().len() {
+                true => output::render_layer(layout, &keyboard, li),
+                false => String::new(),
+            })
+        })
+        .collect::<Vec<_>>();
+    output::print_columns(&layers, 2);
+    output::print_eval_deltas(
+        &a_eval,
+        &b_eval,
+        (weights.score(&a_eval), weights.score(&b_eval)),
+        weights,
+    );
+    Ok(())
+}
+
+fn run_drift(
+    config: &Config,
+    old: &Path,
+    new: &Path,
+    layout: Option<&Path>,
+    top: usize,
+) -> AnyResult<()> {
+    let count = |corpus: &Path| {
+        let mut config = config.clone();
+        config.eval.corpus = corpus.to_path_buf();
+        config.eval.corpora.clear();
+        count_corpus(&config)
+    };
+    let (old, new) = (count(old)?, count(new)?);
+    output::print_corpus_diff(&report::corpus_diff(&old, &new, top));
+
+    let keyboard = config.eval.keyboard()?;
+    let layout = Layout::load(layout.unwrap_or(&config.optimize.output))?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    // each corpus against the reference on that corpus, so only the mix changes
+    let scaled = |count: &counter::CountOutcome| -> AnyResult<Evaluation> {
+        Ok(
+            evaluate::evaluate(&info, count) / reference_eval(&config.eval, &keyboard, count)?
+                * 100.0,
+        )
+    };
+    let (old_eval, new_eval) = (scaled(&old)?, scaled(&new)?);
+    let weights = &config.eval.weights;
+    let scores = (weights.score(&old_eval), weights.score(&new_eval));
+
+    println!();
+    output::print_eval_deltas(&old_eval, &new_eval, scores, weights);
+    let drift = (scores.1 - scores.0) / scores.0.abs();
+    println!("score drift {:+.1}%", drift * 100.0);
+    if drift > report::REOPTIMIZE_DRIFT {
+        println!("the layout does noticeably worse on the new corpus, consider re-optimizing");
+    }
+    Ok(())
+}
+
+fn run_simulate(config: &Config, layout: &Path, text: &Path) -> AnyResult<()> {
+    let layout = Layout::load(layout)?;
+    let keyboard = config.eval.keyboard()?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    let text = std::fs::read(text)?;
+    output::print_simulation(&simulate::simulate(&info, &text));
+    Ok(())
+}
+
+fn run_rank(config: &Config, dir: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let ranked = report::rank_layouts(dir, &keyboard, &count, reference, &config.eval.weights)?;
+    output::print_ranking(&ranked);
+    Ok(())
+}
+
+fn run_digits(config: &Config, path: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let layouts = report::digit_arrangements(&Layout::load(path)?)?;
+    let layers = layouts
+        .iter()
+        .filter_map(|(name, layout)| {
+            let layout = layout.as_ref()?;
+            let li = report::digit_layer(layout)?;
+            Some(format!(
+                "{name}\n{}",
+                output::render_layer(layout, &keyboard, li)
+            ))
+        })
+        .collect::<Vec<_>>();
+    output::print_columns(&layers, layers.len());
+    let ranked = report::rank_named(layouts, &keyboard, &count, reference, &config.eval.weights);
+    println!("relative to {}", config.eval.reference);
+    output::print_ranking(&ranked);
+    Ok(())
+}
+
+fn run_shifts(config: &Config, path: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let layout = Layout::load(path)?;
+    let choices =
+        report::shift_choices(&layout, &keyboard, &count, reference, &config.eval.weights)?;
+    output::print_shift_choices(&choices);
+    Ok(())
+}
+
+fn run_thumbs(config: &Config, path: &Path) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let layout = Layout::load(path)?;
+    let weights = &config.eval.weights;
+    let choices = report::thumb_choices(&layout, &keyboard, &count, reference, weights)?;
+    output::print_thumb_choices(&choices);
+    Ok(())
+}
+
+fn run_speed(config: &Config, path: Option<&Path>, rounds: u32) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let layout = Layout::load(path.unwrap_or(&config.optimize.start))?;
+    let rounds = rounds.max(1);
+
+    let started = Instant::now();
+    for _ in 0..rounds {
+        KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    }
+    let generate = started.elapsed() / rounds;
+    // a fresh layout every round, as costs are cached per layout
+    let started = Instant::now();
+    for _ in 0..rounds {
+        let info =
+            KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+        std::hint::black_box(evaluate::evaluate(&info, &count));
+    }
+    let evaluation = (started.elapsed() / rounds).saturating_sub(generate);
+    let started = Instant::now();
+    for _ in 0..rounds {
+        std::hint::black_box(layout.clone());
+    }
+    let clone = started.elapsed() / rounds;
+
+    let ngrams =
+        count.letter.len() + count.bigrams.len() + count.skipgrams.len() + count.trigrams.len();
+    println!("clone     {clone:>12.2?}");
+    println!("generate  {generate:>12.2?}");
+    println!("evaluate  {evaluation:>12.2?} over {ngrams} n-grams");
+    println!(
+        "{:.0} iterations per second",
+        1.0 / (generate + evaluation).as_secs_f64()
+    );
+    Ok(())
+}
+
+fn run_benchmark(config: &Config, paths: &[PathBuf]) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let extra = paths
+        .iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            Ok((name, Layout::load(path)?))
+        })
+        .collect::<AnyResult<_>>()?;
+    let ranked = report::benchmark(extra, &keyboard, &count, reference, &config.eval.weights);
+    println!("relative to {}", config.eval.reference);
+    output::print_ranking(&ranked);
+    Ok(())
+}
+
+/// The second keyboard of a joint run, scaled against its own reference and
+/// starting layout like the first.
+struct JointBoard<'a> {
+    config: &'a JointConfig,
+    keyboard: Keyboard,
+    reference: Evaluation,
+    scaler: f32,
+}
+
+impl<'a> JointBoard<'a> {
+    fn new(
+        config: &'a JointConfig,
+        eval_config: &EvalConfig,
+        start: &Layout,
+        count: &counter::CountOutcome,
+    ) -> AnyResult<Self> {
+        let keyboard = Keyboard::by_name(&config.keyboard)?;
+        let reference = reference_eval(eval_config, &keyboard, count)?;
+        let start_kl =
+            KeyboardLayout::generate(start, &keyboard).map_err(|k| anyhow!("start layout {k}"))?;
+        let start_eval = evaluate::evaluate(&start_kl, count) / reference * 100.0;
+        let scaler = 1_000_000.0 / eval_config.weights.score(&start_eval);
+        Ok(Self {
+            config,
+            keyboard,
+            reference,
+            scaler,
+        })
+    }
+}
+
+/// Evaluations patched by [`KeyboardLayout::evaluate_delta`] in a row before
+/// one is made afresh, so the rounding of the patches cannot add up.
+const RESYNC_PATCHES: u32 = 256;
+
+/// Anneals `optimize.start` on `count` from `seed` until the configured
+/// iterations are done or `stop` is set.
+fn anneal_seeded(
+    config: &Config,
+    count: &counter::CountOutcome,
+    seed: u64,
+    stop: &AtomicBool,
+) -> AnyResult<optimization::AnnealResult> {
+    let eval_config = &config.eval;
+    let opt_config = &config.optimize;
+    let keyboard = eval_config.keyboard()?;
+    let start_layout = Layout::load(&opt_config.start)?;
+
+    let markov = (eval_config.backend == EvalBackend::Markov)
+        .then(|| markov::MarkovModel::from_counts(count, eval_config.markov_min_probability));
+    let raw_evaluation = |info: &KeyboardLayout| match &markov {
+        Some(model) => markov::evaluate(info, model),
+        None => evaluate::evaluate(info, count),
+    };
+
+    let reference_layout = eval_config.reference_layout()?;
+    let reference_eval = KeyboardLayout::generate(&reference_layout, &keyboard)
+        .map(|info| raw_evaluation(&info))
+        .map_err(|k| anyhow!("reference {k}"))?;
+    let scale_evaluation = |eval: Evaluation| eval / reference_eval * 100.0;
+
+    let start_kl = KeyboardLayout::generate(&start_layout, &keyboard)
+        .map_err(|k| anyhow!("start layout {k}"))?;
+    let start_raw = raw_evaluation(&start_kl);
+    let start_eval = scale_evaluation(start_raw);
+    let start_evaluation = eval_config.weights.score(&start_eval);
+    // negative weights can make the start score negative, which must not turn
+    // the search around
+    let eval_scaler = 1_000_000.0 / start_evaluation.abs();
+    let protected = opt_config
+        .protected
+        .iter()
+        .map(|(name, &cap)| {
+            let at = start_eval
+                .fields()
+                .iter()
+                .position(|(field, _)| field == name);
+            Ok((
+                at.ok_or_else(|| anyhow!("unknown protected metric {name}"))?,
+                cap,
+            ))
+        })
+        .collect::<AnyResult<Vec<_>>>()?;
+    let protected_values = |eval: &Evaluation| {
+        let fields = eval.fields();
+        protected
+            .iter()
+            .map(|&(at, _)| fields[at].1)
+            .collect::<Vec<_>>()
+    };
+    // protected metrics of the current layout, updated on every accepted move
+    let current_protected = RefCell::new(protected_values(&start_eval));
+    // the current layout on the keyboard, its raw evaluation and the patches
+    // made to that since it was last evaluated in full; a candidate differing
+    // only in keys is evaluated from it
+    let current_info = RefCell::new((start_kl, start_raw, 0));
+    let affinity = learnability::PositionAffinity::load_or_default(&eval_config.affinity)?;
+    let learnability = learnability::Learnability::load_or_default(&eval_config.learnability)?;
+    let joint = opt_config
+        .joint
+        .as_ref()
+        .map(|joint| JointBoard::new(joint, eval_config, &start_layout, count))
+        .transpose()?;
+
+    let thumbs = keyboard.thumb_keys();
+    let mut annealer = optimization::Annealer::new(
+        start_layout.clone(),
+        opt_config,
+        &thumbs,
+        optimization::seeded_rng(seed),
         |_, layout| {
-            let any_other_alphabetic = layout.layers().iter().skip(1).any(|layer| {
-                layer
-                    .keys()
-                    .iter()
-                    .any(|key| key.is_some_and(|k| matches!(k.get(), b'a'..=b'z')))
-            });
+            let any_other_alphabetic = layout
+                .iter_keys()
+                .any(|(loc, k)| loc.layer() > 0 && k.char().is_ascii_lowercase());
             let layers_with_numbers = layout
-                .layers()
-                .iter()
-                .map(|layer| {
-                    layer
-                        .keys()
-                        .iter()
-                        .any(|k| k.is_some_and(|k| matches!(k.get(), b'0'..=b'9')))
-                        as u8
-                })
-                .sum::<u8>();
-            if any_other_alphabetic || layers_with_numbers > 1 {
+                .iter_keys()
+                .filter(|(_, k)| k.char().is_ascii_digit())
+                .map(|(loc, _)| loc.layer())
+                .collect::<HashSet<_>>();
+            if any_other_alphabetic || layers_with_numbers.len() > 1 {
                 return None;
             }
 
             let mut keys = HashSet::new();
             let mut holds = HashSet::new();
-            let info = KeyboardLayout::generate_with_usage(
-                layout,
+            let info = KeyboardLayout::generate_owned(
+                layout.clone(),
                 &keyboard,
                 Some(&mut keys),
                 Some(&mut holds),
             )
             .ok()?;
 
-            let scaled = scale_evaluation(evaluate::evaluate(&info, &count));
-            Some((to_evaluation(&scaled) * eval_scaler, (keys, holds)))
+            let (raw, patches) = match &markov {
+                Some(_) => (raw_evaluation(&info), 0),
+                None => {
+                    let (old, old_raw, patches) = &*current_info.borrow();
+                    match *patches < RESYNC_PATCHES {
+                        true => (info.evaluate_delta(old, old_raw, count), patches + 1),
+                        false => (evaluate::evaluate(&info, count), 0),
+                    }
+                }
+            };
+            let scaled = scale_evaluation(raw);
+            let values = protected_values(&scaled);
+            let worsened = values
+                .iter()
+                .zip(current_protected.borrow().iter())
+                .zip(&protected)
+                .any(|((&new, &current), &(_, cap))| new > cap.max(current));
+            if worsened {
+                return None;
+            }
+            let mut score = eval_config.weights.score(&scaled) * eval_scaler;
+            if let Some(joint) = &joint {
+                let joint_info = KeyboardLayout::generate(layout, &joint.keyboard).ok()?;
+                let joint_scaled = evaluate::evaluate(&joint_info, count) / joint.reference * 100.0;
+                let joint_score = eval_config.weights.score(&joint_scaled) * joint.scaler;
+                score = (1.0 - joint.config.weight) * score + joint.config.weight * joint_score
+                    - joint.config.consistency_weight
+                        * joint::finger_consistency(&info, &joint.keyboard, &count.letter);
+            }
+            let score = score + eval_config.affinity_weight * affinity.penalty(layout)
+                - eval_config.learnability_weight * learnability.bonus(layout, &keyboard);
+            Some((score, raw, (keys, holds, values, (info, raw, patches))))
         },
-        |rng, layout, (keys, holds)| {
+        |rng, layout, (keys, holds, values, evaluated)| {
+            *current_protected.borrow_mut() = values;
+            *current_info.borrow_mut() = evaluated;
             let size = layout.layer_size();
 
             for i in 0..size {
@@ -133,23 +1098,561 @@ fn main() {
                 }
             }
 
-            for (li, layer) in layout.layers_mut().iter_mut().enumerate() {
-                for i in 0..size {
-                    let loc = KeyLoc::new(li as u8, i);
-                    if !keys.contains(&loc) && rng.random_bool(0.7) {
-                        *layer.key_mut(i) = None;
-                    }
+            for loc in layout.locs() {
+                if !keys.contains(&loc) && rng.random_bool(0.7) {
+                    layout.set_key_at(loc, None);
                 }
             }
         },
-    );
+    )?;
+    println!("(0,{}),", annealer.best().score);
+    while !stop.load(Ordering::Relaxed) {
+        let Some(step) = annealer.next() else { break };
+        if step.iteration.is_multiple_of(1000) && step.iteration < opt_config.iterations {
+            println!("({},{}),", step.iteration, step.score);
+        }
+    }
+    Ok(annealer.finish())
+}
+
+/// The seed of a run: the configured one, 0 when deterministic, else random.
+fn run_seed(config: &Config) -> u64 {
+    match (config.optimize.seed, config.deterministic) {
+        (Some(seed), _) => seed,
+        (None, true) => 0,
+        (None, false) => rand::rng().random(),
+    }
+}
+
+fn run_optimize(config: &Config) -> AnyResult<()> {
+    let opt_config = &config.optimize;
+    let seed = run_seed(config);
+    let count = count_corpus(config)?;
+
+    // the first ctrl-c finishes the run with the best layout so far, a second one exits
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = stop.clone();
+    ctrlc::set_handler(move || {
+        if handler_stop.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("stopping, press ctrl-c again to exit without saving");
+    })?;
+
+    let result = anneal_seeded(config, &count, seed, &stop)?;
+    let score = result.score;
+    let interrupted = stop.load(Ordering::Relaxed);
+    let (summary, status) = match interrupted {
+        true => ("Epoch Interrupted!", "was interrupted"),
+        false => ("Epoch Finished!", "is complete"),
+    };
     let _ = Notification::new()
-        .summary("Epoch Finished!")
+        .summary(summary)
         .body(&format!(
-            "Training for {THIS_PATH} is complete, with score {score}."
+            "Training for {} {status}, with score {score}.",
+            opt_config.output.display()
         ))
         .show();
-    let json = serde_json::to_string_pretty(&result).unwrap();
-    std::fs::write(THIS_PATH, json).unwrap();
-    output::print_ferris_layout(&result);
+    result.layout.save(&opt_config.output)?;
+    let keyboard = config.eval.keyboard()?;
+    output::print_layout(&result.layout, &keyboard);
+    let stats = &result.stats;
+    println!(
+        "best score {score} after {} iterations, {} moves accepted, {} mutations rejected",
+        stats.iterations, stats.accepted, stats.rejected
+    );
+    if interrupted {
+        let checkpoint = optimization::Checkpoint {
+            seed,
+            iteration: stats.iterations,
+            score,
+            layout: result.layout.clone(),
+        };
+        let path = opt_config.output.with_extension("checkpoint.json");
+        checkpoint.save(&path)?;
+        println!(
+            "interrupted after {} of {} iterations, best score {score}, checkpoint in {}",
+            checkpoint.iteration,
+            opt_config.iterations,
+            path.display()
+        );
+    }
+
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    // evaluated afresh, as the run may have used the markov backend
+    let final_eval = KeyboardLayout::generate(&result.layout, &keyboard)
+        .map(|info| evaluate::evaluate(&info, &count))
+        .map_err(|k| anyhow!("result {k}"))?;
+    let weighted_score = config.eval.weights.score(&(final_eval / reference * 100.0));
+    // the layout is saved already, so a history that cannot be written only warns
+    let logged = history::History::new(HISTORY_PATH).append(&history::RunRecord {
+        timestamp: if config.deterministic {
+            0
+        } else {
+            history::now()
+        },
+        profile: config.profile.clone(),
+        config: serde_json::to_value(config)?,
+        seed,
+        trajectory: result.stats.trajectory,
+        score,
+        weighted_score: Some(weighted_score),
+        layout_hash: history::layout_hash(&result.layout),
+        output: opt_config.output.clone(),
+    });
+    if let Err(error) = logged {
+        eprintln!("warning: run not logged in {HISTORY_PATH}: {error:#}");
+    }
+    Ok(())
+}
+
+fn run_stability(config: &Config, runs: u32) -> AnyResult<()> {
+    let count = count_corpus(config)?;
+    let first = run_seed(config);
+    let seeds = (0..u64::from(runs))
+        .map(|i| first.wrapping_add(i))
+        .collect::<Vec<_>>();
+    let stop = AtomicBool::new(false);
+    let results = seeds
+        .iter()
+        .map(|&seed| {
+            let result = anneal_seeded(config, &count, seed, &stop)?;
+            Ok((result.layout, result.score))
+        })
+        .collect::<AnyResult<Vec<_>>>()?;
+    output::print_stability(&seeds, &report::stability(&results));
+    Ok(())
+}
+
+fn run_beam(config: &Config) -> AnyResult<()> {
+    let opt_config = &config.optimize;
+    let count = count_corpus(config)?;
+    let keyboard = config.eval.keyboard()?;
+    let reference = reference_eval(&config.eval, &keyboard, &count)?;
+    let score = |eval: &Evaluation| config.eval.weights.score(&(*eval / reference * 100.0));
+    let start = Layout::load(&opt_config.start)?;
+
+    let result = beam::beam_search(&start, &keyboard, &count, opt_config.beam_width, score)?;
+    let full_score = |layout: &Layout| {
+        KeyboardLayout::generate(layout, &keyboard)
+            .map(|info| score(&evaluate::evaluate(&info, &count)))
+            .map_err(|k| anyhow!("layout {k}"))
+    };
+    output::print_layout(&result, &keyboard);
+    println!(
+        "start {}, beam {}",
+        full_score(&start)?,
+        full_score(&result)?
+    );
+    result.save(&opt_config.output)
+}
+
+struct NavArgs<'a> {
+    layout: &'a Path,
+    usage: &'a Path,
+    layer_key: Option<usize>,
+    output: Option<&'a Path>,
+}
+
+fn run_nav(config: &Config, args: NavArgs) -> AnyResult<()> {
+    let layout = Layout::load(args.layout)?;
+    let keyboard = config.eval.keyboard()?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    let usage = nav::load_usage(args.usage)?;
+
+    let layer_key = match args.layer_key {
+        Some(key) => key,
+        None => *nav::free_layer_keys(&info)
+            .first()
+            .ok_or_else(|| anyhow!("no free left-hand key to hold the nav layer"))?,
+    };
+    let nav_layer = nav::generate(&info, &config.eval.weights, layer_key, &usage);
+
+    eprintln!("held from key {layer_key}");
+    for (i, key) in nav_layer.keys.iter().enumerate() {
+        if let Some(key) = key {
+            eprintln!("key {i:>2}: {}", key.to_qmk()?);
+        }
+    }
+    for u in &nav_layer.dropped {
+        eprintln!("no room for {} ({})", u.key.to_qmk()?, u.freq);
+    }
+
+    let text = serde_json::to_string_pretty(&nav::attach(&info, &nav_layer)?)?;
+    match args.output {
+        Some(path) => std::fs::write(path, text)?,
+        None => println!("{text}"),
+    }
+    Ok(())
+}
+
+fn run_calibrate(
+    config: &Config,
+    recording: &Path,
+    layout: &Path,
+    output_path: &Path,
+) -> AnyResult<()> {
+    let recording = calibration::load_recording(recording)?;
+    let layout = Layout::load(layout)?;
+    // fitted on the bare geometry, not on top of an earlier calibration
+    let keyboard = Keyboard::by_name(&config.eval.keyboard)?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+
+    let calibration = calibration::Calibration::fit(&info, &recording);
+    for (i, offset) in calibration.offsets.iter().enumerate() {
+        if *offset > 0.0 {
+            println!("key {i:>2}: +{offset:.2}");
+        }
+    }
+    calibration.save(output_path)
+}
+
+fn run_history(command: Option<HistoryCommand>, profile: Option<&str>) -> AnyResult<()> {
+    let history = history::History::new(HISTORY_PATH);
+    let records = match command.unwrap_or(HistoryCommand::List) {
+        HistoryCommand::List => history.for_profile(profile)?,
+        HistoryCommand::Best => history.best(profile)?.into_iter().collect(),
+    };
+    for r in records {
+        println!(
+            "{:>10} {:<16} {:>14} {} seed={} {}",
+            r.timestamp,
+            r.profile,
+            r.weighted_score
+                .map_or("-".to_string(), |score| format!("{score:.1}")),
+            r.layout_hash,
+            r.seed,
+            r.output.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_convert(
+    config: &Config,
+    path: &Path,
+    target: ConvertTarget,
+    output_path: Option<&Path>,
+) -> AnyResult<()> {
+    let layout = Layout::load(path)?;
+    let host = |target| host::export(&layout, &config.eval.keyboard()?, target);
+    if !layout.combos().is_empty() && !matches!(target, ConvertTarget::Markdown) {
+        eprintln!("combos are not exported, define them in the firmware or remapper");
+    }
+    let text = match target {
+        ConvertTarget::Qmk => serde_json::to_string_pretty(&QmkKeymap::from_layout(layout)?)?,
+        ConvertTarget::Kanata => host(host::HostTarget::Kanata)?,
+        ConvertTarget::Keyd => host(host::HostTarget::Keyd)?,
+        ConvertTarget::Karabiner => host(host::HostTarget::Karabiner)?,
+        ConvertTarget::Markdown => markdown::export(&layout),
+    };
+    match output_path {
+        Some(path) => std::fs::write(path, text)?,
+        None => println!("{text}"),
+    }
+    Ok(())
+}
+
+fn run_lp(
+    config: &Config,
+    path: &Path,
+    sfb_weight: f32,
+    output_path: Option<&Path>,
+) -> AnyResult<()> {
+    let layout = Layout::load(path)?;
+    let keyboard = config.eval.keyboard()?;
+    let count = count_corpus(config)?;
+    let info = KeyboardLayout::generate(&layout, &keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    let problem = lp::PlacementProblem::new(&info, &count, &config.eval.weights, sfb_weight);
+
+    let mut text = vec![];
+    problem.write_lp(&mut text)?;
+    match output_path {
+        Some(path) => std::fs::write(path, text)?,
+        None => std::io::Write::write_all(&mut std::io::stdout(), &text)?,
+    }
+    let current = (0..problem.keys.len()).collect::<Vec<_>>();
+    eprintln!(
+        "objective of the layout as given: {}",
+        problem.objective(&current)
+    );
+    Ok(())
+}
+
+fn run_validate(paths: &[PathBuf]) -> AnyResult<()> {
+    let mut failed = 0;
+    for path in paths {
+        let checked = Layout::load(path).and_then(|layout| {
+            layout
+                .reachability()
+                .check()
+                .with_context(|| format!("bad layout {}", path.display()))?;
+            Ok(layout)
+        });
+        match checked {
+            Ok(layout) => println!(
+                "{}: ok, {} keys, {} layers",
+                path.display(),
+                layout.layer_size(),
+                layout.layer_count()
+            ),
+            Err(err) => {
+                // the error names the file
+                println!("{err:#}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} layouts are invalid", paths.len());
+    }
+    Ok(())
+}
+
+fn run_resize(
+    keyboard: &Keyboard,
+    path: &Path,
+    map: Option<&str>,
+    add_layer: bool,
+    remove_layer: Option<u8>,
+    output: Option<&Path>,
+) -> AnyResult<()> {
+    let mut layout = Layout::load(path)?;
+    if let Some(layer) = remove_layer {
+        layout.remove_layer(layer)?;
+    }
+    if add_layer {
+        layout.add_layer()?;
+    }
+    if let Some(spec) = map {
+        let map = keyboard::layout::parse_key_map(spec, layout.layer_size())?;
+        let resized = layout.remap(&map);
+        // everything on a key the map leaves out is lost
+        for old in (0..layout.layer_size()).filter(|i| !map.contains(&Some(*i))) {
+            let taps = layout.layers().filter_map(|layer| layer[old]);
+            let taps = taps.map(|key| format!("{key:?}")).collect::<Vec<_>>();
+            if let Some(hold) = layout.base_hold()[old] {
+                println!("key {old} dropped its hold {hold:?}");
+            }
+            if !taps.is_empty() {
+                println!("key {old} dropped {}", taps.join(" "));
+            }
+        }
+        if resized.combos().len() < layout.combos().len() {
+            println!(
+                "dropped {} combos",
+                layout.combos().len() - resized.combos().len()
+            );
+        }
+        layout = resized;
+    }
+    match layout.layer_size() {
+        size if size <= keyboard.keys().len() => output::print_layout(&layout, keyboard),
+        size => println!("{size} keys, {} layers", layout.layer_count()),
+    }
+    layout.save(output.unwrap_or(path))
+}
+
+fn run_translate(config: &Config, path: &Path, reverse: bool) -> AnyResult<()> {
+    let layout = Layout::load(path)?;
+    let qwerty = ferris::qwerty();
+    let shift = &config.eval.shift;
+    let translator = match reverse {
+        true => translate::Translator::new(&layout, &qwerty, shift)?,
+        false => translate::Translator::new(&qwerty, &layout, shift)?,
+    };
+    let missing = translator.run(std::io::stdin().lock(), std::io::stdout().lock())?;
+    if missing > 0 {
+        eprintln!(
+            "{missing} characters cannot be typed, shown as {}",
+            translate::MISSING
+        );
+    }
+    Ok(())
+}
+
+fn run_gallery(config: &Config, command: Option<GalleryCommand>) -> AnyResult<()> {
+    let mut gallery = gallery::Gallery::open(GALLERY_PATH)?;
+
+    match command.unwrap_or(GalleryCommand::List) {
+        GalleryCommand::Save { name, layout, tags } => {
+            gallery.save(&name, tags, Layout::load(layout)?)?;
+        }
+        GalleryCommand::Champion { name: Some(name) } => gallery.set_champion(&name)?,
+        GalleryCommand::Champion { name: None } => {
+            println!("{}", gallery.champion().unwrap_or("(none)"))
+        }
+        GalleryCommand::List => {
+            let count = count_corpus(config)?;
+            let keyboard = config.eval.keyboard()?;
+            let reference = reference_eval(&config.eval, &keyboard, &count)?;
+            let cache_key = gallery::cache_key(&count, &keyboard, &config.eval);
+
+            for mut entry in gallery.entries()? {
+                let eval = gallery.evaluation(&mut entry, &cache_key, |layout| {
+                    let kl = KeyboardLayout::generate(layout, &keyboard).ok()?;
+                    Some(evaluate::evaluate(&kl, &count))
+                })?;
+                let score = eval.map(|e| config.eval.weights.score(&(e / reference * 100.0)));
+                let marker = match gallery.champion() == Some(entry.name.as_str()) {
+                    true => "*",
+                    false => " ",
+                };
+                match score {
+                    Some(score) => print!("{marker} {:<24} {score:>12.1}", entry.name),
+                    None => print!("{marker} {:<24} {:>12}", entry.name, "invalid"),
+                }
+                println!("  {}", entry.tags.join(", "));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> AnyResult<()> {
+    let cli = Cli::parse();
+    let mut config = Config::load(&cli.config)?;
+    if let Some(corpus) = cli.corpus {
+        config.eval.corpus = corpus;
+        config.eval.corpora.clear();
+    }
+    if let Some(keyboard) = cli.keyboard {
+        config.eval.keyboard = keyboard;
+    }
+    if !cli.extensions.is_empty() {
+        config.eval.extensions = Some(cli.extensions);
+    }
+    if !cli.ignore.is_empty() {
+        config.eval.ignore = cli.ignore;
+    }
+    config.deterministic |= cli.deterministic;
+    // `auto` is what colored does by itself
+    match cli.color {
+        ColorMode::Auto => {}
+        ColorMode::Always => colored::control::set_override(true),
+        ColorMode::Never => colored::control::set_override(false),
+    }
+    output::set_palette(config.palette);
+
+    match cli.command {
+        Command::Count { top, export } => run_count(&config, top, export.as_deref())?,
+        Command::Stats { top } => run_stats(&config, top)?,
+        Command::Languages { min_share } => run_languages(&config, min_share)?,
+        Command::Share { output, min_count } => run_share(&config, &output, min_count)?,
+        Command::Eval {
+            layout,
+            chords,
+            markov,
+            hands,
+        } => run_eval(&config, &layout, chords.as_deref(), markov, hands)?,
+        Command::Offenders {
+            layout,
+            metric,
+            top,
+        } => run_offenders(&config, &layout, &metric, top)?,
+        #[cfg(feature = "tui")]
+        Command::Tui { layout } => run_tui(&config, &layout)?,
+        Command::Watch { layout } => run_watch(&config, &layout)?,
+        #[cfg(feature = "server")]
+        Command::Serve { addr } => run_serve(&config, &addr)?,
+        #[cfg(feature = "capture")]
+        Command::Capture { output } => run_capture(&config, &output)?,
+        Command::Simulate { layout, text } => run_simulate(&config, &layout, &text)?,
+        Command::Drift {
+            old,
+            new,
+            layout,
+            top,
+        } => run_drift(&config, &old, &new, layout.as_deref(), top)?,
+        Command::Compare { a, b } => run_compare(&config, &a, &b)?,
+        Command::Rank { dir } => run_rank(&config, &dir)?,
+        Command::Benchmark { layouts } => run_benchmark(&config, &layouts)?,
+        Command::Speed { layout, rounds } => run_speed(&config, layout.as_deref(), rounds)?,
+        Command::Optimize {
+            start,
+            output,
+            iterations,
+            profile,
+            seed,
+        } => {
+            let opt = &mut config.optimize;
+            opt.start = start.unwrap_or(opt.start.clone());
+            opt.output = output.unwrap_or(opt.output.clone());
+            opt.iterations = iterations.unwrap_or(opt.iterations);
+            opt.seed = seed.or(opt.seed);
+            config.profile = profile.unwrap_or(config.profile);
+            run_optimize(&config)?
+        }
+        Command::Stability { runs, iterations } => {
+            let opt = &mut config.optimize;
+            opt.iterations = iterations.unwrap_or(opt.iterations / 10);
+            run_stability(&config, runs)?
+        }
+        Command::Beam {
+            start,
+            output,
+            width,
+        } => {
+            let opt = &mut config.optimize;
+            opt.start = start.unwrap_or(opt.start.clone());
+            opt.output = output.unwrap_or(opt.output.clone());
+            opt.beam_width = width.unwrap_or(opt.beam_width);
+            run_beam(&config)?
+        }
+        Command::Nav {
+            layout,
+            usage,
+            layer_key,
+            output,
+        } => run_nav(
+            &config,
+            NavArgs {
+                layout: &layout,
+                usage: &usage,
+                layer_key,
+                output: output.as_deref(),
+            },
+        )?,
+        Command::Calibrate {
+            recording,
+            layout,
+            output,
+        } => run_calibrate(&config, &recording, &layout, &output)?,
+        Command::History { command, profile } => run_history(command, profile.as_deref())?,
+        Command::Print { layout } => {
+            output::print_layout(&Layout::load(layout)?, &config.eval.keyboard()?)
+        }
+        Command::Validate { layouts } => run_validate(&layouts)?,
+        Command::Heatmap { layout } => run_heatmap(&config, layout.as_deref())?,
+        Command::Digits { layout } => run_digits(&config, &layout)?,
+        Command::Shifts { layout } => run_shifts(&config, &layout)?,
+        Command::Thumbs { layout } => run_thumbs(&config, &layout)?,
+        Command::Convert {
+            layout,
+            target,
+            output,
+        } => run_convert(&config, &layout, target, output.as_deref())?,
+        Command::Lp {
+            layout,
+            sfb_weight,
+            output,
+        } => run_lp(&config, &layout, sfb_weight, output.as_deref())?,
+        Command::Translate { layout, reverse } => run_translate(&config, &layout, reverse)?,
+        Command::Resize {
+            layout,
+            map,
+            add_layer,
+            remove_layer,
+            output,
+        } => run_resize(
+            &config.eval.keyboard()?,
+            &layout,
+            map.as_deref(),
+            add_layer,
+            remove_layer,
+            output.as_deref(),
+        )?,
+        Command::Gallery { command } => run_gallery(&config, command)?,
+    }
+    Ok(())
 }
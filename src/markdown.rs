@@ -0,0 +1,80 @@
+use std::fmt::Write as _;
+
+use crate::{
+    layout::{Behavior, Layout},
+    symbol::Symbol,
+};
+
+/// Columns of the split 3x5 grid, outermost left to outermost right.
+const COLUMNS: [&str; 10] = ["L5", "L4", "L3", "L2", "L1", "R1", "R2", "R3", "R4", "R5"];
+/// Columns the four thumb keys sit under.
+const THUMB_COLUMNS: std::ops::Range<usize> = 3..7;
+
+/// Every layer of `layout` as a GitHub-flavored Markdown table, the base
+/// layer with its hold behaviors under the taps, followed by the combos.
+pub fn export(layout: &Layout) -> String {
+    let mut out = String::new();
+    for (li, keys) in layout.layers().enumerate() {
+        let cell = |index: usize| {
+            let hold = (li == 0).then(|| layout.base_hold()[index]).flatten();
+            key_cell(keys[index], hold)
+        };
+
+        writeln!(out, "### Layer {li}\n").unwrap();
+        writeln!(out, "| {} |", COLUMNS.join(" | ")).unwrap();
+        writeln!(out, "|{}", ":---:|".repeat(COLUMNS.len())).unwrap();
+        for row in 0..3 {
+            let cells = (0..10)
+                .map(|column| cell(column * 3 + row))
+                .collect::<Vec<_>>();
+            writeln!(out, "| {} |", cells.join(" | ")).unwrap();
+        }
+        let thumbs = (0..10)
+            .map(|column| match THUMB_COLUMNS.contains(&column) {
+                true => cell(30 + column - THUMB_COLUMNS.start),
+                false => String::new(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "| {} |\n", thumbs.join(" | ")).unwrap();
+    }
+
+    if !layout.combos().is_empty() {
+        writeln!(out, "### Combos\n").unwrap();
+        writeln!(out, "| keys | types |\n|:---:|:---:|").unwrap();
+        for combo in layout.combos() {
+            let [a, b] = combo.keys.map(|index| key_cell(layout.key(0, index), None));
+            writeln!(out, "| {a} + {b} | {} |", code(&combo.output.to_string())).unwrap();
+        }
+    }
+    out
+}
+
+/// The tap of a key in code, with its hold in italics below it.
+fn key_cell(key: Option<Symbol>, hold: Option<Behavior>) -> String {
+    let tap = match key {
+        None => String::new(),
+        Some(Symbol::ENTER) => "RET".to_string(),
+        Some(Symbol::TAB) => "TAB".to_string(),
+        Some(Symbol::SPACE) => "SPC".to_string(),
+        Some(key) => code(&key.to_string()),
+    };
+    let hold = match hold {
+        None => return tap,
+        Some(Behavior::Shift) => "shift".to_string(),
+        Some(Behavior::Ctrl) => "ctrl".to_string(),
+        Some(Behavior::Alt) => "alt".to_string(),
+        Some(Behavior::Gui) => "gui".to_string(),
+        Some(Behavior::Layer(layer)) => format!("layer {layer}"),
+    };
+    format!("{tap}<br>*{hold}*")
+}
+
+/// `text` as a code span that survives a table: pipes are escaped even in
+/// code, and a backtick needs a longer fence.
+fn code(text: &str) -> String {
+    let text = text.replace('|', "\\|");
+    match text.contains('`') {
+        true => format!("`` {text} ``"),
+        false => format!("`{text}`"),
+    }
+}
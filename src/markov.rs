@@ -0,0 +1,126 @@
+use rustc_hash::FxHashMap;
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{
+        finger_load, one_bigram, one_letter, one_letter_fingers, BigramEval, Evaluation,
+        FingerEval, KeyboardLayout, LetterEval, TrigramEval,
+    },
+    symbol::Symbol,
+};
+
+const STATIONARY_ITERATIONS: usize = 200;
+const STATIONARY_TOLERANCE: f32 = 1e-7;
+
+/// Character-level Markov chain of a corpus: the chance of each character
+/// following another, and how often each character is typed in the long run.
+#[derive(Debug, Clone)]
+pub struct MarkovModel {
+    symbols: Vec<Symbol>,
+    /// per symbol, the following symbols with their probabilities
+    transitions: Vec<Vec<(usize, f32)>>,
+    stationary: Vec<f32>,
+}
+
+impl MarkovModel {
+    /// Builds the chain from the bigram counts, dropping transitions less
+    /// likely than `min_probability` so evaluation touches fewer pairs.
+    pub fn from_counts(count: &CountOutcome, min_probability: f32) -> Self {
+        let mut symbols = count.letter.keys().map(|&[ch]| ch).collect::<Vec<_>>();
+        symbols.sort_unstable();
+        let index = symbols
+            .iter()
+            .enumerate()
+            .map(|(i, &ch)| (ch, i))
+            .collect::<FxHashMap<_, _>>();
+
+        let mut transitions = vec![vec![]; symbols.len()];
+        for (&[a, b], &freq) in &count.bigrams {
+            if let (Some(&a), Some(&b)) = (index.get(&a), index.get(&b)) {
+                transitions[a].push((b, freq as f32));
+            }
+        }
+        for row in &mut transitions {
+            row.sort_unstable_by_key(|&(b, _)| b);
+            let total = row.iter().map(|(_, f)| f).sum::<f32>();
+            row.retain(|&(_, f)| f / total >= min_probability);
+            let kept = row.iter().map(|(_, f)| f).sum::<f32>();
+            row.iter_mut().for_each(|(_, f)| *f /= kept);
+        }
+
+        // power iteration, starting from the letter frequencies
+        let total = count.letter.values().sum::<u32>() as f32;
+        let mut stationary = symbols
+            .iter()
+            .map(|&ch| count.letter[&[ch]] as f32 / total)
+            .collect::<Vec<_>>();
+        for _ in 0..STATIONARY_ITERATIONS {
+            let mut next = vec![0.0; symbols.len()];
+            let mut dangling = 0.0;
+            for (a, row) in transitions.iter().enumerate() {
+                if row.is_empty() {
+                    dangling += stationary[a];
+                }
+                for &(b, p) in row {
+                    next[b] += stationary[a] * p;
+                }
+            }
+            // symbols never followed by anything restart the chain uniformly
+            let restart = dangling / symbols.len() as f32;
+            next.iter_mut().for_each(|x| *x += restart);
+
+            let change = next
+                .iter()
+                .zip(&stationary)
+                .map(|(x, y)| (x - y).abs())
+                .sum::<f32>();
+            stationary = next;
+            if change < STATIONARY_TOLERANCE {
+                break;
+            }
+        }
+
+        Self {
+            symbols,
+            transitions,
+            stationary,
+        }
+    }
+
+    /// Long-run share of keystrokes that are `ch`.
+    pub fn stationary(&self, ch: Symbol) -> f32 {
+        self.symbols
+            .binary_search(&ch)
+            .map_or(0.0, |i| self.stationary[i])
+    }
+}
+
+/// Expected cost of a single keystroke on `info` when typing text generated by
+/// `model`. Comparable to the n-gram [`crate::evaluate::evaluate`] divided by
+/// the number of keystrokes in the corpus.
+pub fn evaluate(info: &KeyboardLayout, model: &MarkovModel) -> Evaluation {
+    let mut letter = LetterEval::ZERO;
+    let mut bigram = BigramEval::ZERO;
+    let mut finger = FingerEval::ZERO;
+    for (a, row) in model.transitions.iter().enumerate() {
+        let (pa, ch) = (model.stationary[a], model.symbols[a]);
+        if pa == 0.0 {
+            continue;
+        }
+        let (cost, ways) = one_letter(info, [ch]);
+        letter += cost / ways * pa;
+        let (presses, ways) = one_letter_fingers(info, [ch]);
+        finger += presses / ways * pa;
+        for &(b, p) in row {
+            let (cost, ways) = one_bigram(info, [ch, model.symbols[b]]);
+            bigram += cost / ways * (pa * p);
+        }
+    }
+    finger.load = finger_load(&finger, info.keyboard().finger_targets());
+    Evaluation {
+        letter,
+        bigram,
+        trigram: TrigramEval::ZERO,
+        finger,
+    }
+}
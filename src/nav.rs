@@ -0,0 +1,192 @@
+use std::{num::NonZeroU8, path::Path, str::FromStr};
+
+use anyhow::{anyhow, Result as AnyResult};
+use array_map::ArrayMap;
+use serde::Deserialize;
+
+use crate::{
+    chord::{self, Modifier},
+    core::FingerState,
+    evaluate::{letter_cost, EvalWeights, KeyboardLayout},
+    keyboard::{Finger, Hand},
+    layout::Behavior,
+    qmk::{KeyCode, QmkKey, QmkKeymap, QmkLayer, QmkMods, SpecialKey},
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavTarget {
+    Special(SpecialKey),
+    Char(Symbol),
+}
+
+/// A key on the nav layer, such as `left`, `5` or `ctrl+z`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct NavKey {
+    mods: Vec<Modifier>,
+    target: NavTarget,
+}
+
+impl FromStr for NavKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mods, key) = chord::split_chord(s)?;
+        let target = match key.to_ascii_lowercase().as_str() {
+            "left" => NavTarget::Special(SpecialKey::Left),
+            "right" => NavTarget::Special(SpecialKey::Right),
+            "up" => NavTarget::Special(SpecialKey::Up),
+            "down" => NavTarget::Special(SpecialKey::Down),
+            "home" => NavTarget::Special(SpecialKey::Home),
+            "end" => NavTarget::Special(SpecialKey::End),
+            "pgup" | "pageup" => NavTarget::Special(SpecialKey::PageUp),
+            "pgdn" | "pagedown" => NavTarget::Special(SpecialKey::PageDown),
+            "bspc" | "backspace" => NavTarget::Special(SpecialKey::Backspace),
+            "del" | "delete" => NavTarget::Special(SpecialKey::Delete),
+            "esc" | "escape" => NavTarget::Special(SpecialKey::Escape),
+            "ins" | "insert" => NavTarget::Special(SpecialKey::Insert),
+            _ => NavTarget::Char(
+                chord::key_symbol(key).ok_or_else(|| anyhow!("unknown key {key:?} in {s:?}"))?,
+            ),
+        };
+        Ok(Self { mods, target })
+    }
+}
+
+impl TryFrom<String> for NavKey {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl NavKey {
+    pub fn to_qmk(&self) -> AnyResult<QmkKey> {
+        let name = match self.target {
+            NavTarget::Special(key) => key.as_str(),
+            NavTarget::Char(ch) => ch
+                .ascii()
+                .and_then(|byte| KeyCode::try_from(byte).ok())
+                .ok_or_else(|| anyhow!("no qmk keycode for {ch:?}"))?
+                .as_str(),
+        };
+        let mut mods = QmkMods::default();
+        for m in &self.mods {
+            match m {
+                Modifier::Shift => mods.shift = true,
+                Modifier::Ctrl => mods.ctrl = true,
+                Modifier::Alt => mods.alt = true,
+                Modifier::Gui => mods.gui = true,
+            }
+        }
+        Ok(QmkKey::Shortcut(mods, name))
+    }
+}
+
+/// How often a navigation key or shortcut is used.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NavUsage {
+    pub key: NavKey,
+    pub freq: u32,
+}
+
+pub fn load_usage(path: impl AsRef<Path>) -> AnyResult<Vec<NavUsage>> {
+    let data = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+/// A layer for the left hand alone, held from `layer_key` on the base layer.
+#[derive(Debug, Clone)]
+pub struct NavLayer {
+    pub layer_key: usize,
+    pub keys: Vec<Option<NavKey>>,
+    /// usage that did not fit on the left hand
+    pub dropped: Vec<NavUsage>,
+}
+
+/// Cost of tapping `index` while `layer_key` is held, weighted like the
+/// letter metrics of the objective.
+fn position_cost(
+    info: &KeyboardLayout,
+    weights: &EvalWeights,
+    layer_key: usize,
+    index: usize,
+) -> f32 {
+    let keyboard = info.keyboard();
+    let mut positions = ArrayMap::new([FingerState::NONE; 10]);
+    for (i, hold) in [(index, false), (layer_key, true)] {
+        let key = keyboard.key(i);
+        *positions[key.finger()].press_mut(hold) = Some(key.pos());
+    }
+    let cost = letter_cost(info, &positions);
+    weights.base.dot(cost.base) + weights.stretch.dot(cost.stretch)
+}
+
+/// Left-hand keys with no hold behavior, thumbs first, as candidates for the
+/// key that holds the nav layer.
+pub fn free_layer_keys(info: &KeyboardLayout) -> Vec<usize> {
+    let keyboard = info.keyboard();
+    let mut keys = (0..info.layout().layer_size())
+        .filter(|&i| keyboard.key(i).finger().hand == Hand::Left)
+        .filter(|&i| info.layout().base_hold()[i].is_none())
+        .collect::<Vec<_>>();
+    keys.sort_by_key(|&i| keyboard.key(i).finger().finger != Finger::Thumb);
+    keys
+}
+
+/// Places the most used keys on the cheapest left-hand positions reachable
+/// while `layer_key` is held. Positions under the holding finger are skipped.
+pub fn generate(
+    info: &KeyboardLayout,
+    weights: &EvalWeights,
+    layer_key: usize,
+    usage: &[NavUsage],
+) -> NavLayer {
+    let keyboard = info.keyboard();
+    let holding = keyboard.key(layer_key).finger();
+    let mut positions = (0..info.layout().layer_size())
+        .filter(|&i| {
+            let finger = keyboard.key(i).finger();
+            finger.hand == Hand::Left && finger.finger != holding.finger
+        })
+        .map(|i| (i, position_cost(info, weights, layer_key, i)))
+        .collect::<Vec<_>>();
+    positions.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut usage = usage.to_vec();
+    usage.sort_by_key(|u| std::cmp::Reverse(u.freq));
+    let dropped = usage.split_off(positions.len().min(usage.len()));
+
+    let mut keys = vec![None; info.layout().layer_size()];
+    for (u, (i, _)) in usage.into_iter().zip(positions) {
+        keys[i] = Some(u.key);
+    }
+    NavLayer {
+        layer_key,
+        keys,
+        dropped,
+    }
+}
+
+/// The QMK keymap of the alpha layout with the nav layer added on top.
+pub fn attach(info: &KeyboardLayout, nav: &NavLayer) -> AnyResult<QmkKeymap> {
+    let mut layout = info.layout().clone();
+    let layer =
+        NonZeroU8::new(layout.layer_count()).ok_or_else(|| anyhow!("layout has no layers"))?;
+    layout.base_hold_mut()[nav.layer_key] = Some(Behavior::Layer(layer));
+
+    let keys = nav
+        .keys
+        .iter()
+        .map(|key| match key {
+            Some(key) => key.to_qmk(),
+            None => Ok(QmkKey::Direct(KeyCode::Null)),
+        })
+        .collect::<AnyResult<_>>()?;
+
+    let mut keymap = QmkKeymap::from_layout(layout)?;
+    keymap.push_layer(QmkLayer::new(keys));
+    Ok(keymap)
+}
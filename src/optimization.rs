@@ -1,107 +1,343 @@
-use std::num::NonZeroU8;
+use std::{
+    num::NonZeroU8,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
+use anyhow::{bail, Result as AnyResult};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    layout::{Behavior, Layout},
+    config::{MutationRates, OptimizeConfig},
+    evaluate::Evaluation,
+    layout::{Behavior, Edit, EditLog, KeyLoc, Layout},
+    symbol::Symbol,
     ALPHABET,
 };
 
-pub fn mutate(rng: &mut impl Rng, layout: &mut Layout) {
-    const HOLD_SWAP: f64 = 1.0;
-    const KEY_SWAP: f64 = 1.0;
-    const VERTICAL_SWAP: f64 = 0.8;
-    const NEW_KEY: f64 = 0.01;
-    const NEW_HOLD: f64 = 0.00;
-
+/// `thumbs` are the keys pressed by a thumb in ascending order, see
+/// [`Keyboard::thumb_keys`].
+///
+/// [`Keyboard::thumb_keys`]: crate::keyboard::Keyboard::thumb_keys
+pub fn mutate(
+    rng: &mut impl Rng,
+    layout: &mut Layout,
+    log: &mut EditLog,
+    rates: &MutationRates,
+    thumbs: &[usize],
+) {
     let layer_count = layout.layer_count();
     let size = layout.layer_size();
+    // a layout for fewer keys than the keyboard leaves its later thumbs out
+    let thumbs = &thumbs[..thumbs.partition_point(|&i| i < size)];
 
-    if rng.random_bool(NEW_HOLD) {
+    if rng.random_bool(rates.new_hold) {
         let i = rng.random_range(0..size);
         let layer = rng.random_range(0..layer_count);
         let behavior = match NonZeroU8::new(layer) {
             None => Behavior::Shift,
             Some(layer) => Behavior::Layer(layer),
         };
-        layout.base_hold_mut()[i] = Some(behavior);
+        log.apply(layout, Edit::SetHold(i, Some(behavior)));
     }
 
-    if rng.random_bool(NEW_KEY) {
+    if rng.random_bool(rates.new_key) {
         let layer = rng.random_range(0..layer_count);
         let i = rng.random_range(0..size);
         let ch = ALPHABET[rng.random_range(0..ALPHABET.len())];
-        *layout.layer_mut(layer).key_mut(i) = NonZeroU8::new(ch);
+        log.apply(
+            layout,
+            Edit::SetKey(KeyLoc::new(layer, i), Symbol::from_ascii(ch)),
+        );
     }
 
-    if rng.random_bool(HOLD_SWAP) {
+    if rng.random_bool(rates.hold_swap) {
         let [i1, i2] = [(); 2].map(|_| rng.random_range(0..size));
-        layout.base_hold_mut().swap(i1, i2);
+        log.apply(layout, Edit::SwapHolds(i1, i2));
     }
 
-    if rng.random_bool(KEY_SWAP) {
-        let target_layer = rng.random_range(0..layer_count);
-        let layer = layout.layer_mut(target_layer);
+    if rng.random_bool(rates.key_swap) {
+        let layer = rng.random_range(0..layer_count);
         let [i1, i2] = [(); 2].map(|_| rng.random_range(0..size));
-        layer.keys_mut().swap(i1, i2);
+        log.apply(
+            layout,
+            Edit::SwapKeys(KeyLoc::new(layer, i1), KeyLoc::new(layer, i2)),
+        );
     }
 
-    if rng.random_bool(VERTICAL_SWAP) {
+    if rng.random_bool(rates.vertical_swap) {
         let layer1 = rng.random_range(0..layer_count);
         let mut layer2 = rng.random_range(1..layer_count);
         if layer2 <= layer1 {
             layer2 -= 1
         }
         let index = rng.random_range(0..size);
+        log.apply(
+            layout,
+            Edit::SwapKeys(KeyLoc::new(layer1, index), KeyLoc::new(layer2, index)),
+        );
+    }
+
+    if !layout.combos().is_empty() && rng.random_bool(rates.combo_move) {
+        let i = rng.random_range(0..layout.combos().len());
+        let mut keys = layout.combos()[i].keys;
+        let moved = rng.random_range(0..2);
+        let key = rng.random_range(0..size);
+        if key != keys[1 - moved] {
+            keys[moved] = key;
+            log.apply(layout, Edit::MoveCombo(i, keys));
+        }
+    }
+
+    if thumbs.len() >= 2 && rng.random_bool(rates.thumb_pairing) {
+        let i1 = rng.random_range(0..thumbs.len());
+        let mut i2 = rng.random_range(1..thumbs.len());
+        if i2 <= i1 {
+            i2 -= 1;
+        }
+        log.apply(
+            layout,
+            Edit::SwapKeys(KeyLoc::new(0, thumbs[i1]), KeyLoc::new(0, thumbs[i2])),
+        );
+    }
+
+    if !thumbs.is_empty() && rng.random_bool(rates.thumb_whitespace) {
+        const WHITESPACE: [Symbol; 3] = [Symbol::SPACE, Symbol::ENTER, Symbol::TAB];
+        let ch = WHITESPACE[rng.random_range(0..WHITESPACE.len())];
+        let found = layout.find_all_key(|key| key == ch).collect::<Vec<_>>();
+        if !found.is_empty() {
+            let from = found[rng.random_range(0..found.len())];
+            let to = KeyLoc::new(from.layer(), thumbs[rng.random_range(0..thumbs.len())]);
+            log.apply(layout, Edit::SwapKeys(from, to));
+        }
+    }
+}
+
+/// State of a run that was stopped early.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub seed: u64,
+    /// iterations completed before stopping
+    pub iteration: u32,
+    pub score: f32,
+    /// best layout so far
+    pub layout: Layout,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// The generator used for a seeded run.
+pub fn seeded_rng(seed: u64) -> SmallRng {
+    SmallRng::seed_from_u64(seed)
+}
+
+/// How an [`anneal`] run went.
+#[derive(Debug, Clone, Default)]
+pub struct AnnealStats {
+    /// iterations completed, fewer than configured when stopped early
+    pub iterations: u32,
+    /// moves taken
+    pub accepted: u32,
+    /// mutations `eval` turned down and drew again
+    pub rejected: u32,
+    /// the current score every 1000 iterations, and at the iteration a
+    /// stopped run stopped at
+    pub trajectory: Vec<(u32, f32)>,
+}
+
+/// What [`anneal`] ends with.
+#[derive(Debug, Clone)]
+pub struct AnnealResult {
+    /// best layout seen
+    pub layout: Layout,
+    pub score: f32,
+    /// the evaluation `eval` returned with `score`
+    pub evaluation: Evaluation,
+    pub stats: AnnealStats,
+}
+
+/// A new best layout found by an [`Annealer`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub layout: Layout,
+    pub score: f32,
+    pub evaluation: Evaluation,
+}
+
+/// What one iteration of an [`Annealer`] ends with.
+#[derive(Debug, Clone)]
+pub struct AnnealStep {
+    /// iterations completed, this one included
+    pub iteration: u32,
+    /// score of the current layout
+    pub score: f32,
+    /// set when this iteration found a new best layout
+    pub improved: Option<Snapshot>,
+}
+
+/// Anneals a layout by the score `eval` returns with its evaluation, one
+/// iteration per call to `next`, keeping the best layout seen. Every random
+/// choice, in mutation and in `modifier`, is drawn from `rng`, so a run is
+/// repeated by passing the same [`seeded_rng`]. Stopping early is dropping
+/// out of the loop before the configured iterations are done;
+/// [`Annealer::finish`] then gives the best so far.
+pub struct Annealer<'a, R, F, M> {
+    config: &'a OptimizeConfig,
+    thumbs: &'a [usize],
+    eval: F,
+    modifier: M,
+    rng: R,
+    log: EditLog,
+    current: Layout,
+    current_score: f32,
+    best: Snapshot,
+    stats: AnnealStats,
+}
 
-        let [layer1, .., layer2] =
-            &mut layout.layers_mut()[layer1.min(layer2) as usize..=layer1.max(layer2) as usize]
-        else {
-            unreachable!()
+impl<'a, R, E, F, M> Annealer<'a, R, F, M>
+where
+    R: Rng,
+    F: Fn(u32, &Layout) -> Option<(f32, Evaluation, E)>,
+    M: Fn(&mut R, &mut Layout, E),
+{
+    /// `thumbs` are passed on to [`mutate`]. Fails if `eval` turns down the
+    /// starting layout.
+    pub fn new(
+        layout: Layout,
+        config: &'a OptimizeConfig,
+        thumbs: &'a [usize],
+        rng: R,
+        eval: F,
+        modifier: M,
+    ) -> AnyResult<Self> {
+        let Some((score, evaluation, _)) = eval(0, &layout) else {
+            bail!("the starting layout breaks the constraints of the run");
         };
-        std::mem::swap(layer1.key_mut(index), layer2.key_mut(index));
+        Ok(Self {
+            config,
+            thumbs,
+            eval,
+            modifier,
+            rng,
+            log: EditLog::default(),
+            current: layout.clone(),
+            current_score: score,
+            best: Snapshot {
+                layout,
+                score,
+                evaluation,
+            },
+            stats: AnnealStats::default(),
+        })
+    }
+
+    pub fn best(&self) -> &Snapshot {
+        &self.best
+    }
+
+    pub fn stats(&self) -> &AnnealStats {
+        &self.stats
+    }
+
+    /// The best layout seen, recording where the run stopped if it stopped
+    /// early.
+    pub fn finish(mut self) -> AnnealResult {
+        if self.stats.iterations < self.config.iterations {
+            self.stats
+                .trajectory
+                .push((self.stats.iterations, self.current_score));
+        }
+        AnnealResult {
+            layout: self.best.layout,
+            score: self.best.score,
+            evaluation: self.best.evaluation,
+            stats: self.stats,
+        }
     }
 }
 
-pub fn anneal<E>(
-    layout: Layout,
-    iters: u32,
-    profile: impl Fn(f32) -> f32,
-    eval: impl Fn(u32, &Layout) -> Option<(f32, E)>,
-    modifier: impl Fn(&mut SmallRng, &mut Layout, E),
-) -> (Layout, f32) {
-    let mut current = layout;
-    let (mut current_score, _) = eval(0, &current).unwrap();
-    let mut rng = SmallRng::from_os_rng();
-
-    for i in 0..iters {
-        let temperature = profile(i as f32 / iters as f32);
-
-        let mut new_layout = current.clone();
-        let (new_layout, extra, new_score) = loop {
-            mutate(&mut rng, &mut new_layout);
-            let new_score = eval(i, &new_layout);
-            if let Some((score, extra)) = new_score {
-                break (new_layout, extra, score);
+impl<R, E, F, M> Iterator for Annealer<'_, R, F, M>
+where
+    R: Rng,
+    F: Fn(u32, &Layout) -> Option<(f32, Evaluation, E)>,
+    M: Fn(&mut R, &mut Layout, E),
+{
+    type Item = AnnealStep;
+
+    fn next(&mut self) -> Option<AnnealStep> {
+        let i = self.stats.iterations;
+        let iters = self.config.iterations;
+        if i >= iters {
+            return None;
+        }
+        let Self {
+            rng, log, current, ..
+        } = self;
+        let temperature = self.config.temperature * (1.0 - i as f32 / iters as f32);
+
+        // the candidate is `current` mutated in place, rolled back unless accepted
+        let (new_eval, extra, new_score) = loop {
+            log.begin();
+            mutate(rng, current, log, &self.config.mutation, self.thumbs);
+            if let Some((score, evaluation, extra)) = (self.eval)(i, current) {
+                break (evaluation, extra, score);
             }
-            current.clone_into(&mut new_layout);
+            self.stats.rejected += 1;
+            log.rollback(current);
         };
-        if i % 1000 == 0 {
-            println!("({i},{current_score}),");
+        if i.is_multiple_of(1000) {
+            self.stats.trajectory.push((i, self.current_score));
         }
 
-        let accept_prob = if new_score < current_score {
+        let accept_prob = if new_score < self.current_score {
             1.0
         } else {
-            ((current_score - new_score) / temperature).exp()
+            ((self.current_score - new_score) / temperature).exp()
         };
 
+        let mut improved = None;
         if rng.random_bool(accept_prob.into()) {
-            current = new_layout;
-            modifier(&mut rng, &mut current, extra);
-            current_score = new_score;
+            self.stats.accepted += 1;
+            log.clear();
+            (self.modifier)(rng, current, extra);
+            self.current_score = new_score;
+            if new_score < self.best.score {
+                current.clone_into(&mut self.best.layout);
+                self.best.score = new_score;
+                self.best.evaluation = new_eval;
+                improved = Some(self.best.clone());
+            }
+        } else {
+            log.rollback(current);
         }
+        self.stats.iterations = i + 1;
+
+        Some(AnnealStep {
+            iteration: i + 1,
+            score: self.current_score,
+            improved,
+        })
     }
+}
 
-    (current, current_score)
+/// Runs an [`Annealer`] of `layout` until the configured iterations are done
+/// or `stop` is set.
+pub fn anneal<R: Rng, E>(
+    layout: Layout,
+    config: &OptimizeConfig,
+    thumbs: &[usize],
+    rng: R,
+    stop: &AtomicBool,
+    eval: impl Fn(u32, &Layout) -> Option<(f32, Evaluation, E)>,
+    modifier: impl Fn(&mut R, &mut Layout, E),
+) -> AnyResult<AnnealResult> {
+    let mut annealer = Annealer::new(layout, config, thumbs, rng, eval, modifier)?;
+    while !stop.load(Ordering::Relaxed) && annealer.next().is_some() {}
+    Ok(annealer.finish())
 }
@@ -1,19 +1,38 @@
-use colored::Colorize as _;
+use colored::{ColoredString, Colorize as _};
+use glam::Vec2;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{hash_map, HashMap},
-    fmt::{Display, Write as _}, num::NonZeroU8,
+    collections::hash_map,
+    fmt::{Display, Write as _},
+    sync::OnceLock,
 };
 
-use crate::layout::{Behavior, Layout};
+use array_map::Indexable as _;
+
+use crate::{
+    config::CorpusPart,
+    core::FingerTargets,
+    counter::CorpusStats,
+    evaluate::{EvalWeights, Evaluation, FingerEval},
+    keyboard::{HandFinger, Keyboard},
+    layout::{Behavior, Layout, Reachability},
+    report::{
+        CorpusDiff, CrossLayerBigram, LanguageShare, Offender, RankedLayout, ShiftChoice,
+        Stability, SymbolPath, ThumbChoice,
+    },
+    simulate::Simulation,
+    symbol::Symbol,
+};
 
 pub fn render_frequency_table<I, F, E, const NGRAM: usize>(
-    data: HashMap<[u8; NGRAM], E>,
+    data: FxHashMap<[Symbol; NGRAM], E>,
     top_n: usize,
     func: F,
 ) where
-    I: IntoIterator<Item = ([u8; NGRAM], E)>,
-    F: FnOnce(hash_map::IntoIter<[u8; NGRAM], E>) -> I,
-    E: Ord + Display + Copy
+    I: IntoIterator<Item = ([Symbol; NGRAM], E)>,
+    F: FnOnce(hash_map::IntoIter<[Symbol; NGRAM], E>) -> I,
+    E: Ord + Display + Copy,
 {
     let mut pairs = func(data.into_iter()).into_iter().collect::<Vec<_>>();
     let len = pairs.len();
@@ -23,7 +42,7 @@ pub fn render_frequency_table<I, F, E, const NGRAM: usize>(
         .skip(len.saturating_sub(top_n))
         .map(|(chars, _)| {
             let mut total_len = 0;
-            for c in chars.map(char::from) {
+            for c in chars.map(Symbol::char) {
                 if c.is_ascii_graphic() || c == ' ' {
                     total_len += 1;
                 } else {
@@ -38,14 +57,7 @@ pub fn render_frequency_table<I, F, E, const NGRAM: usize>(
 
     println!("top {top_n}");
     for (chars, occur) in pairs.into_iter().skip(len.saturating_sub(top_n)) {
-        let mut printed = String::new();
-        for c in chars.map(char::from) {
-            if c.is_ascii_graphic() || c == ' ' {
-                write!(printed, "{c}").unwrap();
-            } else {
-                write!(printed, "{c:?}").unwrap();
-            }
-        }
+        let printed = printable(&chars);
 
         let count = max_len - printed.len();
         print!("{printed}");
@@ -57,103 +69,752 @@ pub fn render_frequency_table<I, F, E, const NGRAM: usize>(
     }
 }
 
-pub fn print_ferris_layout(layout: &Layout) {
-    fn row1(key: Option<NonZeroU8>) {
-        let key = key.map_or(0, u8::from);
+fn printable(chars: &[Symbol]) -> String {
+    let mut printed = String::new();
+    for c in chars.iter().copied().map(Symbol::char) {
+        if c.is_ascii_graphic() || c == ' ' {
+            write!(printed, "{c}").unwrap();
+        } else {
+            write!(printed, "{c:?}").unwrap();
+        }
+    }
+    printed
+}
+
+/// Scores next to every metric as a percentage of the reference. Metrics that
+/// no layout produces are left out.
+pub fn print_ranking(ranked: &[RankedLayout]) {
+    let Some(names) = ranked.iter().find_map(|r| r.scaled).map(|e| {
+        e.fields()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>()
+    }) else {
+        println!("no valid layouts");
+        return;
+    };
+    let shown = (0..names.len())
+        .filter(|&i| {
+            ranked
+                .iter()
+                .filter_map(|r| r.scaled)
+                .any(|e| e.fields()[i].1.is_finite())
+        })
+        .collect::<Vec<_>>();
+    let name_width = ranked
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+
+    print!("{:>4} {:<name_width$} {:>12}", "rank", "layout", "score");
+    for &i in &shown {
+        print!(" {:>w$}", names[i], w = names[i].len().max(8));
+    }
+    println!();
+
+    for (rank, r) in ranked.iter().enumerate() {
+        print!("{:>4} {:<name_width$}", rank + 1, r.name);
+        let Some(scaled) = r.scaled else {
+            println!(" {:>12}", "invalid");
+            continue;
+        };
+        print!(" {:>12.1}", r.score);
+        let fields = scaled.fields();
+        for &i in &shown {
+            print!(" {:>w$.1}", fields[i].1, w = names[i].len().max(8));
+        }
+        println!();
+    }
+}
+
+pub fn print_cross_layer_bigrams(bigrams: &[CrossLayerBigram]) {
+    println!(
+        "{:<8} {:>10} {:>9} {:>12}",
+        "bigram", "freq", "crossing", "coupling"
+    );
+    for b in bigrams {
+        println!(
+            "{:<8} {:>10} {:>8.0}% {:>12.1}",
+            printable(&b.bigram),
+            b.freq,
+            b.crossing * 100.0,
+            b.coupling
+        );
+    }
+}
 
-        match key {
-            b'\n' => print!("{}│", "RET".green().bold()),
-            b'\t' => print!("{}│", "TAB".green().bold()),
-            b' ' => print!("{}│", "SPC".green().bold()),
-            0 => print!("   │"),
-            _ => print!(" {} │", char::from(key).to_string().green().bold()),
+/// The n-grams adding the most to one metric, with the fingers typing them.
+pub fn print_offenders(metric: &str, offenders: &[Offender]) {
+    println!(
+        "{:<8} {:>10} {:>12} {:>8}  fingers",
+        "ngram", "freq", metric, "share"
+    );
+    for o in offenders {
+        let fingers = o
+            .fingers
+            .iter()
+            .map(|f| format!("{:?} {:?}", f.hand, f.finger).to_lowercase())
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{:<8} {:>10} {:>12.1} {:>7.2}%  {fingers}",
+            printable(&o.ngram),
+            o.freq,
+            o.cost,
+            o.share
+        );
+    }
+}
+
+/// The vector metrics of each hand, raw and in percent of the same hand of
+/// the reference, so that one hand doing badly shows next to the other.
+pub fn print_hand_split(hands: &[Evaluation; 2], reference: &[Evaluation; 2]) {
+    const SPLIT: [&str; 3] = ["letter.base", "letter.stretch", "bigram.movement"];
+    println!(
+        "{:<18} {:>12} {:>12} {:>8} {:>8}",
+        "per hand", "left", "right", "left %", "right %"
+    );
+    let [left, right] = hands.each_ref().map(Evaluation::fields);
+    let [ref_left, ref_right] = reference.each_ref().map(Evaluation::fields);
+    for (i, (name, l)) in left.iter().enumerate() {
+        if !SPLIT.iter().any(|metric| name.starts_with(metric)) {
+            continue;
         }
+        let r = right[i].1;
+        println!(
+            "{name:<18} {l:>12.1} {r:>12.1} {:>8.1} {:>8.1}",
+            l / ref_left[i].1 * 100.0,
+            r / ref_right[i].1 * 100.0
+        );
     }
+}
 
-    fn row2(key: Option<Behavior>) {
-        match key {
-            Some(Behavior::Shift) => print!(" {} │", "S".blue().bold()),
-            Some(Behavior::Layer(layer)) => print!(" {} │", layer.to_string().blue()),
-            None => print!("   │"),
+/// The n-grams that moved most, as percentages of their table, and the
+/// characters only one corpus types.
+pub fn print_corpus_diff(diff: &CorpusDiff) {
+    for (name, shifts) in [
+        ("letter", &diff.letters),
+        ("bigram", &diff.bigrams),
+        ("trigram", &diff.trigrams),
+    ] {
+        if shifts.is_empty() {
+            continue;
+        }
+        println!("{name:<8} {:>9} {:>9} {:>9}", "old", "new", "delta");
+        for shift in shifts {
+            let delta = (shift.new - shift.old) * 100.0;
+            println!(
+                "{:<8} {:>8.3}% {:>8.3}% {:>+8.3}%",
+                printable(&shift.ngram),
+                shift.old * 100.0,
+                shift.new * 100.0,
+                delta
+            );
+        }
+        println!();
+    }
+    for (name, chars) in [("gained", &diff.gained), ("lost", &diff.lost)] {
+        if !chars.is_empty() {
+            let chars = chars.iter().map(|&ch| printable(&[ch])).collect::<Vec<_>>();
+            println!("{name}: {}", chars.join(" "));
         }
     }
+}
 
-    for (li, layer) in layout.layers().iter().enumerate() {
-        let keys = layer.keys();
+/// Totals of a corpus, the keys of the alphabet it never types, and its
+/// `top` most frequent rejected characters.
+pub fn print_corpus_stats(stats: &CorpusStats, top: usize) {
+    let typed = stats.typed.values().sum::<u64>();
+    let rejected = stats.rejected.values().sum::<u64>();
+    let total = (typed + rejected).max(1) as f64;
+    println!(
+        "{} files, {typed} characters typed, {rejected} rejected ({:.3}%)",
+        stats.files,
+        rejected as f64 / total * 100.0
+    );
 
-        print!("┌");
-        for _ in 1..10 {
-            print!("───┬");
+    let unseen = Symbol::alphabet()
+        .filter(|key| !stats.typed.contains_key(key))
+        .map(|key| printable(&[key]))
+        .collect::<Vec<_>>();
+    println!(
+        "{} of {} alphabet keys typed",
+        crate::ALPHABET.len() - unseen.len(),
+        crate::ALPHABET.len()
+    );
+    if !unseen.is_empty() {
+        println!("never typed: {}", unseen.join(" "));
+    }
+
+    let mut rejected = stats.rejected.iter().collect::<Vec<_>>();
+    rejected.sort_unstable_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    if !rejected.is_empty() {
+        println!("\n{:<10} {:>12} {:>9}", "rejected", "count", "share");
+        for (ch, &count) in rejected.into_iter().take(top) {
+            let name = format!("{ch:?} U+{:04X}", u32::from(*ch));
+            println!(
+                "{name:<10} {count:>12} {:>8.3}%",
+                count as f64 / total * 100.0
+            );
         }
-        println!("───┐");
-        for row in 0..3 {
-            print!("│");
-            for column in 0..10 {
-                let index = column * 3 + row;
-                let key = keys[index];
-                row1(key);
-            }
-            println!();
-
-            print!("│");
-            for column in 0..10 {
-                let index = column * 3 + row;
-                row2(
-                    (li == 0)
-                        .then_some(())
-                        .and_then(|_| layout.base_hold()[index]),
-                );
+    }
+
+    println!("\n{:<10} {:>12} {:>9}", "extension", "typed", "share");
+    let mut extensions = stats.extensions.iter().collect::<Vec<_>>();
+    extensions.sort_by(|a, b| b.1.cmp(a.1));
+    for (extension, &count) in extensions {
+        let extension = match extension.as_str() {
+            "" => "(none)",
+            extension => extension,
+        };
+        println!(
+            "{extension:<10} {count:>12} {:>8.2}%",
+            count as f64 / typed.max(1) as f64 * 100.0
+        );
+    }
+}
+
+/// The share of the presses on each finger next to its target, overloaded
+/// fingers in the worse color.
+pub fn print_finger_load(fingers: &FingerEval, targets: &FingerTargets) {
+    let total = fingers.total_presses();
+    println!("{:<16} {:>8} {:>8}", "finger", "share", "target");
+    for finger in HandFinger::iter() {
+        let name = format!("{:?} {:?}", finger.hand, finger.finger).to_lowercase();
+        let (share, target) = (
+            fingers.presses(finger) / total * 100.0,
+            targets.of(finger.finger),
+        );
+        let line = format!("{name:<16} {share:>7.2}% {target:>7.1}%");
+        match share > target {
+            true => println!("{}", Palette::current().worse(&line)),
+            false => println!("{line}"),
+        }
+    }
+}
+
+/// Each symbol with how it is typed now and otherwise, switches that lower
+/// the score highlighted.
+pub fn print_shift_choices(choices: &[ShiftChoice]) {
+    let path = |path: SymbolPath| match path {
+        SymbolPath::Shift(key) => format!("shift {key:?}"),
+        SymbolPath::Key(loc) => format!("layer {} key {}", loc.layer(), loc.index()),
+    };
+    println!(
+        "{:<6} {:>7}  {:<16} {:<16} {:>8}",
+        "symbol", "share", "now", "otherwise", "change"
+    );
+    for choice in choices {
+        let alternative = choice.alternative.map_or("-".to_string(), path);
+        let line = format!(
+            "{:<6} {:>6.2}%  {:<16} {:<16} {:>+7.2}%",
+            format!("{:?}", choice.ch),
+            choice.share,
+            path(choice.current),
+            alternative,
+            choice.change
+        );
+        match choice.change < 0.0 {
+            true => println!("{}", Palette::current().better(&line)),
+            false => println!("{line}"),
+        }
+    }
+}
+
+/// Where each whitespace symbol is, the hands typing next to it, and the
+/// change in score of moving it to every thumb key.
+pub fn print_thumb_choices(choices: &[ThumbChoice]) {
+    for choice in choices {
+        let keys = choice
+            .current
+            .iter()
+            .map(|loc| format!("layer {} key {}", loc.layer(), loc.index()))
+            .collect::<Vec<_>>();
+        println!(
+            "{:?} ({:.2}% of the letters) on {}",
+            choice.ch,
+            choice.share,
+            keys.join(", ")
+        );
+        println!(
+            "  left hand {:.1}% before it, {:.1}% after it",
+            choice.left_before, choice.left_after
+        );
+        for &(loc, change) in &choice.options {
+            let Some(change) = change else {
+                println!("  alone on key {:>2}        -", loc.index());
+                continue;
+            };
+            let line = format!("  alone on key {:>2} {change:>+7.2}%", loc.index());
+            match change < 0.0 {
+                true => println!("{}", Palette::current().better(&line)),
+                false => println!("{line}"),
             }
-            println!();
+        }
+    }
+}
+
+/// The score of every run and its agreement with the best one, then the
+/// spread and whether the runs agree.
+pub fn print_stability(seeds: &[u64], stability: &Stability) {
+    println!("{:>20} {:>12} {:>10}", "seed", "score", "like best");
+    for ((seed, score), overlap) in seeds
+        .iter()
+        .zip(&stability.scores)
+        .zip(&stability.overlap_with_best)
+    {
+        println!("{seed:>20} {score:>12.1} {overlap:>9.1}%");
+    }
+    let spread = stability.std_dev / stability.mean.abs() * 100.0;
+    println!(
+        "mean {:.1}, standard deviation {:.1} ({spread:.2}%), {:.1}% of the keys placed alike",
+        stability.mean, stability.std_dev, stability.overlap
+    );
+    match stability.is_robust() {
+        true => println!(
+            "{}",
+            Palette::current().better("the runs agree, the layout is robust")
+        ),
+        false => println!(
+            "{}",
+            Palette::current()
+                .worse("the runs disagree, run longer or keep the best of more starts")
+        ),
+    }
+}
+
+/// Files, bytes and the share of the bytes of every language of a corpus.
+pub fn print_language_mix(mix: &[LanguageShare]) {
+    let total = mix.iter().map(|share| share.bytes).sum::<u64>().max(1) as f64;
+    println!(
+        "{:<12} {:>7} {:>12} {:>8}  extensions",
+        "language", "files", "bytes", "share"
+    );
+    for share in mix {
+        println!(
+            "{:<12} {:>7} {:>12} {:>7.2}%  {}",
+            share.language.unwrap_or("(other)"),
+            share.files,
+            share.bytes,
+            share.bytes as f64 / total * 100.0,
+            share.extensions.join(" ")
+        );
+    }
+}
+
+/// `parts` as the `[[eval.corpora]]` tables of `keyboard.toml`.
+pub fn render_corpora_toml(parts: &[CorpusPart]) -> String {
+    let mut out = String::new();
+    for part in parts {
+        writeln!(out, "[[eval.corpora]]").unwrap();
+        writeln!(out, "path = {:?}", part.path.display().to_string()).unwrap();
+        writeln!(out, "weight = {}", part.weight).unwrap();
+        if let Some(mode) = part.mode {
+            let mode = serde_json::to_string(&mode).unwrap();
+            writeln!(out, "mode = {mode}").unwrap();
+        }
+        if let Some(extensions) = &part.extensions {
+            writeln!(out, "extensions = {extensions:?}").unwrap();
+        }
+    }
+    out
+}
+
+/// Colors of the printed layouts and reports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Palette {
+    #[default]
+    Default,
+    /// the Okabe-Ito colors, told apart with any kind of color blindness;
+    /// blue and orange instead of green and red
+    ColorBlind,
+}
+
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+/// Picks the palette of everything printed afterwards, once.
+pub fn set_palette(palette: Palette) {
+    let _ = PALETTE.set(palette);
+}
 
-            if row == 2 {
-                print!("└");
-            } else {
-                print!("├");
+impl Palette {
+    fn current() -> Self {
+        PALETTE.get().copied().unwrap_or_default()
+    }
+
+    fn key(self, text: &str) -> ColoredString {
+        match self {
+            Self::Default => text.green().bold(),
+            Self::ColorBlind => text.truecolor(86, 180, 233).bold(),
+        }
+    }
+
+    fn hold(self, text: &str) -> ColoredString {
+        match self {
+            Self::Default => text.blue(),
+            Self::ColorBlind => text.truecolor(230, 159, 0),
+        }
+    }
+
+    fn better(self, text: &str) -> ColoredString {
+        match self {
+            Self::Default => text.green(),
+            Self::ColorBlind => text.truecolor(0, 114, 178),
+        }
+    }
+
+    fn worse(self, text: &str) -> ColoredString {
+        match self {
+            Self::Default => text.red(),
+            Self::ColorBlind => text.truecolor(213, 94, 0),
+        }
+    }
+
+    /// Background shade for `heat` from 0 to 1; the color-blind one only
+    /// changes in brightness.
+    fn heat(self, text: &str, heat: f32) -> ColoredString {
+        match self {
+            Self::Default => text.on_truecolor((40.0 + heat * 180.0) as u8, 30, 30),
+            Self::ColorBlind => {
+                let shade = |max: f32| (20.0 + heat * (max - 20.0)) as u8;
+                text.on_truecolor(20, shade(114.0), shade(178.0))
             }
-            for x in 0..9 {
-                print!("───");
-                if (x <= 1 || x >= 7) && row == 2 {
-                    print!("┴")
-                } else {
-                    print!("┼");
+        }
+    }
+}
+
+/// The layer transition matrix as percentages, rows typed before columns,
+/// shaded by how much traffic each cell carries.
+pub fn print_layer_transitions(matrix: &[Vec<f32>]) {
+    let palette = Palette::current();
+    let max = matrix.iter().flatten().copied().fold(0.0, f32::max);
+    println!("layer transitions, % of bigrams from row to column");
+    print!("{:>6}", "");
+    for to in 0..matrix.len() {
+        print!(" {to:>7}");
+    }
+    println!();
+    for (from, row) in matrix.iter().enumerate() {
+        print!("{from:>6}");
+        for &share in row {
+            // square root so that the small cells off the diagonal still show
+            let heat = match max > 0.0 {
+                true => (share / max).sqrt(),
+                false => 0.0,
+            };
+            let cell = format!("{:>7.2}", share * 100.0);
+            print!(" {}", palette.heat(&cell, heat));
+        }
+        println!();
+    }
+}
+
+/// The keys activating each layer, then the layers stranded or looping.
+pub fn print_reachability(reachability: &Reachability) {
+    let mut layers = reachability
+        .activations
+        .iter()
+        .map(|&(_, layer)| layer)
+        .collect::<Vec<_>>();
+    layers.sort_unstable();
+    layers.dedup();
+    for layer in layers {
+        let keys = reachability
+            .activations
+            .iter()
+            .filter(|&&(_, to)| to == layer)
+            .map(|(loc, _)| match loc.layer() {
+                0 => format!("key {}", loc.index()),
+                from => format!("layer {from} key {}", loc.index()),
+            })
+            .collect::<Vec<_>>();
+        println!("layer {layer} activated by {}", keys.join(", "));
+    }
+    let palette = Palette::current();
+    for layer in &reachability.unreachable {
+        println!(
+            "{}",
+            palette.worse(&format!("layer {layer} is stranded, no key activates it"))
+        );
+    }
+    for cycle in &reachability.cycles {
+        let layers = cycle
+            .iter()
+            .map(u8::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let text = format!("layers {layers} activate one another in a loop");
+        println!("{}", palette.worse(&text));
+    }
+}
+
+/// `field` over the plane of `keyboard` in braille, two dots across and four
+/// down per character, each dot as square as a terminal cell allows. Denser
+/// dots are higher values, dithered between the lowest and the highest on a
+/// square root scale so that the low end still shows.
+pub fn print_braille_heatmap(keyboard: &Keyboard, field: impl Fn(Vec2) -> f32) {
+    // braille bit of the dot at [row][column]
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+    // ordered dithering thresholds, in eighths
+    const BAYER: [[u8; 2]; 4] = [[0, 4], [6, 2], [1, 5], [7, 3]];
+    // millimetres of margin around the outermost key centres
+    const MARGIN: f32 = 10.0;
+
+    let (min, max) = keyboard.keys().iter().fold(
+        (Vec2::splat(f32::INFINITY), Vec2::splat(f32::NEG_INFINITY)),
+        |(min, max), key| (min.min(key.pos()), max.max(key.pos())),
+    );
+    let (min, max) = (min - MARGIN, max + MARGIN);
+    let columns = terminal_size::terminal_size()
+        .map_or(80, |(width, _)| usize::from(width.0))
+        .min(120);
+    let dot = (max.x - min.x) / (columns * 2) as f32;
+    let rows = ((max.y - min.y) / dot / 4.0).ceil() as usize;
+
+    let values = (0..rows * 4)
+        .map(|y| {
+            (0..columns * 2)
+                .map(|x| field(min + Vec2::new(x as f32 + 0.5, y as f32 + 0.5) * dot))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let (low, high) = values
+        .iter()
+        .flatten()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(low, high), &v| {
+            (low.min(v), high.max(v))
+        });
+    let range = (high - low).max(f32::EPSILON);
+
+    for row in 0..rows {
+        let line = (0..columns)
+            .map(|column| {
+                let mut bits = 0;
+                for (dy, dots) in DOTS.iter().enumerate() {
+                    for (dx, &bit) in dots.iter().enumerate() {
+                        let value = ((values[row * 4 + dy][column * 2 + dx] - low) / range).sqrt();
+                        let threshold = (f32::from(BAYER[dy][dx]) + 0.5) / 8.0;
+                        if value > threshold {
+                            bits |= bit;
+                        }
+                    }
                 }
+                char::from_u32(0x2800 + bits).unwrap()
+            })
+            .collect::<String>();
+        println!("{}", line.trim_end_matches('\u{2800}'));
+    }
+    println!("{dot:.1} mm per dot, blank at {low:.3}, full at {high:.3}");
+}
+
+/// One layer of `layout` on the keys of `keyboard`, with the hold behaviors
+/// under the base layer.
+pub fn render_layer(layout: &Layout, keyboard: &Keyboard, li: usize) -> String {
+    let palette = Palette::current();
+    let key_label = |key: Option<Symbol>| match key {
+        Some(Symbol::ENTER) => palette.key("RET").to_string(),
+        Some(Symbol::TAB) => palette.key("TAB").to_string(),
+        Some(Symbol::SPACE) => palette.key("SPC").to_string(),
+        None => "   ".to_string(),
+        Some(key) => format!(" {} ", palette.key(&key.to_string())),
+    };
+    let hold_label = |hold: Option<Behavior>| {
+        let name = match hold {
+            Some(Behavior::Shift) => "S".to_string(),
+            Some(Behavior::Ctrl) => "C".to_string(),
+            Some(Behavior::Alt) => "A".to_string(),
+            Some(Behavior::Gui) => "G".to_string(),
+            Some(Behavior::Layer(layer)) => {
+                return format!(" {} ", palette.hold(&layer.to_string()));
             }
-            print!("───");
-            if row == 2 {
-                println!("┘");
-            } else {
-                println!("┤");
+            None => return "   ".to_string(),
+        };
+        format!(" {} ", palette.hold(&name).bold())
+    };
+
+    let grid = keyboard.grid(layout.layer_size());
+    let (columns, rows) = (grid.columns(), grid.rows());
+    let mut cells = vec![vec![None; columns]; rows];
+    for i in 0..layout.layer_size().min(keyboard.keys().len()) {
+        let (column, row) = grid.pos(i);
+        cells[row][column] = Some(i);
+    }
+    let cell = |x: usize, y: usize| cells.get(y).and_then(|row| row.get(x)).copied().flatten();
+    let filled = |x: usize, y: usize| cell(x, y).is_some();
+    // whether a border runs along the top of the cell at x, y and down its left
+    let across = |x: usize, y: usize| filled(x, y) || y > 0 && filled(x, y - 1);
+    let down = |x: usize, y: usize| filled(x, y) || x > 0 && filled(x - 1, y);
+    let corner = |x: usize, y: usize| {
+        let left = x > 0 && across(x - 1, y);
+        let up = y > 0 && down(x, y - 1);
+        match (left, across(x, y), up, down(x, y)) {
+            (true, true, true, true) => '┼',
+            (true, true, true, false) => '┴',
+            (true, true, false, true) => '┬',
+            (true, false, true, true) => '┤',
+            (false, true, true, true) => '├',
+            (false, true, false, true) => '┌',
+            (true, false, false, true) => '┐',
+            (false, true, true, false) => '└',
+            (true, false, true, false) => '┘',
+            (false, false, false, false) => ' ',
+            (_, _, false, false) => '─',
+            (false, false, _, _) => '│',
+        }
+    };
+
+    let mut out = String::new();
+    for y in 0..=rows {
+        let mut line = String::new();
+        for x in 0..=columns {
+            line.push(corner(x, y));
+            if x < columns {
+                line.push_str(if across(x, y) { "───" } else { "   " });
             }
         }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+        if y == rows {
+            break;
+        }
 
-        fn tab() {
-            for _ in 0..12 {
-                print!(" ")
+        for holds in [false, true] {
+            let mut line = String::new();
+            for x in 0..=columns {
+                line.push(if down(x, y) { '│' } else { ' ' });
+                match cell(x, y) {
+                    Some(i) if holds => {
+                        let hold = (li == 0).then(|| layout.base_hold()[i]).flatten();
+                        line.push_str(&hold_label(hold));
+                    }
+                    Some(i) => line.push_str(&key_label(layout.layer(li as u8)[i])),
+                    None if x < columns => line.push_str("   "),
+                    None => {}
+                }
             }
+            writeln!(out, "{}", line.trim_end()).unwrap();
         }
+    }
+    writeln!(out).unwrap();
+    out
+}
+
+pub fn print_layout(layout: &Layout, keyboard: &Keyboard) {
+    let layers = (0..layout.layers().len())
+        .map(|li| render_layer(layout, keyboard, li))
+        .collect::<Vec<_>>();
+    print_columns(&layers, usize::MAX);
+    let missing = layout.layer_size().saturating_sub(keyboard.keys().len());
+    if missing > 0 {
+        println!("{missing} keys of the layout are not on the keyboard");
+    }
+    for combo in layout.combos() {
+        println!(
+            "combo {} + {} = {:?}",
+            combo.keys[0], combo.keys[1], combo.output
+        );
+    }
+}
 
-        tab();
-        print!("│");
-        for i in 30..34 {
-            row1(keys[i]);
+/// Length of `s` on the terminal, skipping color escapes.
+fn visible_len(s: &str) -> usize {
+    let mut len = 0;
+    let mut escape = false;
+    for c in s.chars() {
+        match (escape, c) {
+            (false, '\x1b') => escape = true,
+            (false, _) => len += 1,
+            (true, 'm') => escape = false,
+            (true, _) => {}
         }
-        println!();
-        tab();
-        print!("│");
-        for i in 30..34 {
-            row2((li == 0).then_some(()).and_then(|_| layout.base_hold()[i]));
+    }
+    len
+}
+
+/// Prints `blocks` in rows of up to `max_columns`, as many as the terminal
+/// is wide enough for, with their lines aligned. Output that is not a
+/// terminal gets one block per row.
+pub fn print_columns(blocks: &[String], max_columns: usize) {
+    const GAP: usize = 4;
+    let width = blocks
+        .iter()
+        .flat_map(|block| block.lines())
+        .map(visible_len)
+        .max()
+        .unwrap_or(0);
+    let columns = terminal_size::terminal_size()
+        .map_or(1, |(terminal, _)| {
+            (usize::from(terminal.0) + GAP) / (width + GAP)
+        })
+        .clamp(1, max_columns);
+
+    for row in blocks.chunks(columns) {
+        let lines = row
+            .iter()
+            .map(|block| block.lines().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        for i in 0..lines.iter().map(Vec::len).max().unwrap_or(0) {
+            let mut line = String::new();
+            for block in &lines {
+                let part = block.get(i).copied().unwrap_or("");
+                line.push_str(part);
+                line.push_str(&" ".repeat(width - visible_len(part) + GAP));
+            }
+            println!("{}", line.trim_end());
         }
-        println!();
-        tab();
-        print!("└");
-        for _ in 0..3 {
-            print!("───");
-            print!("┴")
+    }
+}
+
+/// Every metric of two scaled evaluations and how `b` differs from `a`.
+/// Changes that lower the score are green (blue in the color-blind palette),
+/// so a rise in a metric with a negative weight, such as rolls, is too.
+pub fn print_eval_deltas(
+    a: &Evaluation,
+    b: &Evaluation,
+    scores: (f32, f32),
+    weights: &EvalWeights,
+) {
+    let mut rows = vec![("score".to_string(), scores.0, scores.1, 1.0)];
+    rows.extend(
+        a.fields()
+            .into_iter()
+            .zip(b.fields())
+            .zip(weights.as_evaluation().fields())
+            .map(|(((name, a), (_, b)), (_, weight))| (name, a, b, weight)),
+    );
+
+    let palette = Palette::current();
+    println!("{:<22} {:>12} {:>12} {:>12}", "metric", "a", "b", "delta");
+    for (name, a, b, weight) in rows {
+        if !a.is_finite() && !b.is_finite() {
+            continue;
         }
-        print!("───");
-        println!("┘");
+        let delta = b - a;
+        let text = format!("{delta:>+12.2}");
+        let better = match weight < 0.0 {
+            true => -delta,
+            false => delta,
+        };
+        let text = match better {
+            d if d < 0.0 => palette.better(&text),
+            d if d > 0.0 => palette.worse(&text),
+            _ => text.normal(),
+        };
+        println!("{name:<22} {a:>12.2} {b:>12.2} {text}");
+    }
+}
 
-        println!()
+pub fn print_simulation(sim: &Simulation) {
+    println!("typed {} characters, skipped {}", sim.typed, sim.skipped);
+    println!("{:<16} {:>12}", "finger", "travel");
+    for (finger, travel) in sim.travel.iter() {
+        let name = format!("{:?} {:?}", finger.hand, finger.finger).to_lowercase();
+        println!("{name:<16} {travel:>12.1}");
     }
+    println!("{:<16} {:>12.1}", "total", sim.total_travel());
+    println!("{:<16} {:>12}", "sfbs", sim.sfbs);
+    println!("{:<16} {:>12}", "sfss", sim.sfss);
+    println!("{:<16} {:>12}", "layer switches", sim.layer_switches);
 }
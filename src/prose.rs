@@ -0,0 +1,94 @@
+/// The keys typed to write `text`: whitespace runs become one space or, across
+/// a blank line, one paragraph break. With `markdown`, code blocks and markup
+/// are dropped first, keeping link and emphasis text.
+pub fn normalize(text: &str, markdown: bool) -> String {
+    let text = match markdown {
+        true => strip_markdown(text),
+        false => text.to_string(),
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut newlines = None;
+    for ch in text.chars() {
+        if ch.is_whitespace() {
+            let seen = newlines.get_or_insert(0);
+            *seen += u32::from(ch == '\n');
+            continue;
+        }
+        match newlines.take() {
+            Some(_) if out.is_empty() => {}
+            Some(n) if n >= 2 => out.push('\n'),
+            Some(_) => out.push(' '),
+            None => {}
+        }
+        out.push(ch);
+    }
+    out
+}
+
+fn strip_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut fenced = false;
+    for line in text.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fenced = !fenced;
+            continue;
+        }
+        let rule = trimmed.len() >= 3
+            && trimmed
+                .chars()
+                .all(|ch| matches!(ch, '-' | '*' | '_' | ' '))
+            && trimmed.chars().any(|ch| ch != ' ');
+        if fenced || rule || line.starts_with("    ") || line.starts_with('\t') {
+            // keep paragraphs apart where a block was dropped
+            out.push('\n');
+            continue;
+        }
+
+        let mut rest = trimmed;
+        loop {
+            let before = rest;
+            rest = rest.trim_start_matches('>').trim_start();
+            rest = rest.trim_start_matches('#').trim_start();
+            for marker in ["- ", "* ", "+ "] {
+                rest = rest.strip_prefix(marker).unwrap_or(rest);
+            }
+            let digits = rest.len()
+                - rest
+                    .trim_start_matches(|ch: char| ch.is_ascii_digit())
+                    .len();
+            if digits > 0 {
+                rest = rest[digits..].strip_prefix(". ").unwrap_or(rest);
+            }
+            if rest == before {
+                break;
+            }
+        }
+
+        strip_inline(rest, &mut out);
+        out.push('\n');
+    }
+    out
+}
+
+/// Drops emphasis and code markers, keeps the text of links and images.
+fn strip_inline(line: &str, out: &mut String) {
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '*' | '_' | '`' => {}
+            '!' if chars.peek().is_some_and(|&(_, next)| next == '[') => {}
+            '[' => {}
+            ']' if line[i..].starts_with("](") => {
+                // skip the url
+                for (_, ch) in chars.by_ref() {
+                    if ch == ')' {
+                        break;
+                    }
+                }
+            }
+            ch => out.push(ch),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::{collections::HashMap, sync::atomic::AtomicBool};
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+use rustc_hash::FxHashMap;
+
+use crate::{
+    config::{EvalConfig, OptimizeConfig},
+    counter::{self, CountOutcome, NgramDepth, TextOptions},
+    evaluate::{self, EvalWeights, Evaluation, GenerateError, KeyboardLayout},
+    ferris,
+    keyboard::Keyboard,
+    layout::Layout,
+    optimization,
+    symbol::{self, Symbol},
+};
+
+fn value_error(err: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn untypable(err: GenerateError) -> PyErr {
+    value_error(format!("layout {err}"))
+}
+
+#[pyclass(name = "Layout", module = "keyboard")]
+#[derive(Clone)]
+pub struct PyLayout(Layout);
+
+#[pymethods]
+impl PyLayout {
+    #[staticmethod]
+    fn load(path: &str) -> PyResult<Self> {
+        Layout::load(path).map(Self).map_err(value_error)
+    }
+
+    /// A built-in Ferris Sweep layout such as `qwerty`.
+    #[staticmethod]
+    fn builtin(name: &str) -> PyResult<Self> {
+        ferris::by_name(name)
+            .map(Self)
+            .ok_or_else(|| value_error(format!("no built-in layout {name:?}")))
+    }
+
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        serde_json::from_str(json).map(Self).map_err(value_error)
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.0).map_err(value_error)
+    }
+
+    fn save(&self, path: &str) -> PyResult<()> {
+        self.0.save(path).map_err(value_error)
+    }
+
+    #[getter]
+    fn layer_count(&self) -> u8 {
+        self.0.layer_count()
+    }
+
+    /// The tapped characters of one layer, `None` where it is transparent.
+    fn layer(&self, layer: u8) -> Vec<Option<char>> {
+        self.0
+            .layer(layer)
+            .iter()
+            .map(|k| k.map(Symbol::char))
+            .collect()
+    }
+}
+
+#[pyclass(name = "Keyboard", module = "keyboard")]
+pub struct PyKeyboard(Keyboard);
+
+#[pymethods]
+impl PyKeyboard {
+    /// A built-in geometry (`ferris_sweep`, `laptop`), a bundled board such as
+    /// `corne`, or a geometry file.
+    #[staticmethod]
+    fn by_name(name: &str) -> PyResult<Self> {
+        Keyboard::by_name(name).map(Self).map_err(value_error)
+    }
+
+    #[staticmethod]
+    fn ferris_sweep() -> Self {
+        Self(Keyboard::ferris_sweep())
+    }
+}
+
+#[pyclass(name = "CountOutcome", module = "keyboard")]
+pub struct PyCountOutcome(CountOutcome);
+
+fn ngrams<const N: usize>(counts: &FxHashMap<[Symbol; N], u32>) -> HashMap<String, u32> {
+    counts.iter().map(|(k, &v)| (symbol::text(k), v)).collect()
+}
+
+#[pymethods]
+impl PyCountOutcome {
+    /// Counts every file under `path` that the default config counts,
+    /// through the same cache as the CLI.
+    #[staticmethod]
+    fn count(path: &str) -> PyResult<Self> {
+        let filter = EvalConfig::default().corpus_filter().map_err(value_error)?;
+        let text = TextOptions::default();
+        Ok(Self(
+            counter::count(path, NgramDepth::default(), &filter, &text).0,
+        ))
+    }
+
+    /// Mixes `[(count, weight), ...]`, each scaled to its total first.
+    #[staticmethod]
+    fn mix(parts: Vec<(PyRef<'_, PyCountOutcome>, f32)>) -> Self {
+        let parts = parts
+            .iter()
+            .map(|(count, weight)| (&count.0, *weight))
+            .collect::<Vec<_>>();
+        Self(CountOutcome::mix(&parts))
+    }
+
+    #[getter]
+    fn letters(&self) -> HashMap<String, u32> {
+        ngrams(&self.0.letter)
+    }
+
+    #[getter]
+    fn bigrams(&self) -> HashMap<String, u32> {
+        ngrams(&self.0.bigrams)
+    }
+
+    #[getter]
+    fn trigrams(&self) -> HashMap<String, u32> {
+        ngrams(&self.0.trigrams)
+    }
+
+    #[getter]
+    fn skipgrams(&self) -> HashMap<String, u32> {
+        ngrams(&self.0.skipgrams)
+    }
+
+    #[getter]
+    fn quadgrams(&self) -> HashMap<String, u32> {
+        ngrams(&self.0.quadgrams)
+    }
+}
+
+fn raw_evaluation(
+    layout: &Layout,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+) -> PyResult<Evaluation> {
+    let info = KeyboardLayout::generate(layout, keyboard).map_err(untypable)?;
+    Ok(evaluate::evaluate(&info, count))
+}
+
+/// Every raw metric of `layout`, keyed like `bigram.sfb`.
+#[pyfunction(name = "evaluate")]
+fn py_evaluate(
+    layout: &PyLayout,
+    keyboard: &PyKeyboard,
+    count: &PyCountOutcome,
+) -> PyResult<HashMap<String, f32>> {
+    let eval = raw_evaluation(&layout.0, &keyboard.0, &count.0)?;
+    Ok(eval.fields().into_iter().collect())
+}
+
+/// The final layout, its score, and the trajectory of an anneal.
+type AnnealResult = (PyLayout, f32, Vec<(u32, f32)>);
+
+/// Anneals `start` with the default weights, normalized against `reference`
+/// like the CLI.
+#[pyfunction]
+#[pyo3(signature = (start, keyboard, count, iterations = 100_000, temperature = 30.0, seed = 0, reference = None))]
+#[allow(clippy::too_many_arguments)]
+fn anneal(
+    py: Python<'_>,
+    start: &PyLayout,
+    keyboard: &PyKeyboard,
+    count: &PyCountOutcome,
+    iterations: u32,
+    temperature: f32,
+    seed: u64,
+    reference: Option<&PyLayout>,
+) -> PyResult<AnnealResult> {
+    let (keyboard, count) = (&keyboard.0, &count.0);
+    let reference = match reference {
+        Some(layout) => layout.0.clone(),
+        None => ferris::qwerty(),
+    };
+    let reference = raw_evaluation(&reference, keyboard, count)?;
+    let weights = EvalWeights::default();
+    let start_score =
+        weights.score(&(raw_evaluation(&start.0, keyboard, count)? / reference * 100.0));
+    let config = OptimizeConfig {
+        iterations,
+        temperature,
+        ..OptimizeConfig::default()
+    };
+
+    let start = start.0.clone();
+    let result = py
+        .allow_threads(|| {
+            optimization::anneal(
+                start,
+                &config,
+                &keyboard.thumb_keys(),
+                optimization::seeded_rng(seed),
+                &AtomicBool::new(false),
+                |_, layout| {
+                    let info = KeyboardLayout::generate(layout, keyboard).ok()?;
+                    let raw = evaluate::evaluate(&info, count);
+                    Some((
+                        weights.score(&(raw / reference * 100.0)) * 1_000_000.0 / start_score.abs(),
+                        raw,
+                        (),
+                    ))
+                },
+                |_, _, ()| {},
+            )
+        })
+        .map_err(value_error)?;
+    Ok((
+        PyLayout(result.layout),
+        result.score,
+        result.stats.trajectory,
+    ))
+}
+
+#[pymodule]
+fn keyboard(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyLayout>()?;
+    m.add_class::<PyKeyboard>()?;
+    m.add_class::<PyCountOutcome>()?;
+    m.add_function(wrap_pyfunction!(py_evaluate, m)?)?;
+    m.add_function(wrap_pyfunction!(anneal, m)?)?;
+    Ok(())
+}
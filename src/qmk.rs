@@ -1,13 +1,18 @@
-use anyhow::Result as AnyResult;
+use anyhow::{anyhow, Result as AnyResult};
 use std::{fmt::Display, num::NonZeroU8};
 
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde::Serialize;
 
-use crate::layout::{Behavior, Layout};
+use crate::{
+    layout::{Behavior, Layout},
+    symbol::Symbol,
+};
 
 impl QmkKeymap {
+    /// Fails on layers no key activates, which the keymap would strand.
     pub fn from_layout(value: Layout) -> AnyResult<Self> {
+        value.reachability().check()?;
         let mut layers = Vec::with_capacity(usize::from(value.layer_count()));
 
         let (base_hold, layout_layers) = value.into_parts();
@@ -19,10 +24,13 @@ impl QmkKeymap {
                 .into_iter()
                 .zip(base_hold)
                 .map(|(key, hold)| {
-                    let code = KeyCode::try_from_primitive(key.map_or(0, u8::from))?;
-                    Ok::<_, <KeyCode as TryFromPrimitive>::Error>(match hold {
+                    let code = key_code(key)?;
+                    Ok::<_, anyhow::Error>(match hold {
                         None => QmkKey::Direct(code),
                         Some(Behavior::Shift) => QmkKey::ModTapShift(code),
+                        Some(Behavior::Ctrl) => QmkKey::ModTapCtrl(code),
+                        Some(Behavior::Alt) => QmkKey::ModTapAlt(code),
+                        Some(Behavior::Gui) => QmkKey::ModTapGui(code),
                         Some(Behavior::Layer(layer)) => QmkKey::ModTapLayer(code, layer),
                     })
                 })
@@ -35,7 +43,7 @@ impl QmkKeymap {
             let keys = layer
                 .into_keys()
                 .into_iter()
-                .map(|key| KeyCode::try_from_primitive(key.map_or(0, u8::from)).map(QmkKey::Direct))
+                .map(|key| key_code(key).map(QmkKey::Direct))
                 .collect::<Result<Vec<_>, _>>()?;
 
             layers.push(QmkLayer { keys });
@@ -54,6 +62,18 @@ impl QmkKeymap {
     }
 }
 
+/// The keycode typing `key`, [`KeyCode::Null`] for none.
+fn key_code(key: Option<Symbol>) -> AnyResult<KeyCode> {
+    let byte = match key {
+        None => 0,
+        Some(key) => key
+            .ascii()
+            .ok_or_else(|| anyhow!("no keycode for {key:?}"))?,
+    };
+    Ok(KeyCode::try_from_primitive(byte)?)
+}
+
+/// A keymap in the json format accepted by `qmk json2c`.
 #[derive(Serialize, Clone)]
 pub struct QmkKeymap {
     version: u32,
@@ -71,12 +91,37 @@ pub struct QmkLayer {
     keys: Vec<QmkKey>,
 }
 
+impl QmkLayer {
+    pub fn new(keys: Vec<QmkKey>) -> Self {
+        Self { keys }
+    }
+}
+
+impl QmkKeymap {
+    pub fn push_layer(&mut self, layer: QmkLayer) {
+        self.layers.push(layer);
+    }
+}
+
 #[derive(Serialize, Clone, Copy)]
 #[serde(into = "String")]
 pub enum QmkKey {
     Direct(KeyCode),
     ModTapShift(KeyCode),
+    ModTapCtrl(KeyCode),
+    ModTapAlt(KeyCode),
+    ModTapGui(KeyCode),
     ModTapLayer(KeyCode, NonZeroU8),
+    /// any keycode by name, with modifiers applied
+    Shortcut(QmkMods, &'static str),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QmkMods {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub gui: bool,
 }
 
 impl From<QmkKey> for String {
@@ -90,7 +135,26 @@ impl Display for QmkKey {
         match self {
             QmkKey::Direct(k) => write!(f, "{}", k.as_str()),
             QmkKey::ModTapShift(k) => write!(f, "LSFT_T({})", k.as_str()),
+            QmkKey::ModTapCtrl(k) => write!(f, "LCTL_T({})", k.as_str()),
+            QmkKey::ModTapAlt(k) => write!(f, "LALT_T({})", k.as_str()),
+            QmkKey::ModTapGui(k) => write!(f, "LGUI_T({})", k.as_str()),
             QmkKey::ModTapLayer(k, l) => write!(f, "LT({},{})", l.get(), k.as_str()),
+            QmkKey::Shortcut(mods, name) => {
+                let wrappers = [
+                    (mods.ctrl, "C("),
+                    (mods.shift, "S("),
+                    (mods.alt, "A("),
+                    (mods.gui, "G("),
+                ];
+                let mut closing = 0;
+                for (held, wrapper) in wrappers {
+                    if held {
+                        write!(f, "{wrapper}")?;
+                        closing += 1;
+                    }
+                }
+                write!(f, "{name}{}", ")".repeat(closing))
+            }
         }
     }
 }
@@ -204,3 +268,22 @@ key_code! {
         Question = b'?', "KC_QUES"
     }
 }
+
+key_code! {
+    /// Keys with no character, only reachable from QMK.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SpecialKey {
+        Left = 0, "KC_LEFT"
+        Right = 1, "KC_RGHT"
+        Up = 2, "KC_UP"
+        Down = 3, "KC_DOWN"
+        Home = 4, "KC_HOME"
+        End = 5, "KC_END"
+        PageUp = 6, "KC_PGUP"
+        PageDown = 7, "KC_PGDN"
+        Backspace = 8, "KC_BSPC"
+        Delete = 9, "KC_DEL"
+        Escape = 10, "KC_ESC"
+        Insert = 11, "KC_INS"
+    }
+}
@@ -0,0 +1,932 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result as AnyResult};
+use array_map::ArrayMap;
+use glam::Vec2;
+use rustc_hash::FxHashMap;
+
+use crate::{
+    config::CorpusPart,
+    core::{self, BigramEval, TrigramEval},
+    counter::{Bigrams, CorpusMode, CountOutcome, Letters},
+    evaluate::{
+        self, one_bigram, one_bigram_any, one_trigram, EvalWeights, Evaluation, KeyboardLayout,
+    },
+    ferris,
+    keyboard::{Hand, HandFinger, Keyboard},
+    layout::{Behavior, KeyLoc, Layout},
+    shift::ShiftTable,
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct CrossLayerBigram {
+    pub bigram: [Symbol; 2],
+    pub freq: u32,
+    /// share of the ways to type this bigram that switch layers
+    pub crossing: f32,
+    pub coupling: f32,
+}
+
+/// Bigrams whose characters live on different layers, most costly first.
+pub fn cross_layer_bigrams(
+    info: &KeyboardLayout,
+    bigrams: &Bigrams,
+    top_n: usize,
+) -> Vec<CrossLayerBigram> {
+    let mut result = bigrams
+        .iter()
+        .filter_map(|(&bigram, &freq)| {
+            let (crossed, total) = one_bigram_any(
+                info,
+                (0.0, 0.0),
+                |_, [c1, c2]| (f32::from(u8::from(c1.tap_layer() != c2.tap_layer())), 1.0),
+                |x, y| (x.0 + y.0, x.1 + y.1),
+                bigram,
+            );
+            let crossing = crossed / total;
+            (crossing > 0.0).then(|| {
+                let (eval, count) = one_bigram(info, bigram);
+                CrossLayerBigram {
+                    bigram,
+                    freq,
+                    crossing,
+                    coupling: eval.coupling / count * freq as f32,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    result.sort_unstable_by(|a, b| b.coupling.total_cmp(&a.coupling));
+    result.truncate(top_n);
+    result
+}
+
+#[derive(Debug, Clone)]
+pub struct Offender {
+    pub ngram: Vec<Symbol>,
+    pub freq: u32,
+    /// the metric of the n-gram averaged over the ways of typing it, times `freq`
+    pub cost: f32,
+    /// percent of the metric over the whole corpus
+    pub share: f32,
+    /// the finger tapping each symbol, the first way it is typed
+    pub fingers: Vec<HandFinger>,
+}
+
+/// The `top_n` n-grams adding the most to `metric`, a bigram or trigram
+/// metric named like in [`Evaluation::fields`] such as `bigram.sfb`.
+/// `bigram.sfs` ranks skipgrams by their sfb, as the evaluation does.
+pub fn top_offenders(
+    info: &KeyboardLayout,
+    count: &CountOutcome,
+    metric: &str,
+    top_n: usize,
+) -> AnyResult<Vec<Offender>> {
+    let position =
+        |fields: &[(String, f32)], metric: &str| fields.iter().position(|(name, _)| name == metric);
+    let bigram_fields = BigramEval::ZERO.fields("bigram");
+    let trigram_fields = TrigramEval::ZERO.fields("trigram");
+    let mut result = if let Some(i) = position(&bigram_fields, metric) {
+        let (ngrams, i) = match metric == "bigram.sfs" {
+            true => (
+                &count.skipgrams,
+                position(&bigram_fields, "bigram.sfb").unwrap(),
+            ),
+            false => (&count.bigrams, i),
+        };
+        offenders(info, ngrams, |b| {
+            let (eval, ways) = one_bigram(info, b);
+            (eval.fields("bigram")[i].1, ways)
+        })
+    } else if let Some(i) = position(&trigram_fields, metric) {
+        offenders(info, &count.trigrams, |t| {
+            let (eval, ways) = one_trigram(info, t);
+            (eval.fields("trigram")[i].1, ways)
+        })
+    } else {
+        bail!("{metric} is not a bigram or trigram metric");
+    };
+
+    let total = result.iter().map(|o| o.cost).sum::<f32>();
+    for offender in &mut result {
+        offender.share = offender.cost / total * 100.0;
+    }
+    result.sort_unstable_by(|a, b| b.cost.total_cmp(&a.cost));
+    result.truncate(top_n);
+    Ok(result)
+}
+
+/// Every n-gram of `ngrams` that the layout types with a positive `cost`,
+/// which gives the summed metric and the number of ways it is typed.
+fn offenders<const N: usize>(
+    info: &KeyboardLayout,
+    ngrams: &FxHashMap<[Symbol; N], u32>,
+    cost: impl Fn([Symbol; N]) -> (f32, f32),
+) -> Vec<Offender> {
+    ngrams
+        .iter()
+        .filter(|(ngram, _)| ngram.iter().all(|&k| info.try_key(k).is_some()))
+        .filter_map(|(&ngram, &freq)| {
+            let (sum, ways) = cost(ngram);
+            let cost = sum / ways * freq as f32;
+            (cost > 0.0).then(|| Offender {
+                ngram: ngram.to_vec(),
+                freq,
+                cost,
+                share: 0.0,
+                fingers: ngram
+                    .iter()
+                    .filter_map(|&k| info.key(k).first())
+                    .map(|c| info.keyboard().key(c.key()).finger())
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Share of bigrams that go from a tap on one layer to a tap on another,
+/// `[from][to]`, with each bigram split evenly across the ways of typing it.
+pub fn layer_transitions(info: &KeyboardLayout, bigrams: &Bigrams) -> Vec<Vec<f32>> {
+    let layers = usize::from(info.layout().layer_count());
+    let mut matrix = vec![vec![0.0; layers]; layers];
+    let mut total = 0.0;
+    for (&[a, b], &freq) in bigrams {
+        let (Some(from), Some(to)) = (info.try_key(a), info.try_key(b)) else {
+            continue;
+        };
+        let weight = freq as f32 / (from.len() * to.len()) as f32;
+        for c1 in from {
+            for c2 in to {
+                matrix[usize::from(c1.tap_layer())][usize::from(c2.tap_layer())] += weight;
+            }
+        }
+        total += freq as f32;
+    }
+    if total > 0.0 {
+        matrix
+            .iter_mut()
+            .flatten()
+            .for_each(|share| *share /= total);
+    }
+    matrix
+}
+
+/// Weighted `letter.base` cost of tapping at `point` with the finger of the
+/// nearest key, which shows the finger axes and the stagger of `keyboard`.
+pub fn tap_cost_at(keyboard: &Keyboard, weights: &EvalWeights, point: Vec2) -> f32 {
+    let finger = keyboard.key(keyboard.nearest_key(point)).finger();
+    let mut fingers = ArrayMap::new([core::FingerState::NONE; 10]);
+    fingers[finger].tap = Some(point);
+    let cost = core::letter_cost(keyboard.hand_model(), &keyboard.base_positions(), &fingers);
+    weights.base.dot(cost.base)
+}
+
+/// Share of the letters that press each key, held keys included, with each
+/// letter split evenly across the ways of typing it.
+pub fn key_usage(info: &KeyboardLayout, letters: &Letters) -> Vec<f32> {
+    let mut usage = vec![0.0; info.keyboard().keys().len()];
+    let mut total = 0.0;
+    for (&[letter], &freq) in letters {
+        let Some(combos) = info.try_key(letter) else {
+            continue;
+        };
+        let weight = freq as f32 / combos.len() as f32;
+        for combo in combos {
+            let pressed = [
+                Some(combo.key()),
+                combo.chord(),
+                combo.shift(),
+                combo.layer(),
+            ];
+            for key in pressed.into_iter().flatten() {
+                usage[key] += weight;
+            }
+        }
+        total += freq as f32;
+    }
+    if total > 0.0 {
+        usage.iter_mut().for_each(|share| *share /= total);
+    }
+    usage
+}
+
+/// `usage` of every key spread around its centre, so that it can be drawn
+/// as a field over the keyboard.
+pub fn usage_at(keyboard: &Keyboard, usage: &[f32], point: Vec2) -> f32 {
+    // millimetres, about a third of a key
+    const SPREAD: f32 = 6.0;
+    keyboard
+        .keys()
+        .iter()
+        .zip(usage)
+        .map(|(key, &share)| {
+            share * (-key.pos().distance_squared(point) / (2.0 * SPREAD * SPREAD)).exp()
+        })
+        .sum()
+}
+
+/// Languages told apart by extension, with the mode their files are counted in.
+const LANGUAGES: &[(&str, CorpusMode, &[&str])] = &[
+    ("rust", CorpusMode::Code, &["rs"]),
+    ("python", CorpusMode::Code, &["py", "pyi"]),
+    ("c/c++", CorpusMode::Code, &["c", "h", "cc", "cpp", "hpp"]),
+    (
+        "javascript",
+        CorpusMode::Code,
+        &["js", "jsx", "ts", "tsx", "mjs"],
+    ),
+    ("go", CorpusMode::Code, &["go"]),
+    ("java", CorpusMode::Code, &["java", "kt"]),
+    (
+        "shaders",
+        CorpusMode::Code,
+        &["wgsl", "glsl", "vert", "frag", "comp"],
+    ),
+    ("shell", CorpusMode::Code, &["sh", "bash", "zsh", "fish"]),
+    ("lua", CorpusMode::Code, &["lua"]),
+    ("nix", CorpusMode::Code, &["nix"]),
+    ("html/css", CorpusMode::Code, &["html", "css", "scss"]),
+    ("yaml", CorpusMode::Code, &["yml", "yaml"]),
+    ("toml", CorpusMode::Code, &["toml"]),
+    ("json", CorpusMode::Code, &["json"]),
+    ("markdown", CorpusMode::Markdown, &["md", "markdown"]),
+    ("text", CorpusMode::Prose, &["txt", "rst", "org"]),
+];
+
+#[derive(Debug, Clone)]
+pub struct LanguageShare {
+    /// `None` for the files of no known language
+    pub language: Option<&'static str>,
+    pub mode: CorpusMode,
+    /// the extensions of this language the corpus has
+    pub extensions: Vec<String>,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Files and bytes of each language among `files`, largest first, with the
+/// files of no known language last.
+pub fn language_mix(files: &[PathBuf]) -> Vec<LanguageShare> {
+    let mut mix = LANGUAGES
+        .iter()
+        .map(|&(language, mode, _)| LanguageShare {
+            language: Some(language),
+            mode,
+            extensions: vec![],
+            files: 0,
+            bytes: 0,
+        })
+        .collect::<Vec<_>>();
+    mix.push(LanguageShare {
+        language: None,
+        mode: CorpusMode::Code,
+        extensions: vec![],
+        files: 0,
+        bytes: 0,
+    });
+
+    for file in files {
+        let extension = file
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let i = LANGUAGES
+            .iter()
+            .position(|(_, _, extensions)| extensions.contains(&extension))
+            .unwrap_or(LANGUAGES.len());
+        let share = &mut mix[i];
+        share.files += 1;
+        share.bytes += std::fs::metadata(file).map_or(0, |meta| meta.len());
+        if share.language.is_some() && !share.extensions.iter().any(|ext| ext == extension) {
+            share.extensions.push(extension.to_string());
+        }
+    }
+
+    mix.retain(|share| share.files > 0);
+    mix.sort_by_key(|share| (share.language.is_none(), std::cmp::Reverse(share.bytes)));
+    mix
+}
+
+/// A part of `eval.corpora` for every known language with at least
+/// `min_share` of the bytes of `mix`, weighted by its bytes.
+pub fn propose_corpora(corpus: &Path, mix: &[LanguageShare], min_share: f32) -> Vec<CorpusPart> {
+    let total = mix.iter().map(|share| share.bytes).sum::<u64>().max(1) as f32;
+    let kept = mix
+        .iter()
+        .filter(|share| share.language.is_some() && share.bytes as f32 / total >= min_share)
+        .collect::<Vec<_>>();
+    let kept_total = kept.iter().map(|share| share.bytes).sum::<u64>().max(1) as f32;
+    kept.into_iter()
+        .map(|share| CorpusPart {
+            path: corpus.to_path_buf(),
+            weight: (share.bytes as f32 / kept_total * 100.0).round() / 100.0,
+            mode: Some(share.mode),
+            extensions: Some(share.extensions.clone()),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct RankedLayout {
+    pub name: String,
+    /// `None` if the layout failed to load or cannot type the alphabet
+    pub scaled: Option<Evaluation>,
+    pub score: f32,
+}
+
+/// Scores every named layout, best first. `None` layouts failed to load.
+pub fn rank_named(
+    layouts: impl IntoIterator<Item = (String, Option<Layout>)>,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    reference: Evaluation,
+    weights: &EvalWeights,
+) -> Vec<RankedLayout> {
+    let mut ranked = layouts
+        .into_iter()
+        .map(|(name, layout)| {
+            let scaled = layout.and_then(|layout| {
+                let info = KeyboardLayout::generate(&layout, keyboard).ok()?;
+                Some(evaluate::evaluate(&info, count) / reference * 100.0)
+            });
+            let score = scaled.map_or(f32::INFINITY, |e| weights.score(&e));
+            RankedLayout {
+                name,
+                scaled,
+                score,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    ranked.sort_by(|a, b| a.score.total_cmp(&b.score));
+    ranked
+}
+
+/// Every layout json directly inside `dir`, best score first.
+pub fn rank_layouts(
+    dir: impl AsRef<Path>,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    reference: Evaluation,
+    weights: &EvalWeights,
+) -> AnyResult<Vec<RankedLayout>> {
+    let mut layouts = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        layouts.push((name, Layout::load(&path).ok()));
+    }
+    Ok(rank_named(layouts, keyboard, count, reference, weights))
+}
+
+/// The built-in layouts plus `extra`, ranked on the same corpus.
+pub fn benchmark(
+    extra: Vec<(String, Layout)>,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    reference: Evaluation,
+    weights: &EvalWeights,
+) -> Vec<RankedLayout> {
+    let layouts = ferris::BUILTIN
+        .into_iter()
+        .map(|name| (name.to_string(), ferris::by_name(name)))
+        .chain(extra.into_iter().map(|(name, layout)| (name, Some(layout))));
+    rank_named(layouts, keyboard, count, reference, weights)
+}
+
+/// `layout` with its digits as they are, as a numpad and as a row, on the
+/// layer that holds them. The numpad takes the index, middle and ring columns
+/// of the hand that does not hold the layer, 789 on top and 0 on a free
+/// thumb, and the row takes the home row, 1 to 5 on the left. Whatever the
+/// digits displace moves to the keys they left. An arrangement that needs the
+/// key holding the layer is `None`.
+pub fn digit_arrangements(layout: &Layout) -> AnyResult<Vec<(String, Option<Layout>)>> {
+    let Some(li) = digit_layer(layout) else {
+        bail!("the layout has no digits");
+    };
+    let keys = layout.layer(li as u8);
+    let mut digits = keys
+        .iter()
+        .enumerate()
+        .filter(|(_, key)| key.is_some_and(|key| key.char().is_ascii_digit()))
+        .collect::<Vec<_>>();
+    digits.sort_by_key(|&(_, key)| *key);
+    digits.dedup_by_key(|&mut (_, key)| *key);
+    if digits.len() != 10 || keys.len() < 34 {
+        bail!("layer {li} does not hold each digit on one of 34 keys");
+    }
+    let holds = (0..keys.len())
+        .filter(|&i| match layout.base_hold().get(i) {
+            Some(Some(Behavior::Layer(layer))) => usize::from(layer.get()) == li,
+            _ => false,
+        })
+        .collect::<Vec<_>>();
+    let on_right = |i: usize| (15..30).contains(&i) || i >= 32;
+
+    // key index of each digit 0 to 9
+    let (numpad, columns, thumbs) = match !holds.is_empty() && holds.iter().all(|&i| on_right(i)) {
+        true => ("numpad (left)", [3, 2, 1], [31, 30]),
+        false => ("numpad (right)", [6, 7, 8], [32, 33]),
+    };
+    let thumb = thumbs
+        .into_iter()
+        .find(|i| !holds.contains(i))
+        .unwrap_or(thumbs[0]);
+    let mut pad = [thumb; 10];
+    for digit in 1..10 {
+        pad[digit] = columns[(digit - 1) % 3] * 3 + 2 - (digit - 1) / 3;
+    }
+    let row = std::array::from_fn::<_, 10, _>(|digit| ((digit + 9) % 10) * 3 + 1);
+
+    let arrange = |targets: [usize; 10]| {
+        if holds.iter().any(|hold| targets.contains(hold)) {
+            return None;
+        }
+        let mut new = keys.to_vec();
+        let mut freed = vec![];
+        for (i, _) in &digits {
+            new[*i] = None;
+            freed.push(*i);
+        }
+        let mut displaced = vec![];
+        for (digit, &target) in targets.iter().enumerate() {
+            displaced.extend(new[target].take());
+            new[target] = Symbol::from_ascii(b'0' + digit as u8);
+        }
+        let freed = freed
+            .into_iter()
+            .filter(|&i| new[i].is_none())
+            .collect::<Vec<_>>();
+        for (key, i) in displaced.into_iter().zip(freed) {
+            new[i] = Some(key);
+        }
+        let mut layout = layout.clone();
+        layout.layer_mut(li as u8).copy_from_slice(&new);
+        Some(layout)
+    };
+
+    Ok(vec![
+        ("as is".to_string(), Some(layout.clone())),
+        (numpad.to_string(), arrange(pad)),
+        ("row".to_string(), arrange(row)),
+    ])
+}
+
+/// The first layer with a digit on it.
+pub fn digit_layer(layout: &Layout) -> Option<usize> {
+    layout.layers().position(|layer| {
+        layer
+            .iter()
+            .any(|key| key.is_some_and(|key| key.char().is_ascii_digit()))
+    })
+}
+
+/// Relative change in score past which a layout is worth re-optimizing for
+/// the newer corpus.
+pub const REOPTIMIZE_DRIFT: f32 = 0.05;
+
+/// An n-gram whose share of its table differs between two corpora.
+#[derive(Debug, Clone)]
+pub struct NgramShift {
+    pub ngram: Vec<Symbol>,
+    pub old: f32,
+    pub new: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CorpusDiff {
+    pub letters: Vec<NgramShift>,
+    pub bigrams: Vec<NgramShift>,
+    pub trigrams: Vec<NgramShift>,
+    /// characters typed only in the new corpus
+    pub gained: Vec<Symbol>,
+    /// characters typed only in the old corpus
+    pub lost: Vec<Symbol>,
+}
+
+/// The `top_n` n-grams of each length whose share changed most from `old` to
+/// `new`, compared as shares so corpora of any size line up.
+pub fn corpus_diff(old: &CountOutcome, new: &CountOutcome, top_n: usize) -> CorpusDiff {
+    let typed = |count: &CountOutcome, ch: Symbol| count.letter.get(&[ch]).is_some_and(|&f| f > 0);
+    let mut chars = old
+        .letter
+        .keys()
+        .chain(new.letter.keys())
+        .map(|&[ch]| ch)
+        .collect::<Vec<_>>();
+    chars.sort_unstable();
+    chars.dedup();
+
+    CorpusDiff {
+        letters: shifts(&old.letter, &new.letter, top_n),
+        bigrams: shifts(&old.bigrams, &new.bigrams, top_n),
+        trigrams: shifts(&old.trigrams, &new.trigrams, top_n),
+        gained: chars
+            .iter()
+            .copied()
+            .filter(|&ch| !typed(old, ch) && typed(new, ch))
+            .collect(),
+        lost: chars
+            .iter()
+            .copied()
+            .filter(|&ch| typed(old, ch) && !typed(new, ch))
+            .collect(),
+    }
+}
+
+fn shifts<const N: usize>(
+    old: &FxHashMap<[Symbol; N], u32>,
+    new: &FxHashMap<[Symbol; N], u32>,
+    top_n: usize,
+) -> Vec<NgramShift> {
+    let total = |table: &FxHashMap<[Symbol; N], u32>| {
+        table.values().map(|&f| f as f32).sum::<f32>().max(1.0)
+    };
+    let (old_total, new_total) = (total(old), total(new));
+    let share = |table: &FxHashMap<[Symbol; N], u32>, ngram, total| {
+        table.get(ngram).map_or(0.0, |&f| f as f32 / total)
+    };
+
+    let mut result = old
+        .keys()
+        .chain(new.keys().filter(|ngram| !old.contains_key(*ngram)))
+        .map(|ngram| NgramShift {
+            ngram: ngram.to_vec(),
+            old: share(old, ngram, old_total),
+            new: share(new, ngram, new_total),
+        })
+        .collect::<Vec<_>>();
+    result.sort_unstable_by(|a, b| {
+        (b.new - b.old)
+            .abs()
+            .total_cmp(&(a.new - a.old).abs())
+            .then_with(|| a.ngram.cmp(&b.ngram))
+    });
+    result.truncate(top_n);
+    result
+}
+
+/// How a symbol is typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolPath {
+    /// shift held over the key of this character
+    Shift(Symbol),
+    /// a key of its own
+    Key(KeyLoc),
+}
+
+#[derive(Debug, Clone)]
+pub struct ShiftChoice {
+    pub ch: Symbol,
+    /// share of the letters typed, in percent
+    pub share: f32,
+    pub current: SymbolPath,
+    /// the cheapest way of typing it the other way, `None` if there is none
+    pub alternative: Option<SymbolPath>,
+    /// change in score the alternative makes, in percent of the score
+    pub change: f32,
+}
+
+/// For every typed symbol, the score of typing it the other way: a symbol
+/// shifted today on the free key where it costs least, and a symbol on a key
+/// of its own shifted over the key where it costs least, with its key freed.
+/// Only the n-grams of the symbol are evaluated again. Best switches first.
+pub fn shift_choices(
+    layout: &Layout,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    reference: Evaluation,
+    weights: &EvalWeights,
+) -> AnyResult<Vec<ShiftChoice>> {
+    let info =
+        KeyboardLayout::generate(layout, keyboard).map_err(|ch| anyhow!("the layout {ch}"))?;
+    let table = keyboard.shift_table();
+    let score = |eval: Evaluation| weights.score(&(eval / reference * 100.0));
+    let current_score = score(evaluate::evaluate(&info, count));
+    let total = count
+        .letter
+        .values()
+        .map(|&f| f as f32)
+        .sum::<f32>()
+        .max(1.0);
+
+    let mut choices = vec![];
+    for ch in Symbol::alphabet() {
+        let freq = count.letter.get(&[ch]).copied().unwrap_or(0);
+        if ch.char().is_ascii_alphanumeric() || ch.char().is_ascii_whitespace() || freq == 0 {
+            continue;
+        }
+        let (with, without) = split_count(count, ch);
+        let rest = evaluate::evaluate(&info, &without);
+        let score_with = |layout: &Layout, table: ShiftTable| {
+            let keyboard = keyboard.clone().with_shift_table(table);
+            let info = KeyboardLayout::generate(layout, &keyboard).ok()?;
+            let mut eval = rest + evaluate::evaluate(&info, &with);
+            eval.finger.load = core::finger_load(&eval.finger, keyboard.finger_targets());
+            Some(score(eval))
+        };
+
+        let (current, candidates) = match table.is_shifted(ch) {
+            true => (SymbolPath::Shift(table.unshifted(ch)), free_keys(layout)),
+            false => {
+                let Some(loc) = layout.find_all_key(|key| key == ch).next() else {
+                    continue;
+                };
+                (SymbolPath::Key(loc), shiftable_keys(layout, table, ch))
+            }
+        };
+        let best = candidates
+            .into_iter()
+            .filter_map(|path| {
+                let (layout, table) = typed_by(layout, table, ch, current, path)?;
+                Some((path, score_with(&layout, table)?))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        choices.push(ShiftChoice {
+            ch,
+            share: freq as f32 / total * 100.0,
+            current,
+            alternative: best.map(|(path, _)| path),
+            change: best.map_or(0.0, |(_, s)| {
+                (s - current_score) / current_score.abs() * 100.0
+            }),
+        });
+    }
+    choices.sort_by(|a, b| a.change.total_cmp(&b.change).then(a.ch.cmp(&b.ch)));
+    Ok(choices)
+}
+
+#[derive(Debug, Clone)]
+pub struct ThumbChoice {
+    pub ch: Symbol,
+    /// share of the letters typed, in percent
+    pub share: f32,
+    /// keys typing it now
+    pub current: Vec<KeyLoc>,
+    /// of the bigrams ending in it, the share whose first character the left
+    /// hand types, in percent
+    pub left_before: f32,
+    /// the same for the bigrams starting with it and their second character
+    pub left_after: f32,
+    /// every thumb key of its layer with the change in score of typing it
+    /// there alone, in percent of the score; `None` if the layout then cannot
+    /// type everything
+    pub options: Vec<(KeyLoc, Option<f32>)>,
+}
+
+/// For space, enter and tab, which hand types the characters next to them
+/// and the score of each alone on every thumb key of its layer: whatever the
+/// thumb key typed moves to the key it leaves, and any other key typing it
+/// is cleared.
+pub fn thumb_choices(
+    layout: &Layout,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+    reference: Evaluation,
+    weights: &EvalWeights,
+) -> AnyResult<Vec<ThumbChoice>> {
+    let score = |layout: &Layout| {
+        let info = KeyboardLayout::generate(layout, keyboard).ok()?;
+        Some(weights.score(&(evaluate::evaluate(&info, count) / reference * 100.0)))
+    };
+    let current_score =
+        score(layout).ok_or_else(|| anyhow!("the layout cannot type the alphabet"))?;
+    let total = count
+        .letter
+        .values()
+        .map(|&f| f as f32)
+        .sum::<f32>()
+        .max(1.0);
+    let hand_of = |ch: Symbol| {
+        let key = keyboard.shift_table().unshifted(ch);
+        let loc = layout.find_all_key(|k| k == key).next()?;
+        Some(keyboard.key(loc.index()).finger().hand)
+    };
+
+    let mut choices = vec![];
+    for ch in [Symbol::SPACE, Symbol::ENTER, Symbol::TAB] {
+        let freq = count.letter.get(&[ch]).copied().unwrap_or(0);
+        let current = layout.find_all_key(|key| key == ch).collect::<Vec<_>>();
+        let Some(&first) = current.first().filter(|_| freq > 0) else {
+            continue;
+        };
+        // share of the left hand among the neighbours of `ch` on one side
+        let left_share = |side: usize| {
+            let (mut left, mut all) = (0.0, 0.0);
+            for (bigram, &freq) in &count.bigrams {
+                let other = bigram[1 - side];
+                if bigram[side] != ch || other == ch {
+                    continue;
+                }
+                if let Some(hand) = hand_of(other) {
+                    all += freq as f32;
+                    if hand == Hand::Left {
+                        left += freq as f32;
+                    }
+                }
+            }
+            left / f32::max(all, 1.0) * 100.0
+        };
+
+        let options = keyboard
+            .thumb_keys()
+            .into_iter()
+            .filter(|&thumb| thumb < layout.layer_size())
+            .map(|thumb| {
+                let to = KeyLoc::new(first.layer(), thumb);
+                let mut moved = layout.clone();
+                let there = layout.key_at(to).filter(|&key| key != ch);
+                for &loc in &current {
+                    moved.set_key_at(loc, None);
+                }
+                moved.set_key_at(first, there);
+                moved.set_key_at(to, Some(ch));
+                let change =
+                    score(&moved).map(|s| (s - current_score) / current_score.abs() * 100.0);
+                (to, change)
+            })
+            .collect();
+
+        choices.push(ThumbChoice {
+            ch,
+            share: freq as f32 / total * 100.0,
+            left_before: left_share(1),
+            left_after: left_share(0),
+            current,
+            options,
+        });
+    }
+    Ok(choices)
+}
+
+/// The n-grams of `count` that type `ch`, and the rest.
+fn split_count(count: &CountOutcome, ch: Symbol) -> (CountOutcome, CountOutcome) {
+    fn split<const N: usize>(
+        table: &FxHashMap<[Symbol; N], u32>,
+        ch: Symbol,
+        with: &mut FxHashMap<[Symbol; N], u32>,
+        without: &mut FxHashMap<[Symbol; N], u32>,
+    ) {
+        for (&ngram, &freq) in table {
+            match ngram.contains(&ch) {
+                true => with.insert(ngram, freq),
+                false => without.insert(ngram, freq),
+            };
+        }
+    }
+    let mut with = CountOutcome::default();
+    let mut without = CountOutcome {
+        caps_words: count.caps_words.clone(),
+        ..CountOutcome::default()
+    };
+    split(&count.letter, ch, &mut with.letter, &mut without.letter);
+    split(&count.bigrams, ch, &mut with.bigrams, &mut without.bigrams);
+    split(
+        &count.trigrams,
+        ch,
+        &mut with.trigrams,
+        &mut without.trigrams,
+    );
+    split(
+        &count.skipgrams,
+        ch,
+        &mut with.skipgrams,
+        &mut without.skipgrams,
+    );
+    split(
+        &count.quadgrams,
+        ch,
+        &mut with.quadgrams,
+        &mut without.quadgrams,
+    );
+    (with, without)
+}
+
+fn free_keys(layout: &Layout) -> Vec<SymbolPath> {
+    layout
+        .locs()
+        .filter(|&loc| layout.key_at(loc).is_none())
+        .map(SymbolPath::Key)
+        .collect()
+}
+
+/// Symbols on the layout whose shift types nothing yet.
+fn shiftable_keys(layout: &Layout, table: &ShiftTable, ch: Symbol) -> Vec<SymbolPath> {
+    let mut keys = layout
+        .iter_keys()
+        .map(|(_, key)| key)
+        .filter(|&key| {
+            key != ch
+                && key.char().is_ascii_graphic()
+                && !key.char().is_ascii_alphabetic()
+                && table.shifted(key).is_none()
+                && !table.is_shifted(key)
+        })
+        .collect::<Vec<_>>();
+    keys.sort_unstable();
+    keys.dedup();
+    keys.into_iter().map(SymbolPath::Shift).collect()
+}
+
+/// `layout` and `table` with `ch` typed by `to` instead of `from`.
+fn typed_by(
+    layout: &Layout,
+    table: &ShiftTable,
+    ch: Symbol,
+    from: SymbolPath,
+    to: SymbolPath,
+) -> Option<(Layout, ShiftTable)> {
+    let mut layout = layout.clone();
+    let mut symbols = table
+        .symbols()
+        .filter(|&(shift, _)| shift != ch)
+        .collect::<Vec<_>>();
+    if let SymbolPath::Key(loc) = from {
+        layout.set_key_at(loc, None);
+    }
+    match to {
+        SymbolPath::Key(loc) => layout.set_key_at(loc, Some(ch)),
+        SymbolPath::Shift(key) => symbols.push((ch, key)),
+    }
+    Some((layout, ShiftTable::with_symbols(symbols).ok()?))
+}
+
+/// Coefficient of variation of the final scores, in percent, under which
+/// repeated runs count as agreeing.
+pub const STABLE_SPREAD: f32 = 1.0;
+/// Share of the keys, in percent, repeated runs have to place alike to count
+/// as agreeing.
+pub const STABLE_OVERLAP: f32 = 80.0;
+
+#[derive(Debug, Clone)]
+pub struct Stability {
+    pub scores: Vec<f32>,
+    pub mean: f32,
+    pub std_dev: f32,
+    /// share of the typed characters, in percent, that two runs put on the
+    /// same key, averaged over every pair of runs
+    pub overlap: f32,
+    /// the same against the best run, for each run
+    pub overlap_with_best: Vec<f32>,
+}
+
+impl Stability {
+    /// Whether the runs agree closely enough that a longer or multi-start
+    /// run is unlikely to find much better.
+    pub fn is_robust(&self) -> bool {
+        self.std_dev / self.mean.abs() * 100.0 < STABLE_SPREAD && self.overlap > STABLE_OVERLAP
+    }
+}
+
+/// Spread of the scores and agreement of the layouts of repeated runs.
+pub fn stability(runs: &[(Layout, f32)]) -> Stability {
+    let scores = runs.iter().map(|&(_, score)| score).collect::<Vec<_>>();
+    let n = scores.len().max(1) as f32;
+    let mean = scores.iter().sum::<f32>() / n;
+    let std_dev = (scores.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt();
+
+    let mut pairs = vec![];
+    for (i, (a, _)) in runs.iter().enumerate() {
+        for (b, _) in &runs[i + 1..] {
+            pairs.push(placement_overlap(a, b));
+        }
+    }
+    let overlap = match pairs.is_empty() {
+        true => 100.0,
+        false => pairs.iter().sum::<f32>() / pairs.len() as f32,
+    };
+    let best = runs.iter().min_by(|(_, a), (_, b)| a.total_cmp(b));
+    let overlap_with_best = runs
+        .iter()
+        .map(|(layout, _)| best.map_or(100.0, |(best, _)| placement_overlap(layout, best)))
+        .collect();
+    Stability {
+        scores,
+        mean,
+        std_dev,
+        overlap,
+        overlap_with_best,
+    }
+}
+
+/// Share of the characters of either layout, in percent, that both put on
+/// the same layer and key.
+pub fn placement_overlap(a: &Layout, b: &Layout) -> f32 {
+    let place = |layout: &Layout, ch: Symbol| layout.find_all_key(|key| key == ch).next();
+    let placed = Symbol::alphabet()
+        .map(|ch| (place(a, ch), place(b, ch)))
+        .filter(|places| *places != (None, None))
+        .collect::<Vec<_>>();
+    let same = placed.iter().filter(|(a, b)| a == b).count();
+    same as f32 / placed.len().max(1) as f32 * 100.0
+}
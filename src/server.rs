@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Result as AnyResult};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{self, KeyboardLayout},
+    keyboard::Keyboard,
+    layout::Layout,
+};
+
+fn evaluate_body(
+    request: &mut Request,
+    keyboard: &Keyboard,
+    count: &CountOutcome,
+) -> AnyResult<String> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    let layout: Layout = serde_json::from_str(&body)?;
+    let info = KeyboardLayout::generate(&layout, keyboard).map_err(|k| anyhow!("layout {k}"))?;
+    Ok(serde_json::to_string(&evaluate::evaluate(&info, count))?)
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+/// Serves `POST /evaluate`, which takes a layout json and answers with its raw
+/// `Evaluation` on the preloaded corpus, or `{"error": "..."}` with status 400.
+/// Any origin may call it, so a web frontend can be served from elsewhere.
+pub fn serve(addr: &str, keyboard: &Keyboard, count: &CountOutcome) -> AnyResult<()> {
+    let server = Server::http(addr).map_err(|err| anyhow!("cannot listen on {addr}: {err}"))?;
+    println!("listening on http://{addr}");
+
+    for mut request in server.incoming_requests() {
+        let (status, body) = match (request.method(), request.url()) {
+            (Method::Options, _) => (204, String::new()),
+            (Method::Post, "/evaluate") => match evaluate_body(&mut request, keyboard, count) {
+                Ok(json) => (200, json),
+                Err(err) => (
+                    400,
+                    serde_json::json!({ "error": format!("{err:#}") }).to_string(),
+                ),
+            },
+            _ => (404, serde_json::json!({ "error": "not found" }).to_string()),
+        };
+
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(header("Content-Type", "application/json"))
+            .with_header(header("Access-Control-Allow-Origin", "*"))
+            .with_header(header("Access-Control-Allow-Methods", "POST, OPTIONS"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+        if let Err(err) = request.respond(response) {
+            eprintln!("failed to respond: {err}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Result as AnyResult};
+use serde::{Deserialize, Serialize};
+
+use crate::counter::{CorpusMode, CountOutcome, NgramDepth, NgramTable};
+
+/// Version tag of the format; files with any other tag are rejected.
+pub const SCHEMA: &str = "keyboard-corpus/1";
+
+/// Corpora shipped with the binary, by name, for results before counting a
+/// corpus of one's own.
+pub const PRESETS: [(&str, &str); 5] = [
+    // READMEs and guides of the crates this one depends on
+    ("english", include_str!("../presets/english.json")),
+    // serde, clap, glam, ratatui, syn and others
+    ("rust", include_str!("../presets/rust.json")),
+    // the standard library without its tests
+    ("python", include_str!("../presets/python.json")),
+    // the shell scripts of a Debian install
+    ("shell", include_str!("../presets/shell.json")),
+    // data entry: tab separated ledgers, invoices and measurements, each row
+    // ending in enter
+    ("numeric", include_str!("../presets/numeric.json")),
+];
+
+/// N-gram counts of a corpus with none of its text beyond the n-grams, for
+/// evaluating layouts on someone else's corpus. The counts are keyed like the
+/// ffi corpus json, so the file can be passed there as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedCorpus {
+    pub schema: String,
+    pub meta: CorpusMeta,
+    #[serde(flatten)]
+    pub counts: NgramTable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusMeta {
+    pub mode: CorpusMode,
+    pub depth: NgramDepth,
+    /// n-grams seen fewer times than this were left out, so that rare
+    /// sequences such as names or keys cannot be pieced back together
+    pub min_count: u32,
+    pub files: usize,
+    pub bytes: u64,
+    pub keystrokes: u64,
+    /// files and bytes per extension
+    pub languages: BTreeMap<String, LanguageStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LanguageStats {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl SharedCorpus {
+    /// `count` as counted from `files`, dropping n-grams seen fewer than
+    /// `min_count` times.
+    pub fn new(
+        count: &CountOutcome,
+        files: &[PathBuf],
+        mode: CorpusMode,
+        depth: NgramDepth,
+        min_count: u32,
+    ) -> Self {
+        let mut languages = BTreeMap::<String, LanguageStats>::new();
+        for file in files {
+            let extension = file
+                .extension()
+                .map_or(String::new(), |ext| ext.to_string_lossy().into_owned());
+            let stats = languages.entry(extension).or_default();
+            stats.files += 1;
+            stats.bytes += std::fs::metadata(file).map_or(0, |meta| meta.len());
+        }
+
+        Self {
+            schema: SCHEMA.to_string(),
+            meta: CorpusMeta {
+                mode,
+                depth,
+                min_count,
+                files: files.len(),
+                bytes: languages.values().map(|stats| stats.bytes).sum(),
+                keystrokes: count.letter.values().map(|&freq| u64::from(freq)).sum(),
+                languages,
+            },
+            counts: NgramTable::new(count, min_count),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> AnyResult<Self> {
+        let shared: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if shared.schema != SCHEMA {
+            bail!(
+                "unsupported corpus schema {:?}, expected {SCHEMA:?}",
+                shared.schema
+            );
+        }
+        Ok(shared)
+    }
+
+    /// The bundled corpus called `name`, see [`PRESETS`].
+    pub fn preset(name: &str) -> AnyResult<Self> {
+        let Some((_, json)) = PRESETS.iter().find(|(preset, _)| *preset == name) else {
+            let names = PRESETS.map(|(name, _)| name);
+            bail!(
+                "unknown preset {name:?}, expected one of {}",
+                names.join(", ")
+            );
+        };
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> AnyResult<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn to_count(&self) -> AnyResult<CountOutcome> {
+        self.counts.to_count()
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context as _, Result as AnyResult};
+
+/// The bash, zsh and fish history files of the current user that exist.
+pub fn default_files() -> Vec<PathBuf> {
+    let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+        return vec![];
+    };
+    let data =
+        std::env::var_os("XDG_DATA_HOME").map_or_else(|| home.join(".local/share"), PathBuf::from);
+    [
+        home.join(".bash_history"),
+        home.join(".zsh_history"),
+        home.join(".histfile"),
+        data.join("fish/fish_history"),
+    ]
+    .into_iter()
+    .filter(|path| path.is_file())
+    .collect()
+}
+
+/// `~/` at the start of `path` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Every command in a history file, oldest first. Fish's yaml is told apart
+/// by its `- cmd: ` lines; otherwise each line is a command, with the
+/// `#1700000000` timestamp lines of bash skipped, the `: 1700000000:0;`
+/// prefix of zsh's extended history removed and lines ending in a backslash
+/// joined to the next.
+pub fn read_commands(path: &Path) -> AnyResult<Vec<String>> {
+    let bytes = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+    let text = String::from_utf8_lossy(&bytes);
+    if text.lines().any(|line| line.starts_with("- cmd: ")) {
+        let commands = text.lines().filter_map(|line| line.strip_prefix("- cmd: "));
+        return Ok(commands.map(unescape_fish).collect());
+    }
+
+    let mut commands = vec![];
+    let mut pending: Option<String> = None;
+    for line in text.lines() {
+        let line = match pending.take() {
+            Some(mut command) => {
+                command.push('\n');
+                command.push_str(line);
+                command
+            }
+            None if is_bash_timestamp(line) => continue,
+            None => strip_zsh_prefix(line).to_string(),
+        };
+        match line.strip_suffix('\\') {
+            Some(continued) => pending = Some(continued.to_string()),
+            None => commands.push(line),
+        }
+    }
+    commands.extend(pending);
+    Ok(commands)
+}
+
+/// The commands of `paths` as text to count, one per line. Each distinct
+/// command is typed once per doubling of how often it was run, so a `ls` run
+/// 500 times counts 9 times rather than drowning out the rest.
+pub fn corpus_text(paths: &[PathBuf]) -> AnyResult<String> {
+    if paths.is_empty() {
+        bail!("no shell history found, pass history:<file>");
+    }
+    let mut runs = BTreeMap::<String, u32>::new();
+    for path in paths {
+        for command in read_commands(path)? {
+            let command = command.trim();
+            if !command.is_empty() {
+                *runs.entry(command.to_string()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut text = String::new();
+    for (command, times) in runs {
+        for _ in 0..=times.ilog2() {
+            text.push_str(&command);
+            text.push('\n');
+        }
+    }
+    Ok(text)
+}
+
+fn is_bash_timestamp(line: &str) -> bool {
+    line.strip_prefix('#')
+        .is_some_and(|rest| !rest.is_empty() && rest.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// `line` without the `: <start>:<duration>;` of zsh's extended history.
+fn strip_zsh_prefix(line: &str) -> &str {
+    let Some(rest) = line.strip_prefix(": ") else {
+        return line;
+    };
+    match rest.split_once(';') {
+        Some((stamp, command)) if stamp.bytes().all(|b| b.is_ascii_digit() || b == b':') => command,
+        _ => line,
+    }
+}
+
+/// Fish writes newlines as `\n` and backslashes as `\\`.
+fn unescape_fish(command: &str) -> String {
+    let mut out = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(ch) = chars.next() {
+        match (ch, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                out.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                out.push('\\');
+                chars.next();
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
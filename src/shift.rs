@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::symbol::Symbol;
+
+/// Which characters are typed by holding shift over the key of another.
+/// `A`-`Z` always shift `a`-`z`; the symbol pairs depend on the locale, and
+/// the default only pairs `?` with `/` as on US-ASCII layouts, since a
+/// layered layout gives the other symbols keys of their own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "BTreeMap<char, char>", into = "BTreeMap<char, char>")]
+pub struct ShiftTable {
+    unshifted: [u8; 256],
+    shifted: [Option<u8>; 256],
+}
+
+impl ShiftTable {
+    /// Every shifted symbol of a US keyboard, for the host's keymap.
+    pub fn us() -> Self {
+        let shifted = b"~!@#$%^&*()_+{}|:\"<>?";
+        let unshifted = b"`1234567890-=[]\\;',./";
+        let pairs = shifted
+            .iter()
+            .zip(unshifted)
+            .map(|(&shift, &base)| (Symbol::byte(shift), Symbol::byte(base)));
+        Self::with_symbols(pairs).unwrap()
+    }
+
+    /// The letters and `(shifted, unshifted)` symbol pairs.
+    pub fn with_symbols(pairs: impl IntoIterator<Item = (Symbol, Symbol)>) -> Result<Self, String> {
+        let byte = |symbol: Symbol| {
+            symbol
+                .ascii()
+                .ok_or_else(|| format!("{symbol:?} is not ascii"))
+        };
+        let pairs = pairs
+            .into_iter()
+            .map(|(shift, base)| Ok((byte(shift)?, byte(base)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        let mut unshifted = std::array::from_fn(|i| i as u8);
+        let mut shifted = [None; 256];
+        let letters = (b'A'..=b'Z').map(|ch| (ch, ch.to_ascii_lowercase()));
+        for (shift, base) in letters.chain(pairs) {
+            if shift == base
+                || unshifted[usize::from(shift)] != shift
+                || shifted[usize::from(shift)].is_some()
+            {
+                return Err(format!("{:?} is already paired", char::from(shift)));
+            }
+            if let Some(other) = shifted[usize::from(base)] {
+                return Err(format!(
+                    "shifting {:?} types {:?} already",
+                    char::from(base),
+                    char::from(other)
+                ));
+            }
+            if unshifted[usize::from(base)] != base {
+                return Err(format!(
+                    "{:?} is a shifted character itself",
+                    char::from(base)
+                ));
+            }
+            unshifted[usize::from(shift)] = base;
+            shifted[usize::from(base)] = Some(shift);
+        }
+        Ok(Self { unshifted, shifted })
+    }
+
+    /// The key that has to be shifted to type `key`, or `key` itself. Only
+    /// ascii is ever shifted.
+    pub fn unshifted(&self, key: Symbol) -> Symbol {
+        key.ascii()
+            .and_then(|byte| Symbol::from_ascii(self.unshifted[usize::from(byte)]))
+            .unwrap_or(key)
+    }
+
+    /// What shifting `key` types, if anything.
+    pub fn shifted(&self, key: Symbol) -> Option<Symbol> {
+        Symbol::from_ascii(self.shifted[usize::from(key.ascii()?)]?)
+    }
+
+    pub fn is_shifted(&self, key: Symbol) -> bool {
+        self.unshifted(key) != key
+    }
+
+    /// The `(shifted, unshifted)` pairs other than the letters.
+    pub fn symbols(&self) -> impl Iterator<Item = (Symbol, Symbol)> + '_ {
+        (1..0x80)
+            .map(Symbol::byte)
+            .filter(|&ch| self.is_shifted(ch) && !ch.char().is_ascii_uppercase())
+            .map(|ch| (ch, self.unshifted(ch)))
+    }
+}
+
+impl Default for ShiftTable {
+    fn default() -> Self {
+        Self::with_symbols([(Symbol::byte(b'?'), Symbol::byte(b'/'))]).unwrap()
+    }
+}
+
+impl TryFrom<BTreeMap<char, char>> for ShiftTable {
+    type Error = String;
+
+    fn try_from(symbols: BTreeMap<char, char>) -> Result<Self, Self::Error> {
+        let symbol =
+            |ch: char| Symbol::from_char(ch).ok_or_else(|| format!("{ch:?} has no symbol"));
+        let pairs = symbols
+            .into_iter()
+            .map(|(shift, base)| Ok((symbol(shift)?, symbol(base)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Self::with_symbols(pairs)
+    }
+}
+
+impl From<ShiftTable> for BTreeMap<char, char> {
+    fn from(table: ShiftTable) -> Self {
+        table
+            .symbols()
+            .map(|(shift, base)| (shift.char(), base.char()))
+            .collect()
+    }
+}
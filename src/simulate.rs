@@ -0,0 +1,108 @@
+use array_map::ArrayMap;
+
+use crate::{
+    evaluate::{KeyCombo, KeyboardLayout},
+    keyboard::HandFinger,
+    symbol::Symbol,
+};
+
+/// Totals from typing a text key by key.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// characters typed
+    pub typed: u64,
+    /// characters the layout cannot type, or outside the alphabet
+    pub skipped: u64,
+    pub travel: ArrayMap<HandFinger, f32, 10>,
+    /// consecutive taps by the same finger on different keys
+    pub sfbs: u64,
+    /// taps by the same finger on different keys with one tap between them
+    pub sfss: u64,
+    /// consecutive characters tapped on different layers
+    pub layer_switches: u64,
+}
+
+impl Simulation {
+    pub fn total_travel(&self) -> f32 {
+        self.travel.values().sum()
+    }
+}
+
+/// Types `text` on `info`, picking for each character the combo that moves the
+/// fingers least. Fingers start on their base keys and stay on the last key
+/// they pressed or held.
+pub fn simulate(info: &KeyboardLayout, text: &[u8]) -> Simulation {
+    let keyboard = info.keyboard();
+    let mut at = ArrayMap::<HandFinger, _, 10>::new([None; 10]);
+    for (i, key) in keyboard.keys().iter().enumerate() {
+        if key.is_base() {
+            at[key.finger()] = Some(i);
+        }
+    }
+
+    let pressed = |combo: &KeyCombo| {
+        std::iter::once(combo.key())
+            .chain(combo.chord())
+            .chain(combo.shift())
+            .chain(combo.layer())
+            .collect::<Vec<_>>()
+    };
+    let moves = |at: &ArrayMap<HandFinger, Option<usize>, 10>, index: usize| {
+        let key = keyboard.key(index);
+        at[key.finger()].map_or(0.0, |from| keyboard.key(from).pos().distance(key.pos()))
+    };
+
+    let mut sim = Simulation {
+        typed: 0,
+        skipped: 0,
+        travel: ArrayMap::new([0.0; 10]),
+        sfbs: 0,
+        sfss: 0,
+        layer_switches: 0,
+    };
+    let mut last: Option<KeyCombo> = None;
+    let mut before_last: Option<KeyCombo> = None;
+
+    for &ch in text {
+        if ch == b'\r' {
+            continue;
+        }
+        let Some(combos) = Symbol::from_ascii(ch).and_then(|ch| info.try_key(ch)) else {
+            sim.skipped += 1;
+            continue;
+        };
+        let combo = *combos
+            .iter()
+            .min_by(|a, b| {
+                let cost = |c| pressed(c).into_iter().map(|i| moves(&at, i)).sum::<f32>();
+                cost(a).total_cmp(&cost(b))
+            })
+            .unwrap();
+
+        let same_finger = |other: KeyCombo| {
+            let (prev, now) = (keyboard.key(other.key()), keyboard.key(combo.key()));
+            other.key() != combo.key() && prev.finger() == now.finger()
+        };
+        if before_last.is_some_and(same_finger) {
+            sim.sfss += 1;
+        }
+        if let Some(last) = last {
+            if same_finger(last) {
+                sim.sfbs += 1;
+            }
+            if last.tap_layer() != combo.tap_layer() {
+                sim.layer_switches += 1;
+            }
+        }
+
+        for index in pressed(&combo) {
+            let finger = keyboard.key(index).finger();
+            sim.travel[finger] += moves(&at, index);
+            at[finger] = Some(index);
+        }
+        sim.typed += 1;
+        before_last = last;
+        last = Some(combo);
+    }
+    sim
+}
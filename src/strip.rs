@@ -0,0 +1,215 @@
+use std::path::Path;
+
+/// Comment and string syntax of a source file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// nested block comments, raw strings and lifetimes
+    Rust,
+    Python,
+    /// `//` and `/* */` comments, such as the shader languages
+    CLike,
+}
+
+impl Language {
+    /// The language of `path` by its extension, `None` if unknown.
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Self::Rust),
+            "py" | "pyi" => Some(Self::Python),
+            "c" | "h" | "cc" | "cpp" | "hpp" | "js" | "ts" | "java" | "go" | "wgsl" | "glsl"
+            | "vert" | "frag" | "comp" => Some(Self::CLike),
+            _ => None,
+        }
+    }
+}
+
+/// `source` with comments and/or the contents of string literals left out.
+/// Quotes stay, so `"text"` becomes `""`. Lines left blank by a removed
+/// comment are dropped, and trailing whitespace before a comment with them.
+pub fn strip(source: &str, language: Language, comments: bool, strings: bool) -> String {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(source.len());
+    let mut line_start = 0;
+    let mut commented = false;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '\n' {
+            end_line(&mut out, line_start, commented, true);
+            line_start = out.len();
+            commented = false;
+            i += 1;
+            continue;
+        }
+
+        let line_comment = match language {
+            Language::Python => ch == '#',
+            Language::Rust | Language::CLike => starts_with(&chars, i, "//"),
+        };
+        if line_comment {
+            let end = chars[i..]
+                .iter()
+                .position(|&ch| ch == '\n')
+                .map_or(chars.len(), |n| i + n);
+            copy(&mut out, &chars[i..end], !comments);
+            commented |= comments;
+            i = end;
+            continue;
+        }
+
+        if language != Language::Python && starts_with(&chars, i, "/*") {
+            let mut end = block_comment_end(&chars, i, language == Language::Rust);
+            copy(&mut out, &chars[i..end], !comments);
+            // `a /* b */ c` becomes `a c`
+            let spaced = out.len() == line_start || out.ends_with([' ', '\t']);
+            if comments && spaced && chars.get(end) == Some(&' ') {
+                end += 1;
+            }
+            commented |= comments;
+            i = end;
+            continue;
+        }
+
+        if let Some((open, close, escapes)) = string_start(&chars, i, language) {
+            copy(&mut out, &chars[i..i + open], true);
+            i += open;
+            let start = i;
+            while i < chars.len() && !starts_with(&chars, i, &close) {
+                i += match escapes && chars[i] == '\\' {
+                    true => 2,
+                    false => 1,
+                };
+            }
+            let i_end = i.min(chars.len());
+            copy(&mut out, &chars[start..i_end], !strings);
+            i = (i_end + close.chars().count()).min(chars.len());
+            copy(&mut out, &chars[i_end..i], true);
+            continue;
+        }
+
+        if ch == '\'' && language != Language::Python {
+            if let Some(end) = char_literal_end(&chars, i) {
+                copy(&mut out, &chars[i..end], true);
+                i = end;
+                continue;
+            }
+        }
+
+        out.push(ch);
+        i += 1;
+    }
+    end_line(&mut out, line_start, commented, false);
+    out
+}
+
+fn copy(out: &mut String, chars: &[char], keep: bool) {
+    if keep {
+        out.extend(chars);
+    }
+}
+
+fn starts_with(chars: &[char], i: usize, pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .all(|(at, ch)| chars.get(i + at) == Some(&ch))
+}
+
+/// Trims the line that starts at `line_start` if a comment was removed from
+/// it, dropping it entirely if nothing else is left.
+fn end_line(out: &mut String, line_start: usize, commented: bool, newline: bool) {
+    if commented {
+        let kept = out[line_start..].trim_end().len();
+        out.truncate(line_start + kept);
+        if kept == 0 {
+            return;
+        }
+    }
+    if newline {
+        out.push('\n');
+    }
+}
+
+/// Index just past the block comment opening at `i`.
+fn block_comment_end(chars: &[char], mut i: usize, nested: bool) -> usize {
+    let mut depth = 0;
+    while i < chars.len() {
+        if starts_with(chars, i, "/*") && (nested || depth == 0) {
+            depth += 1;
+            i += 2;
+        } else if starts_with(chars, i, "*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    chars.len()
+}
+
+/// If a string literal opens at `i`: the length of its prefix and opening
+/// quote, the closing delimiter, and whether backslashes escape.
+fn string_start(chars: &[char], i: usize, language: Language) -> Option<(usize, String, bool)> {
+    let ident = |ch: char| ch.is_alphanumeric() || ch == '_';
+    if i > 0 && ident(chars[i - 1]) && chars[i] != '"' && chars[i] != '\'' {
+        return None;
+    }
+    let prefix = chars[i..]
+        .iter()
+        .take_while(|&&ch| ch.is_ascii_alphabetic())
+        .collect::<String>();
+
+    match language {
+        Language::Rust => {
+            let raw = matches!(prefix.as_str(), "r" | "br" | "cr");
+            if !(raw || matches!(prefix.as_str(), "" | "b" | "c")) {
+                return None;
+            }
+            let at = i + prefix.len();
+            let hashes = match raw {
+                true => chars[at..].iter().take_while(|&&ch| ch == '#').count(),
+                false => 0,
+            };
+            (chars.get(at + hashes) == Some(&'"')).then(|| {
+                let close = format!("\"{}", "#".repeat(hashes));
+                (prefix.len() + hashes + 1, close, !raw)
+            })
+        }
+        Language::Python => {
+            let prefix_ok = prefix.len() <= 2 && prefix.chars().all(|ch| "rRbBuUfF".contains(ch));
+            let at = i + prefix.len();
+            let quote = *chars.get(at)?;
+            if !prefix_ok || (quote != '"' && quote != '\'') {
+                return None;
+            }
+            let triple = quote.to_string().repeat(3);
+            let close = match starts_with(chars, at, &triple) {
+                true => triple,
+                false => quote.to_string(),
+            };
+            Some((prefix.len() + close.chars().count(), close, true))
+        }
+        Language::CLike => (chars[i] == '"').then(|| (1, "\"".to_string(), true)),
+    }
+}
+
+/// Index just past the character literal opening at `i`, `None` if the quote
+/// starts a lifetime instead.
+fn char_literal_end(chars: &[char], i: usize) -> Option<usize> {
+    match chars.get(i + 1)? {
+        '\\' => {
+            // `'\n'`, `'\''` and `'\u{1F600}'`
+            let close = chars
+                .iter()
+                .skip(i + 3)
+                .take(10)
+                .position(|&ch| ch == '\'')?;
+            Some(i + 3 + close + 1)
+        }
+        _ => (chars.get(i + 2) == Some(&'\'')).then_some(i + 3),
+    }
+}
@@ -0,0 +1,195 @@
+use rustc_hash::FxHashMap;
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    fmt,
+    num::NonZeroU16,
+    sync::{LazyLock, RwLock},
+};
+
+use crate::{in_alphabet, ALPHABET};
+
+/// One typed symbol: a character or a named key such as enter.
+///
+/// Ascii symbols are their own byte, so converting from and to bytes is free.
+/// Every other character gets the next free id above ascii the first time it
+/// is seen, which makes ids differ between runs: symbols are written out as
+/// their character, never their id.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(NonZeroU16);
+
+/// Characters past ascii in the order they were interned, and their ids.
+#[derive(Default)]
+struct Interner {
+    chars: Vec<char>,
+    ids: FxHashMap<char, Symbol>,
+}
+
+impl Interner {
+    /// The id of `ch`, giving it the next one up to `last` if it is new.
+    fn intern(&mut self, ch: char, last: u16) -> Option<Symbol> {
+        if let Some(&symbol) = self.ids.get(&ch) {
+            return Some(symbol);
+        }
+        let id = u16::try_from(self.chars.len())
+            .ok()
+            .and_then(|index| index.checked_add(FIRST_INTERNED))
+            .filter(|&id| id <= last)?;
+        let symbol = Symbol(NonZeroU16::new(id).unwrap());
+        self.chars.push(ch);
+        self.ids.insert(ch, symbol);
+        Some(symbol)
+    }
+}
+
+static INTERNER: LazyLock<RwLock<Interner>> = LazyLock::new(Default::default);
+
+/// First id past ascii.
+const FIRST_INTERNED: u16 = 0x80;
+
+const NAMES: [(Symbol, &str); 6] = [
+    (Symbol::SPACE, "space"),
+    (Symbol::TAB, "tab"),
+    (Symbol::ENTER, "enter"),
+    (Symbol::ESCAPE, "escape"),
+    (Symbol::BACKSPACE, "backspace"),
+    (Symbol::DELETE, "delete"),
+];
+
+impl Symbol {
+    pub const SPACE: Self = Self::byte(b' ');
+    pub const TAB: Self = Self::byte(b'\t');
+    pub const ENTER: Self = Self::byte(b'\n');
+    pub const ESCAPE: Self = Self::byte(0x1b);
+    pub const BACKSPACE: Self = Self::byte(0x08);
+    pub const DELETE: Self = Self::byte(0x7f);
+
+    /// The symbol of an ascii byte, for constants.
+    pub const fn byte(byte: u8) -> Self {
+        match Self::from_ascii(byte) {
+            Some(symbol) => symbol,
+            None => panic!("not a nonzero ascii byte"),
+        }
+    }
+
+    /// `None` for zero and bytes past ascii.
+    pub const fn from_ascii(byte: u8) -> Option<Self> {
+        match NonZeroU16::new(byte as u16) {
+            Some(id) if byte.is_ascii() => Some(Self(id)),
+            _ => None,
+        }
+    }
+
+    /// Interns `ch` if it is new. `None` for the nul character and for new
+    /// characters once every id is taken.
+    pub fn from_char(ch: char) -> Option<Self> {
+        if let Ok(byte) = u8::try_from(ch) {
+            if byte.is_ascii() {
+                return Self::from_ascii(byte);
+            }
+        }
+        if let Some(&symbol) = INTERNER.read().unwrap().ids.get(&ch) {
+            return Some(symbol);
+        }
+        INTERNER.write().unwrap().intern(ch, u16::MAX)
+    }
+
+    /// The key named `name`, as written by [`Symbol::name`].
+    pub fn named(name: &str) -> Option<Self> {
+        NAMES
+            .iter()
+            .find(|&&(_, n)| n == name)
+            .map(|&(symbol, _)| symbol)
+    }
+
+    pub fn name(self) -> Option<&'static str> {
+        NAMES
+            .iter()
+            .find(|&&(symbol, _)| symbol == self)
+            .map(|&(_, name)| name)
+    }
+
+    /// The id itself, the same within one run only.
+    pub fn id(self) -> u16 {
+        self.0.get()
+    }
+
+    /// The byte of an ascii symbol.
+    pub fn ascii(self) -> Option<u8> {
+        u8::try_from(self.0.get()).ok().filter(u8::is_ascii)
+    }
+
+    pub fn char(self) -> char {
+        match self.ascii() {
+            Some(byte) => char::from(byte),
+            None => INTERNER.read().unwrap().chars[usize::from(self.0.get() - FIRST_INTERNED)],
+        }
+    }
+
+    /// Every symbol of [`ALPHABET`] in its order.
+    pub fn alphabet() -> impl Iterator<Item = Self> {
+        ALPHABET.iter().map(|&byte| Self::byte(byte))
+    }
+
+    /// Whether the symbol is one of [`ALPHABET`], the symbols that are
+    /// counted and placed.
+    pub fn in_alphabet(self) -> bool {
+        self.ascii().is_some_and(in_alphabet)
+    }
+}
+
+/// The text of an n-gram.
+pub fn text(symbols: &[Symbol]) -> String {
+    symbols.iter().map(|symbol| symbol.char()).collect()
+}
+
+/// The n-gram of `text`, `None` unless it is `N` characters that all have a
+/// symbol.
+pub fn ngram<const N: usize>(text: &str) -> Option<[Symbol; N]> {
+    let symbols = text
+        .chars()
+        .map(Symbol::from_char)
+        .collect::<Option<Vec<_>>>()?;
+    symbols.try_into().ok()
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.char())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.char())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_char(self.char())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let ch = char::deserialize(deserializer)?;
+        Self::from_char(ch).ok_or_else(|| D::Error::custom(format!("{ch:?} has no symbol")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interner_runs_out_of_ids() {
+        let mut interner = Interner::default();
+        let last = FIRST_INTERNED + 1;
+        let a = interner.intern('é', last).unwrap();
+        let b = interner.intern('ß', last).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(interner.intern('ж', last), None);
+        assert_eq!(interner.intern('é', last), Some(a));
+        assert_eq!(interner.chars, ['é', 'ß']);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{BufRead, Write};
+
+use anyhow::{Context as _, Result as AnyResult};
+
+use crate::{
+    layout::{KeyLoc, Layout},
+    shift::ShiftTable,
+    symbol::Symbol,
+};
+
+/// Written in place of a character the other layout cannot type.
+pub const MISSING: char = '\u{fffd}';
+
+/// Maps what a key produces under one layout to what the same physical key
+/// (and the same layer/shift) produces under another.
+pub struct Translator {
+    map: [Option<Symbol>; 128],
+}
+
+impl Translator {
+    /// Shifted characters are typed by shifting the key `shift` pairs them
+    /// with, on both layouts. Fails if `from` types a character on a layer or
+    /// key `to` does not have.
+    pub fn new(from: &Layout, to: &Layout, shift: &ShiftTable) -> AnyResult<Self> {
+        let mut map = [None; 128];
+        for byte in 1..0x80 {
+            let ch = Symbol::byte(byte);
+            let real = shift.unshifted(ch);
+            let Some(loc) = from.find_all_key(|k| k == real).next() else {
+                continue;
+            };
+            map[usize::from(byte)] = Self::produce(to, loc, real != ch, shift)
+                .with_context(|| format!("cannot translate {ch:?}"))?;
+        }
+        Ok(Self { map })
+    }
+
+    fn produce(
+        layout: &Layout,
+        loc: KeyLoc,
+        shifted: bool,
+        shift: &ShiftTable,
+    ) -> AnyResult<Option<Symbol>> {
+        let Some(key) = layout.checked_key_at(loc)? else {
+            return Ok(None);
+        };
+        Ok(match shifted {
+            true => shift.shifted(key),
+            false => Some(key),
+        })
+    }
+
+    pub fn translate(&self, ch: Symbol) -> Option<Symbol> {
+        self.map[usize::from(ch.ascii()?)]
+    }
+
+    /// Characters without a counterpart become [`MISSING`].
+    pub fn translate_str(&self, text: &str) -> String {
+        text.chars()
+            .map(|ch| {
+                let symbol = u8::try_from(ch).ok().and_then(Symbol::from_ascii);
+                symbol
+                    .and_then(|symbol| self.translate(symbol))
+                    .map_or(MISSING, Symbol::char)
+            })
+            .collect()
+    }
+
+    /// Translates line by line, returning how many characters had no
+    /// counterpart.
+    pub fn run(&self, input: impl BufRead, mut output: impl Write) -> std::io::Result<usize> {
+        let mut missing = 0;
+        for line in input.lines() {
+            let translated = self.translate_str(&line?);
+            missing += translated.chars().filter(|&ch| ch == MISSING).count();
+            writeln!(output, "{translated}")?;
+            output.flush()?;
+        }
+        Ok(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ferris;
+
+    #[test]
+    fn same_layout_types_the_same() {
+        let qwerty = ferris::qwerty();
+        let translator = Translator::new(&qwerty, &qwerty, &ShiftTable::default()).unwrap();
+        assert_eq!(
+            translator.translate_str("Hello (world) 123"),
+            "Hello (world) 123"
+        );
+    }
+
+    #[test]
+    fn keys_keep_their_position() {
+        let shift = ShiftTable::default();
+        let translator = Translator::new(&ferris::qwerty(), &ferris::colemak_dh(), &shift).unwrap();
+        assert_eq!(translator.translate_str("asdf"), "arst");
+        assert_eq!(translator.translate_str("Jk"), "Ne");
+    }
+
+    #[test]
+    fn untypeable_characters_are_marked() {
+        let qwerty = ferris::qwerty();
+        let translator = Translator::new(&qwerty, &qwerty, &ShiftTable::default()).unwrap();
+        assert_eq!(translator.translate_str("a\u{e9}b"), format!("a{MISSING}b"));
+    }
+
+    #[test]
+    fn missing_layer_is_an_error() {
+        let (base_hold, mut layers) = ferris::qwerty().into_parts();
+        layers.truncate(2);
+        let two = Layout::new(base_hold, layers);
+        let error = Translator::new(&ferris::qwerty(), &two, &ShiftTable::default())
+            .err()
+            .unwrap();
+        assert!(format!("{error:#}").contains("no layer"), "{error:#}");
+    }
+}
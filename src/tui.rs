@@ -0,0 +1,319 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result as AnyResult};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout as Split},
+    style::{Color, Style, Stylize as _},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::{
+    counter::CountOutcome,
+    evaluate::{self, EvalWeights, Evaluation, KeyboardLayout},
+    keyboard::{KeyGrid, Keyboard},
+    layout::{Behavior, Edit, EditLog, KeyLoc, Layout},
+    symbol::Symbol,
+};
+
+fn key_label(key: Option<Symbol>) -> String {
+    match key {
+        None => "   ".to_string(),
+        Some(Symbol::ENTER) => "RET".to_string(),
+        Some(Symbol::TAB) => "TAB".to_string(),
+        Some(Symbol::SPACE) => "SPC".to_string(),
+        Some(ch) => format!(" {ch} "),
+    }
+}
+
+fn hold_label(hold: Option<Behavior>) -> String {
+    match hold {
+        None => "   ".to_string(),
+        Some(Behavior::Shift) => " S ".to_string(),
+        Some(Behavior::Ctrl) => " C ".to_string(),
+        Some(Behavior::Alt) => " A ".to_string(),
+        Some(Behavior::Gui) => " G ".to_string(),
+        Some(Behavior::Layer(l)) => format!(" {l} "),
+    }
+}
+
+pub struct App<'a> {
+    path: PathBuf,
+    layout: Layout,
+    keyboard: &'a Keyboard,
+    grid: KeyGrid,
+    count: &'a CountOutcome,
+    reference: Evaluation,
+    weights: &'a EvalWeights,
+    /// scaled evaluation when the editor was opened
+    start: Evaluation,
+    scaled: Evaluation,
+    layer: u8,
+    cursor: usize,
+    selected: Option<KeyLoc>,
+    edits: EditLog,
+    message: String,
+}
+
+impl<'a> App<'a> {
+    pub fn new(
+        path: PathBuf,
+        keyboard: &'a Keyboard,
+        count: &'a CountOutcome,
+        reference: Evaluation,
+        weights: &'a EvalWeights,
+    ) -> AnyResult<Self> {
+        let layout = Layout::load(&path)?;
+        let info =
+            KeyboardLayout::generate(&layout, keyboard).map_err(|k| anyhow!("layout {k}"))?;
+        let scaled = evaluate::evaluate(&info, count) / reference * 100.0;
+        Ok(Self {
+            path,
+            grid: keyboard.grid(layout.layer_size()),
+            layout,
+            keyboard,
+            count,
+            reference,
+            weights,
+            start: scaled,
+            scaled,
+            layer: 0,
+            cursor: 0,
+            selected: None,
+            edits: EditLog::default(),
+            message: String::new(),
+        })
+    }
+
+    fn evaluate(&self, layout: &Layout) -> Option<Evaluation> {
+        let info = KeyboardLayout::generate(layout, self.keyboard).ok()?;
+        Some(evaluate::evaluate(&info, self.count) / self.reference * 100.0)
+    }
+
+    fn swap(&mut self, a: KeyLoc, b: KeyLoc) {
+        self.edits.begin();
+        self.edits.apply(&mut self.layout, Edit::SwapKeys(a, b));
+        match self.evaluate(&self.layout) {
+            Some(scaled) => {
+                self.edits.commit();
+                self.message = format!("swapped, score {:+.1}", self.rescore(scaled));
+            }
+            None => {
+                self.edits.rollback(&mut self.layout);
+                self.message = "swap leaves a character untypable".to_string();
+            }
+        }
+    }
+
+    /// Takes `scaled` as the evaluation, returning the change in score.
+    fn rescore(&mut self, scaled: Evaluation) -> f32 {
+        let before = self.weights.score(&self.scaled);
+        self.scaled = scaled;
+        self.weights.score(&scaled) - before
+    }
+
+    /// Undoes or redoes the last swap. Every layout in the log was typable.
+    fn step_history(&mut self, undo: bool) {
+        let (done, verb) = match undo {
+            true => (self.edits.undo(&mut self.layout), "undo"),
+            false => (self.edits.redo(&mut self.layout), "redo"),
+        };
+        if !done {
+            self.message = format!("nothing to {verb}");
+        } else if let Some(scaled) = self.evaluate(&self.layout) {
+            self.message = format!("{verb} swap, score {:+.1}", self.rescore(scaled));
+        }
+    }
+
+    fn move_cursor(&mut self, dx: isize, dy: isize) {
+        let grid = &self.grid;
+        let (column, row) = grid.pos(self.cursor);
+        let column = column.saturating_add_signed(dx).min(grid.columns() - 1);
+        let row = row.saturating_add_signed(dy).min(grid.rows() - 1);
+        // snap to the closest key of a row with gaps, such as the thumb row
+        let target = grid.index(column, row).or_else(|| {
+            (0..grid.columns())
+                .filter_map(|c| Some((c.abs_diff(column), grid.index(c, row)?)))
+                .min()
+                .map(|(_, index)| index)
+        });
+        if let Some(index) = target {
+            self.cursor = index;
+        }
+    }
+
+    /// Returns whether the editor should keep running.
+    fn handle(&mut self, code: KeyCode) -> AnyResult<bool> {
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Left | KeyCode::Char('h') => self.move_cursor(-1, 0),
+            KeyCode::Right | KeyCode::Char('l') => self.move_cursor(1, 0),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(0, -1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(0, 1),
+            KeyCode::Tab => self.layer = (self.layer + 1) % self.layout.layer_count(),
+            KeyCode::BackTab => {
+                self.layer =
+                    (self.layer + self.layout.layer_count() - 1) % self.layout.layer_count()
+            }
+            KeyCode::Char(' ') | KeyCode::Enter => {
+                let here = KeyLoc::new(self.layer, self.cursor);
+                match self.selected.take() {
+                    Some(first) if first != here => self.swap(first, here),
+                    Some(_) => {}
+                    None => self.selected = Some(here),
+                }
+            }
+            KeyCode::Char('u') => self.step_history(true),
+            KeyCode::Char('r') => self.step_history(false),
+            KeyCode::Char('s') => {
+                self.layout.save(&self.path)?;
+                self.message = format!("saved {}", self.path.display());
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    /// How often the key at `index` on the shown layer is typed, shifted or not.
+    fn heat(&self, index: usize) -> u32 {
+        let Some(key) = self.layout.key(self.layer, index) else {
+            return 0;
+        };
+        let freq = |ch: Symbol| self.count.letter.get(&[ch]).copied().unwrap_or(0);
+        freq(key) + self.keyboard.shift_table().shifted(key).map_or(0, freq)
+    }
+
+    fn render_grid(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+        let max_heat = (0..self.layout.layer_size())
+            .map(|i| self.heat(i))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+
+        let mut lines = vec![];
+        for row in 0..self.grid.rows() {
+            let mut keys = vec![];
+            let mut holds = vec![];
+            for column in 0..self.grid.columns() {
+                let Some(index) = self.grid.index(column, row) else {
+                    keys.push(Span::raw("    "));
+                    holds.push(Span::raw("    "));
+                    continue;
+                };
+
+                let heat = self.heat(index) as f32 / max_heat as f32;
+                let mut style = Style::new().bg(Color::Rgb((40.0 + heat * 180.0) as u8, 30, 30));
+                if index == self.cursor {
+                    style = style.reversed();
+                }
+                if self.selected == Some(KeyLoc::new(self.layer, index)) {
+                    style = style.fg(Color::Yellow).bold();
+                }
+                keys.push(Span::styled(
+                    key_label(self.layout.key(self.layer, index)),
+                    style,
+                ));
+                keys.push(Span::raw(" "));
+
+                let hold = (self.layer == 0)
+                    .then(|| self.layout.base_hold()[index])
+                    .flatten();
+                holds.push(Span::styled(hold_label(hold), Style::new().blue()));
+                holds.push(Span::raw(" "));
+            }
+            lines.push(Line::from(keys));
+            lines.push(Line::from(holds));
+        }
+
+        let title = format!(" layer {} / {} ", self.layer, self.layout.layer_count());
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn render_metrics(&self, frame: &mut Frame, area: ratatui::layout::Rect) {
+        let score = (
+            self.weights.score(&self.start),
+            self.weights.score(&self.scaled),
+        );
+        let rows = std::iter::once(("score".to_string(), score.0, score.1, 1.0))
+            .chain(
+                self.start
+                    .fields()
+                    .into_iter()
+                    .zip(self.scaled.fields())
+                    .zip(self.weights.as_evaluation().fields())
+                    .map(|(((name, a), (_, b)), (_, weight))| (name, a, b, weight)),
+            )
+            .filter(|(_, a, b, _)| a.is_finite() || b.is_finite())
+            .map(|(name, a, b, weight)| {
+                let delta = b - a;
+                // a metric with a negative weight is better for rising
+                let better = match weight < 0.0 {
+                    true => -delta,
+                    false => delta,
+                };
+                let color = match better {
+                    d if d < 0.0 => Color::Green,
+                    d if d > 0.0 => Color::Red,
+                    _ => Color::Reset,
+                };
+                Row::new([
+                    Span::raw(name),
+                    Span::raw(format!("{b:>12.2}")),
+                    Span::styled(format!("{delta:>+10.2}"), Style::new().fg(color)),
+                ])
+            });
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(18),
+                Constraint::Length(12),
+                Constraint::Length(10),
+            ],
+        )
+        .header(Row::new(["metric", "       value", "  vs start"]).bold())
+        .block(Block::bordered().title(" evaluation "));
+        frame.render_widget(table, area);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [main, status] =
+            Split::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [grid, metrics] =
+            Split::horizontal([Constraint::Length(42), Constraint::Min(0)]).areas(main);
+
+        self.render_grid(frame, grid);
+        self.render_metrics(frame, metrics);
+
+        let help = "arrows/hjkl move  space select/swap  u/r undo/redo  tab layer  s save  q quit";
+        let status_line = match self.message.is_empty() {
+            true => help.to_string(),
+            false => format!("{}  |  {help}", self.message),
+        };
+        frame.render_widget(Paragraph::new(status_line).dim(), status);
+    }
+
+    pub fn run(mut self, terminal: &mut DefaultTerminal) -> AnyResult<()> {
+        loop {
+            terminal.draw(|frame| self.render(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle(key.code)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Runs the editor on the terminal, restoring it even if the editor fails.
+pub fn run(app: App) -> AnyResult<()> {
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,128 @@
+use rand::{seq::IndexedRandom, Rng};
+
+use crate::{
+    counter::{CountOutcome, NGramTracker, NgramDepth},
+    optimization::seeded_rng,
+    symbol::Symbol,
+};
+
+/// Normal mode commands and how often each is typed, roughly as a
+/// programmer's vim logs them: motions most, then edits, the commands that
+/// enter insert mode and a few ex commands.
+const COMMANDS: &[(&str, u32)] = &[
+    ("j", 120),
+    ("k", 100),
+    ("l", 40),
+    ("h", 35),
+    ("w", 50),
+    ("b", 35),
+    ("e", 15),
+    ("W", 5),
+    ("B", 5),
+    ("}", 12),
+    ("{", 10),
+    ("0", 6),
+    ("^", 6),
+    ("$", 10),
+    ("gg", 8),
+    ("G", 8),
+    ("%", 5),
+    ("f(", 4),
+    ("f,", 3),
+    ("t)", 3),
+    ("t,", 2),
+    (";", 6),
+    ("n", 15),
+    ("N", 5),
+    ("*", 6),
+    ("zz", 6),
+    ("x", 15),
+    ("dd", 25),
+    ("dw", 10),
+    ("diw", 6),
+    ("D", 4),
+    ("yy", 15),
+    ("yiw", 5),
+    ("p", 25),
+    ("P", 6),
+    ("u", 25),
+    (".", 20),
+    ("v", 8),
+    ("V", 8),
+    (">>", 3),
+    ("<<", 2),
+    ("J", 3),
+    ("r", 4),
+    ("i", 30),
+    ("a", 15),
+    ("A", 15),
+    ("I", 6),
+    ("o", 25),
+    ("O", 10),
+    ("ciw", 10),
+    ("cw", 12),
+    ("cc", 4),
+    ("C", 4),
+    ("ci\"", 4),
+    ("ci(", 3),
+    ("s", 3),
+    (":w\n", 20),
+    (":wq\n", 4),
+    (":q\n", 4),
+    (":%s/", 2),
+];
+
+/// Commands after which insert mode types the text of the corpus, ended by
+/// escape, which is off the alphabet and breaks the n-grams.
+const INSERTS: &[&str] = &[
+    "i", "a", "A", "I", "o", "O", "ciw", "cw", "cc", "C", "ci\"", "ci(", "s", ":%s/",
+];
+
+/// Commands that take a count in front of them, such as `3j` or `2dd`.
+const COUNTED: &[&str] = &[
+    "j", "k", "l", "h", "w", "b", "e", "}", "{", "x", "dd", "dw", "yy", "p", ">>", "<<", "J",
+];
+
+/// Share of the counted commands that get a count.
+const COUNT_CHANCE: f64 = 0.15;
+
+/// Keystrokes generated per model, enough for every common trigram.
+const KEYSTROKES: usize = 1_000_000;
+
+/// The n-grams of normal mode alone, from a fixed seed so every run counts
+/// the same.
+pub fn normal_mode(depth: NgramDepth) -> CountOutcome {
+    let mut rng = seeded_rng(0);
+    let mut count = CountOutcome::default();
+    let mut tracker = NGramTracker::new(depth);
+    let mut typed = 0;
+
+    while typed < KEYSTROKES {
+        let &(command, _) = COMMANDS
+            .choose_weighted(&mut rng, |&(_, weight)| weight)
+            .unwrap();
+
+        let prefix = match COUNTED.contains(&command) && rng.random_bool(COUNT_CHANCE) {
+            true => rng.random_range(2..10u8).to_string(),
+            false => String::new(),
+        };
+        for key in prefix.bytes().chain(command.bytes()) {
+            match Symbol::from_ascii(key).filter(|key| key.in_alphabet()) {
+                Some(key) => tracker.apply(&mut count, key),
+                None => tracker.clear(),
+            }
+            typed += 1;
+        }
+        if INSERTS.contains(&command) {
+            tracker.clear();
+        }
+    }
+    count
+}
+
+/// `count` typed as insert mode text among normal mode commands, which take
+/// `share` of the keystrokes.
+pub fn with_normal_mode(count: &CountOutcome, share: f32, depth: NgramDepth) -> CountOutcome {
+    let normal = normal_mode(depth);
+    CountOutcome::mix(&[(count, 1.0 - share), (&normal, share)])
+}